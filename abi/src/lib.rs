@@ -0,0 +1,616 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Interface between the supervisor and user programs
+//!
+//! A system call passes its number and up to six arguments in registers, and
+//! gets back a single value, of which the last 4095 values are errors.
+//!
+//! | | number | arguments | result |
+//! |---|---|---|---|
+//! | x86_64 (`syscall`) | rax | rdi, rsi, rdx, r10, r8, r9 | rax |
+//! | i386 (`int 0x80`) | eax | ebx, ecx, edx, esi, edi, ebp | eax |
+//!
+//! Some system calls return more values in the argument registers.
+//!
+//! # Messages
+//!
+//! Endpoint system calls pass a message in the argument registers:
+//!
+//! | argument | in | out |
+//! |---|---|---|
+//! | 0 | endpoint handle | |
+//! | 1 | buffer address | |
+//! | 2 | see [`message_length`] | length of the received data |
+//! | 3 | handle to transfer, 0 for none | transferred handle, 0 for none |
+//! | 4, 5 | message words | received message words |
+//!
+//! The received data is truncated to the capacity of the buffer, its length is
+//! returned in full.
+
+#![no_std]
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::arch::{asm, global_asm};
+
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Vector of the system call interrupt on i386.
+pub const SYSCALL_VECTOR: u8 = 0x80;
+
+#[allow(non_snake_case)]
+pub mod Syscall {
+    /// Terminates the process with the exit code in the first argument.
+    pub const EXIT: usize = 0;
+    /// Terminates the calling thread, the process exits with the last thread.
+    pub const EXIT_THREAD: usize = 1;
+    /// Gives up the CPU in favor of other ready threads.
+    pub const YIELD: usize = 2;
+    /// Returns the pid of the process.
+    pub const GET_PID: usize = 3;
+    /// Returns the pid of the parent process, 0 if there is none.
+    pub const GET_PARENT_PID: usize = 4;
+    /// Waits for the child with the pid in the first argument to exit, or any
+    /// child if it is 0, stores its exit code at the pointer in the second
+    /// argument unless it is null, and returns its pid.
+    pub const WAIT: usize = 5;
+    /// Removes the handle in the first argument from the process.
+    pub const HANDLE_CLOSE: usize = 6;
+    /// Creates a new handle to the object of the handle in the first argument
+    /// with the rights in the second argument, which must be a subset, and
+    /// returns it. Requires [`Rights::DUPLICATE`].
+    pub const HANDLE_DUPLICATE: usize = 7;
+    /// Reduces the rights of the handle in the first argument to those in the
+    /// second argument, which must be a subset.
+    pub const HANDLE_REDUCE: usize = 8;
+    /// Returns the rights of the handle in the first argument.
+    pub const HANDLE_RIGHTS: usize = 9;
+    /// Creates an endpoint and returns a handle to it with all rights.
+    pub const ENDPOINT_CREATE: usize = 10;
+    /// Sends a message and waits until it is received. Requires
+    /// [`Rights::WRITE`].
+    pub const SEND: usize = 11;
+    /// Waits for a message, and returns the pid of the sender. Requires
+    /// [`Rights::READ`].
+    pub const RECEIVE: usize = 12;
+    /// Sends a message and waits for the reply of the receiver, the endpoint
+    /// handle is ignored for the reply. Requires [`Rights::WRITE`].
+    pub const CALL: usize = 13;
+    /// Replies to the last message received through a call, the endpoint
+    /// handle is ignored.
+    pub const REPLY: usize = 14;
+    /// Replies like [`REPLY`] and waits for the next message like [`RECEIVE`].
+    pub const REPLY_RECEIVE: usize = 15;
+    /// Creates a notification and returns a handle to it with all rights.
+    pub const NOTIFICATION_CREATE: usize = 16;
+    /// Sets the bits in the second argument. Requires [`Rights::WRITE`].
+    pub const NOTIFICATION_SIGNAL: usize = 17;
+    /// Waits until a bit is set, and returns and clears all bits. Requires
+    /// [`Rights::READ`].
+    pub const NOTIFICATION_WAIT: usize = 18;
+    /// Creates a port and returns a handle to it with all rights.
+    pub const PORT_CREATE: usize = 19;
+    /// Delivers events of the notification, endpoint or process in the second
+    /// argument to the port with the key in the third argument. Requires
+    /// [`Rights::WRITE`] for the port and [`Rights::READ`] for the source.
+    pub const PORT_BIND: usize = 20;
+    /// Delivers a timer event with the key in the second argument at the
+    /// deadline in nanoseconds since boot in the third, 0 cancels the timer.
+    /// On i386 the fourth argument holds the upper half of the deadline.
+    /// Requires [`Rights::WRITE`].
+    pub const PORT_SET_TIMER: usize = 21;
+    /// Waits for events, stores up to the number in the third argument in the
+    /// array of [`Event`]s in the second, and returns how many it stored.
+    /// Requires [`Rights::READ`].
+    pub const PORT_WAIT: usize = 22;
+    /// Creates a resource for the range of the length in the third argument
+    /// from the one in the second argument, which must lie within the resource
+    /// in the first argument, and returns a handle to it with the same rights.
+    /// Requires [`Rights::DUPLICATE`].
+    pub const RESOURCE_CREATE: usize = 23;
+    /// Allows the process to access the ports of the range in the second and
+    /// third argument from user mode. Requires [`Rights::WRITE`] for the
+    /// [`ResourceKind::IO_PORTS`] resource in the first argument.
+    pub const IO_PORT_ENABLE: usize = 24;
+    /// Maps the page-aligned physical range in the second and third argument
+    /// uncached, and returns its address. Requires [`Rights::MAP`] for the
+    /// [`ResourceKind::MMIO`] resource in the first argument, and fails with
+    /// [`Error::AccessDenied`] if the range overlaps RAM.
+    pub const MMIO_MAP: usize = 25;
+    /// Sets the bits in the fourth argument of the notification in the third
+    /// argument whenever the interrupt in the second argument fires, which
+    /// stays masked until [`IRQ_ACK`]. Requires [`Rights::READ`] for the
+    /// [`ResourceKind::IRQ`] resource in the first argument and
+    /// [`Rights::WRITE`] for the notification.
+    pub const IRQ_BIND: usize = 26;
+    /// Undoes [`IRQ_BIND`] for the interrupt in the second argument. Requires
+    /// [`Rights::READ`] for the resource in the first argument, and fails with
+    /// [`Error::AccessDenied`] if another process bound the interrupt.
+    pub const IRQ_UNBIND: usize = 27;
+    /// Unmasks the interrupt in the second argument once it was handled.
+    /// Requires [`Rights::READ`] for the resource in the first argument, and
+    /// fails with [`Error::AccessDenied`] if another process bound the
+    /// interrupt.
+    pub const IRQ_ACK: usize = 28;
+    /// Sets the action of the signal in the first argument to the
+    /// [`SignalAction`] at the pointer in the second argument unless it is
+    /// null, and stores the previous one at the pointer in the third unless it
+    /// is null. The actions of [`Signal::KILL`] and [`Signal::STOP`] can't be
+    /// changed.
+    pub const SIGNAL_ACTION: usize = 29;
+    /// Changes the signal mask of the thread with the set in the second
+    /// argument as the first argument, one of [`SignalMaskHow`], says, and
+    /// returns the previous mask.
+    pub const SIGNAL_MASK: usize = 30;
+    /// Sends the signal in the second argument to the process of the handle in
+    /// the first argument, or to the process itself if it is 0. Threads
+    /// blocked in a system call only handle the signal once it returns.
+    /// Requires [`Rights::WRITE`].
+    pub const SIGNAL_SEND: usize = 31;
+    /// Returns from a signal handler to the interrupted code, with the stack
+    /// pointer the handler returned with, see [`signal_restorer`].
+    pub const SIGNAL_RETURN: usize = 32;
+    /// Waits on the 32-bit futex at the address in the first argument if it
+    /// holds the value in the second argument, until it is woken or the
+    /// deadline in nanoseconds since boot in the third argument passed, 0
+    /// waits forever. On i386 the fourth argument holds the upper half of the
+    /// deadline. Futexes are identified by their physical address, so that
+    /// shared memory works across processes.
+    pub const FUTEX_WAIT: usize = 33;
+    /// Wakes up to the number of threads in the second argument waiting on the
+    /// futex at the address in the first argument, and returns how many it
+    /// woke.
+    pub const FUTEX_WAKE: usize = 34;
+    /// Wakes up to the number of threads in the third argument waiting on the
+    /// futex at the address in the first argument, if it holds the value in
+    /// the second argument, and moves up to the number in the fifth argument of
+    /// the remaining ones to the futex at the address in the fourth. Returns
+    /// how many threads it woke and moved.
+    pub const FUTEX_REQUEUE: usize = 35;
+    /// Sets the thread pointer of the calling thread to the user address in
+    /// the first argument, which is the FS base on x86_64 and the base of the
+    /// segment in GS on i386.
+    pub const SET_THREAD_POINTER: usize = 36;
+    /// Changes the scheduling of the calling thread to the policy in the first
+    /// argument, one of [`SchedulingPolicy`]. The second argument is the
+    /// priority from 0 to 31 for [`SchedulingPolicy::FIXED_PRIORITY`], higher
+    /// ones run first. The third argument is the mask of CPUs the thread may
+    /// run on, one bit per CPU, which must contain at least one CPU. The
+    /// fourth argument is the deadline in nanoseconds since boot for
+    /// [`SchedulingPolicy::DEADLINE`], on i386 the fifth argument holds its
+    /// upper half.
+    pub const SET_SCHEDULING: usize = 37;
+    /// Blocks the calling thread for at least the nanoseconds in the first
+    /// argument. On i386 the second argument holds the upper half of the
+    /// duration.
+    pub const SLEEP: usize = 38;
+}
+
+/// Signal numbers, a process killed by a signal exits with its negated number.
+#[allow(non_snake_case)]
+pub mod Signal {
+    pub const HUP: usize = 1;
+    pub const INT: usize = 2;
+    pub const QUIT: usize = 3;
+    /// Raised by an invalid instruction.
+    pub const ILL: usize = 4;
+    pub const TRAP: usize = 5;
+    pub const ABRT: usize = 6;
+    /// Raised by a misaligned or otherwise invalid memory access.
+    pub const BUS: usize = 7;
+    /// Raised by an arithmetic error.
+    pub const FPE: usize = 8;
+    /// Terminates the process, can't be handled, ignored or blocked.
+    pub const KILL: usize = 9;
+    pub const USR1: usize = 10;
+    /// Raised by an access to memory which is not accessible.
+    pub const SEGV: usize = 11;
+    pub const USR2: usize = 12;
+    pub const PIPE: usize = 13;
+    pub const ALRM: usize = 14;
+    pub const TERM: usize = 15;
+    pub const CHLD: usize = 17;
+    pub const CONT: usize = 18;
+    /// Can't be handled, ignored or blocked, and is ignored since processes
+    /// can't be stopped.
+    pub const STOP: usize = 19;
+    pub const TSTP: usize = 20;
+}
+
+/// Special handlers of a [`SignalAction`].
+#[allow(non_snake_case)]
+pub mod SignalHandler {
+    /// Ignores the signal, or terminates the process, depending on the signal.
+    pub const DEFAULT: usize = 0;
+    pub const IGNORE: usize = 1;
+}
+
+#[allow(non_snake_case)]
+pub mod SignalFlags {
+    /// The signal isn't blocked while its handler runs.
+    pub const NODEFER: u32 = 1 << 0;
+    /// The action is reset to the default once the handler is called.
+    pub const RESETHAND: u32 = 1 << 1;
+}
+
+#[allow(non_snake_case)]
+pub mod SignalMaskHow {
+    /// Adds the set to the mask.
+    pub const BLOCK: usize = 0;
+    /// Removes the set from the mask.
+    pub const UNBLOCK: usize = 1;
+    /// Replaces the mask with the set.
+    pub const SET: usize = 2;
+}
+
+/// Cause of a signal.
+#[allow(non_snake_case)]
+pub mod SignalCode {
+    /// Sent by the process in [`SignalInfo::sender`].
+    pub const USER: usize = 0;
+    /// Raised by the instruction which accessed [`SignalInfo::addr`], or
+    /// which is at that address for faults without a memory access.
+    pub const FAULT: usize = 1;
+}
+
+/// Scheduling policies, threads of a policy only run if no thread of a policy
+/// before it is ready.
+#[allow(non_snake_case)]
+pub mod SchedulingPolicy {
+    /// Earliest deadline first.
+    pub const DEADLINE: usize = 0;
+    /// Highest priority first, round-robin within the same priority.
+    pub const FIXED_PRIORITY: usize = 1;
+    /// Round-robin among all threads, for background work.
+    pub const ROUND_ROBIN: usize = 2;
+}
+
+/// Source of a port event.
+#[allow(non_snake_case)]
+pub mod EventKind {
+    /// Bits of a notification were set, the data are the bits.
+    pub const NOTIFICATION: usize = 1;
+    /// A sender waits at an endpoint.
+    pub const ENDPOINT: usize = 2;
+    /// The timer expired, the data is the time.
+    pub const TIMER: usize = 3;
+    /// A process exited, the data is the exit code.
+    pub const PROCESS_EXIT: usize = 4;
+}
+
+/// Hardware a resource grants access to.
+#[allow(non_snake_case)]
+pub mod ResourceKind {
+    /// Range of I/O ports.
+    pub const IO_PORTS: usize = 1;
+    /// Range of physical addresses.
+    pub const MMIO: usize = 2;
+    /// Range of interrupt numbers.
+    pub const IRQ: usize = 3;
+}
+
+/// Handles the init process starts with, to resources covering all of the
+/// hardware.
+#[allow(non_snake_case)]
+pub mod InitHandle {
+    use super::Handle;
+
+    pub const IO_PORTS: Handle = 1;
+    pub const MMIO: Handle = 2;
+    pub const IRQ: Handle = 3;
+}
+
+/// Operations a handle allows on its object.
+#[allow(non_snake_case)]
+pub mod Rights {
+    pub const READ: u32 = 1 << 0;
+    pub const WRITE: u32 = 1 << 1;
+    pub const MAP: u32 = 1 << 2;
+    /// The handle may be duplicated.
+    pub const DUPLICATE: u32 = 1 << 3;
+    /// The handle may be sent to another process.
+    pub const TRANSFER: u32 = 1 << 4;
+    pub const ALL: u32 = READ | WRITE | MAP | DUPLICATE | TRANSFER;
+}
+
+/// Number of signals, including the unused 0.
+pub const SIGNAL_COUNT: usize = 32;
+
+/// Largest amount of data in a message.
+pub const MAX_MESSAGE_SIZE: usize = 512;
+
+/// Largest error code, results from `-MAX_ERROR` on are errors.
+const MAX_ERROR: usize = 4095;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+extern "C" {
+    /// Restorer for a [`SignalAction`], which returns from the handler.
+    #[link_name = "abi_signal_restorer"]
+    pub fn signal_restorer();
+}
+
+#[cfg(target_arch = "x86")]
+global_asm!(
+    ".global abi_signal_restorer",
+    "abi_signal_restorer:",
+    "mov eax, {}",
+    "int 0x80",
+    const Syscall::SIGNAL_RETURN,
+);
+
+#[cfg(target_arch = "x86_64")]
+global_asm!(
+    ".global abi_signal_restorer",
+    "abi_signal_restorer:",
+    "mov eax, {}",
+    "syscall",
+    const Syscall::SIGNAL_RETURN,
+);
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Index into the handle table of a process, 0 is never a valid handle.
+pub type Handle = u32;
+
+/// Set of signals, one bit per signal number.
+pub type SignalSet = u32;
+
+/// What happens when a signal is delivered to a process
+#[derive(Clone, Copy, Default, Debug, FromBytes, IntoBytes, Immutable)]
+#[repr(C)]
+pub struct SignalAction {
+    /// Called as `handler(signal, &SignalInfo, &mut SignalContext)` unless it
+    /// is one of [`SignalHandler`].
+    pub handler: usize,
+    /// Signals blocked while the handler runs, in addition to the signal.
+    pub mask: SignalSet,
+    /// Any of [`SignalFlags`].
+    pub flags: u32,
+    /// Address the handler returns to, which has to perform
+    /// [`Syscall::SIGNAL_RETURN`] without touching the stack.
+    pub restorer: usize,
+}
+
+/// Details of a signal passed to its handler
+#[derive(Clone, Copy, Default, Debug, FromBytes, IntoBytes, Immutable)]
+#[repr(C)]
+pub struct SignalInfo {
+    pub signal: usize,
+    /// One of [`SignalCode`].
+    pub code: usize,
+    /// Pid of the sending process.
+    pub sender: usize,
+    /// Address which caused a fault.
+    pub addr: usize,
+}
+
+/// State of the interrupted code passed to a signal handler, which continues
+/// with any changes to it once the handler returns
+#[cfg(target_arch = "x86")]
+#[derive(Clone, Copy, Default, Debug, FromBytes, IntoBytes, Immutable)]
+#[repr(C)]
+pub struct SignalContext {
+    pub edi: usize,
+    pub esi: usize,
+    pub ebp: usize,
+    pub ebx: usize,
+    pub edx: usize,
+    pub ecx: usize,
+    pub eax: usize,
+    pub eip: usize,
+    pub eflags: usize,
+    pub esp: usize,
+    /// Signal mask of the thread.
+    pub mask: usize,
+}
+
+/// State of the interrupted code passed to a signal handler, which continues
+/// with any changes to it once the handler returns
+#[cfg(target_arch = "x86_64")]
+#[derive(Clone, Copy, Default, Debug, FromBytes, IntoBytes, Immutable)]
+#[repr(C)]
+pub struct SignalContext {
+    pub r15: usize,
+    pub r14: usize,
+    pub r13: usize,
+    pub r12: usize,
+    pub r11: usize,
+    pub r10: usize,
+    pub r9: usize,
+    pub r8: usize,
+    pub rbp: usize,
+    pub rdi: usize,
+    pub rsi: usize,
+    pub rdx: usize,
+    pub rcx: usize,
+    pub rbx: usize,
+    pub rax: usize,
+    pub rip: usize,
+    pub rflags: usize,
+    pub rsp: usize,
+    /// Signal mask of the thread.
+    pub mask: usize,
+}
+
+/// Event delivered through a port
+#[derive(Clone, Copy, Default, Debug)]
+#[repr(C)]
+pub struct Event {
+    /// Key the source was bound with.
+    pub key: usize,
+    /// One of [`EventKind`].
+    pub kind: usize,
+    pub data: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(usize)]
+pub enum Error {
+    /// The system call number is not known.
+    NoSyscall = 1,
+    InvalidArgument = 2,
+    NoMemory = 3,
+    NotFound = 4,
+    /// A pointer argument refers to memory which is not accessible.
+    Fault = 5,
+    /// The handle doesn't exist in the process, or refers to an object of the
+    /// wrong type.
+    InvalidHandle = 6,
+    /// The handle lacks the rights for the operation.
+    AccessDenied = 7,
+    /// The object is in use already.
+    Busy = 8,
+    /// The deadline passed before the wait was over.
+    TimedOut = 9,
+    /// A value didn't match the expected one, so that the operation didn't
+    /// happen.
+    WouldBlock = 10,
+    /// The wait was given up before it was over, as the process is exiting or
+    /// the other side went away.
+    Cancelled = 11,
+    /// An error code this version doesn't know.
+    Unknown = MAX_ERROR,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Error {
+    pub fn from_code(code: usize) -> Self {
+        match code {
+            1 => Self::NoSyscall,
+            2 => Self::InvalidArgument,
+            3 => Self::NoMemory,
+            4 => Self::NotFound,
+            5 => Self::Fault,
+            6 => Self::InvalidHandle,
+            7 => Self::AccessDenied,
+            8 => Self::Busy,
+            9 => Self::TimedOut,
+            10 => Self::WouldBlock,
+            11 => Self::Cancelled,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Encodes the result of a system call into the value returned to the caller.
+pub fn encode_result(result: Result<usize, Error>) -> usize {
+    match result {
+        Ok(value) => value,
+        Err(error) => (error as usize).wrapping_neg(),
+    }
+}
+
+/// Decodes the value returned by a system call.
+pub fn decode_result(value: usize) -> Result<usize, Error> {
+    if value >= MAX_ERROR.wrapping_neg() {
+        Err(Error::from_code(value.wrapping_neg()))
+    } else {
+        Ok(value)
+    }
+}
+
+/// Set containing only `signal`.
+pub const fn signal_set(signal: usize) -> SignalSet {
+    1 << signal
+}
+
+/// Combines the length of the data to send and the capacity of the buffer for
+/// the received data into the length argument of a message.
+pub fn message_length(send: usize, capacity: usize) -> usize {
+    send & 0xFFFF | capacity << 16
+}
+
+/// Performs the system call `number`, unused arguments are ignored, and
+/// updates them with the argument registers afterwards.
+///
+/// # Safety
+///
+/// The arguments have to be valid for the system call.
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn syscall(number: usize, args: &mut [usize; 6]) -> Result<usize, Error> {
+    let result;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        inlateout("rdi") args[0] => args[0],
+        inlateout("rsi") args[1] => args[1],
+        inlateout("rdx") args[2] => args[2],
+        inlateout("r10") args[3] => args[3],
+        inlateout("r8") args[4] => args[4],
+        inlateout("r9") args[5] => args[5],
+        lateout("rcx") _,
+        lateout("r11") _,
+        options(nostack),
+    );
+    decode_result(result)
+}
+
+/// Performs the system call `number`, unused arguments are ignored, and
+/// updates them with the argument registers afterwards.
+///
+/// # Safety
+///
+/// The arguments have to be valid for the system call.
+#[cfg(target_arch = "x86")]
+pub unsafe fn syscall(number: usize, args: &mut [usize; 6]) -> Result<usize, Error> {
+    let result;
+    // esi and ebp can't be asm operands, all arguments are loaded from and
+    // stored to memory, through the pointer kept on the stack
+    asm!(
+        "push ebp",
+        "push esi",
+        "push edi",
+        "mov ebx, [edi]",
+        "mov ecx, [edi + 4]",
+        "mov edx, [edi + 8]",
+        "mov esi, [edi + 12]",
+        "mov ebp, [edi + 20]",
+        "mov edi, [edi + 16]",
+        "int 0x80",
+        "xchg edi, [esp]",
+        "mov [edi], ebx",
+        "mov [edi + 4], ecx",
+        "mov [edi + 8], edx",
+        "mov [edi + 12], esi",
+        "mov [edi + 20], ebp",
+        "pop dword ptr [edi + 16]",
+        "pop esi",
+        "pop ebp",
+        inlateout("eax") number => result,
+        inlateout("edi") args.as_mut_ptr() => _,
+        lateout("ebx") _,
+        lateout("ecx") _,
+        lateout("edx") _,
+    );
+    decode_result(result)
+}
@@ -15,6 +15,11 @@
 use std::env;
 
 fn main() {
+    // host builds of the unit tests use the default linker script
+    if env::var("CARGO_CFG_TARGET_OS").unwrap() != "none" {
+        return;
+    }
+
     let target = env::var("TARGET").unwrap();
     println!("cargo:rerun-if-changed=supervisor/{}.ld", target);
     println!("cargo:rustc-link-arg=-Tsupervisor/{}.ld", target);
@@ -0,0 +1,177 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::mem;
+
+use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout};
+
+use crate::acpi::{GenericAddress, Sdt, SdtHeader};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+#[allow(non_snake_case)]
+pub mod FadtFlags {
+    pub const RESET_REG_SUP: u32 = 1 << 10;
+}
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Fixed ACPI description table
+///
+/// Fields which are not present in older revisions are zeroed.
+#[derive(Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct Fadt {
+    pub header: SdtHeader,
+    pub firmware_ctrl: u32,
+    pub dsdt: u32,
+    pub reserved_0: u8,
+    pub preferred_pm_profile: u8,
+    pub sci_interrupt: u16,
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub s4bios_request: u8,
+    pub pstate_control: u8,
+    pub pm1a_event_block: u32,
+    pub pm1b_event_block: u32,
+    pub pm1a_control_block: u32,
+    pub pm1b_control_block: u32,
+    pub pm2_control_block: u32,
+    pub pm_timer_block: u32,
+    pub gpe0_block: u32,
+    pub gpe1_block: u32,
+    pub pm1_event_length: u8,
+    pub pm1_control_length: u8,
+    pub pm2_control_length: u8,
+    pub pm_timer_length: u8,
+    pub gpe0_length: u8,
+    pub gpe1_length: u8,
+    pub gpe1_base: u8,
+    pub cstate_control: u8,
+    pub worst_c2_latency: u16,
+    pub worst_c3_latency: u16,
+    pub flush_size: u16,
+    pub flush_stride: u16,
+    pub duty_offset: u8,
+    pub duty_width: u8,
+    pub day_alarm: u8,
+    pub month_alarm: u8,
+    pub century: u8,
+    pub boot_architecture_flags: u16,
+    pub reserved_1: u8,
+    pub flags: u32,
+    pub reset_register: GenericAddress,
+    pub reset_value: u8,
+    pub arm_boot_architecture_flags: u16,
+    pub minor_version: u8,
+    pub x_firmware_ctrl: u64,
+    pub x_dsdt: u64,
+    pub x_pm1a_event_block: GenericAddress,
+    pub x_pm1b_event_block: GenericAddress,
+    pub x_pm1a_control_block: GenericAddress,
+    pub x_pm1b_control_block: GenericAddress,
+    pub x_pm2_control_block: GenericAddress,
+    pub x_pm_timer_block: GenericAddress,
+    pub x_gpe0_block: GenericAddress,
+    pub x_gpe1_block: GenericAddress,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Fadt {
+    pub const SIGNATURE: [u8; 4] = *b"FACP";
+
+    pub fn parse(sdt: Sdt<'_>) -> Option<Self> {
+        if sdt.signature() != Self::SIGNATURE {
+            return None;
+        }
+
+        let mut this = Self::new_zeroed();
+        let size = sdt.bytes().len().min(mem::size_of::<Self>());
+        this.as_mut_bytes()[..size].copy_from_slice(&sdt.bytes()[..size]);
+        Some(this)
+    }
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acpi::{sdt::tests::table, AddressSpace};
+
+    /// Builds a FADT of `size` bytes, including the header.
+    fn fadt(size: usize) -> Vec<u8> {
+        let mut data = vec![0; size - mem::size_of::<SdtHeader>()];
+        let mut set = |offset: usize, bytes: &[u8]| {
+            let offset = offset - mem::size_of::<SdtHeader>();
+            if offset < data.len() {
+                data[offset..offset + bytes.len()].copy_from_slice(bytes);
+            }
+        };
+        set(46, &9u16.to_le_bytes());
+        set(112, &FadtFlags::RESET_REG_SUP.to_le_bytes());
+        set(116, &[AddressSpace::SYSTEM_IO, 8, 0, 1]);
+        set(120, &0xCF9u64.to_le_bytes());
+        set(128, &[0x06]);
+        set(140, &0x07FE_3000u64.to_le_bytes());
+        table(&Fadt::SIGNATURE, &data)
+    }
+
+    #[test]
+    fn parse() {
+        let bytes = fadt(mem::size_of::<Fadt>());
+        let fadt = Fadt::parse(Sdt::parse(&bytes).unwrap()).unwrap();
+
+        assert_eq!(mem::size_of::<Fadt>(), 244);
+        assert_eq!({ fadt.sci_interrupt }, 9);
+        assert_eq!({ fadt.flags }, FadtFlags::RESET_REG_SUP);
+        assert_eq!(fadt.reset_register.address_space, AddressSpace::SYSTEM_IO);
+        assert_eq!({ fadt.reset_register.address }, 0xCF9);
+        assert_eq!(fadt.reset_value, 0x06);
+        assert_eq!({ fadt.x_dsdt }, 0x07FE_3000);
+    }
+
+    #[test]
+    fn parse_revision_1() {
+        // ACPI 1.0 ends the table right after the flags
+        let bytes = fadt(116);
+        let fadt = Fadt::parse(Sdt::parse(&bytes).unwrap()).unwrap();
+
+        assert_eq!({ fadt.sci_interrupt }, 9);
+        assert_eq!({ fadt.flags }, FadtFlags::RESET_REG_SUP);
+        assert_eq!({ fadt.reset_register.address }, 0);
+        assert_eq!({ fadt.x_dsdt }, 0);
+    }
+
+    #[test]
+    fn parse_invalid_signature() {
+        let bytes = table(b"HPET", &[0; 80]);
+
+        assert!(Fadt::parse(Sdt::parse(&bytes).unwrap()).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use zerocopy::{FromBytes, Immutable, KnownLayout};
+
+use crate::acpi::{GenericAddress, Sdt, SdtHeader};
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// High precision event timer description table
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct Hpet {
+    pub header: SdtHeader,
+    pub event_timer_block_id: u32,
+    pub base_address: GenericAddress,
+    pub number: u8,
+    pub minimum_tick: u16,
+    pub page_protection: u8,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Hpet {
+    pub const SIGNATURE: [u8; 4] = *b"HPET";
+
+    pub fn parse(sdt: Sdt<'_>) -> Option<&Self> {
+        if sdt.signature() != Self::SIGNATURE {
+            return None;
+        }
+
+        Some(Self::ref_from_prefix(sdt.bytes()).ok()?.0)
+    }
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use core::mem;
+
+    use super::*;
+    use crate::acpi::{sdt::tests::table, AddressSpace};
+
+    #[test]
+    fn parse() {
+        let mut data = vec![0; mem::size_of::<Hpet>() - mem::size_of::<SdtHeader>()];
+        data[0..4].copy_from_slice(&0x8086_A201u32.to_le_bytes());
+        data[8..16].copy_from_slice(&0xFED0_0000u64.to_le_bytes());
+        data[17..19].copy_from_slice(&0x80u16.to_le_bytes());
+        let bytes = table(&Hpet::SIGNATURE, &data);
+        let hpet = Hpet::parse(Sdt::parse(&bytes).unwrap()).unwrap();
+
+        assert_eq!({ hpet.event_timer_block_id }, 0x8086_A201);
+        assert_eq!(hpet.base_address.address_space, AddressSpace::SYSTEM_MEMORY);
+        assert_eq!({ hpet.base_address.address }, 0xFED0_0000);
+        assert_eq!({ hpet.minimum_tick }, 0x80);
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let bytes = table(&Hpet::SIGNATURE, &[0; 16]);
+
+        assert!(Hpet::parse(Sdt::parse(&bytes).unwrap()).is_none());
+    }
+
+    #[test]
+    fn parse_invalid_signature() {
+        let bytes = table(b"APIC", &[0; 20]);
+
+        assert!(Hpet::parse(Sdt::parse(&bytes).unwrap()).is_none());
+    }
+}
@@ -0,0 +1,273 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use zerocopy::{FromBytes, Immutable, KnownLayout};
+
+use crate::acpi::Sdt;
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+#[allow(non_snake_case)]
+pub mod MadtLocalApicFlags {
+    pub const ENABLED: u32 = 1 << 0;
+}
+
+#[allow(non_snake_case)]
+pub mod MadtInterruptFlags {
+    pub const POLARITY_MASK: u16 = 0b11;
+    pub const POLARITY_LOW: u16 = 0b11;
+    pub const TRIGGER_MASK: u16 = 0b11 << 2;
+    pub const TRIGGER_LEVEL: u16 = 0b11 << 2;
+}
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Multiple APIC description table
+#[derive(Clone, Copy)]
+pub struct Madt<'a> {
+    local_apic_address: u32,
+    flags: u32,
+    entries: &'a [u8],
+}
+
+pub struct MadtIter<'a> {
+    entries: &'a [u8],
+}
+
+pub enum MadtEntry<'a> {
+    LocalApic(&'a MadtLocalApic),
+    IoApic(&'a MadtIoApic),
+    InterruptSourceOverride(&'a MadtInterruptSourceOverride),
+    LocalApicNmi(&'a MadtLocalApicNmi),
+    LocalApicAddressOverride(&'a MadtLocalApicAddressOverride),
+    LocalX2Apic(&'a MadtLocalX2Apic),
+    Unknown,
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct MadtLocalApic {
+    pub processor_id: u8,
+    pub apic_id: u8,
+    pub flags: u32,
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct MadtIoApic {
+    pub io_apic_id: u8,
+    pub reserved: u8,
+    pub address: u32,
+    pub gsi_base: u32,
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct MadtInterruptSourceOverride {
+    pub bus: u8,
+    pub source: u8,
+    pub gsi: u32,
+    pub flags: u16,
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct MadtLocalApicNmi {
+    /// 0xFF means all processors.
+    pub processor_id: u8,
+    pub flags: u16,
+    pub lint: u8,
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct MadtLocalApicAddressOverride {
+    pub reserved: u16,
+    pub address: u64,
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct MadtLocalX2Apic {
+    pub reserved: u16,
+    pub x2apic_id: u32,
+    pub flags: u32,
+    pub processor_uid: u32,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl<'a> Madt<'a> {
+    pub const SIGNATURE: [u8; 4] = *b"APIC";
+
+    /// PC-AT compatible 8259 PICs are installed and have to be disabled.
+    pub const PCAT_COMPAT: u32 = 1 << 0;
+
+    pub fn parse(sdt: Sdt<'a>) -> Option<Self> {
+        if sdt.signature() != Self::SIGNATURE {
+            return None;
+        }
+
+        let data = sdt.data();
+        Some(Self {
+            local_apic_address: u32::from_le_bytes(data.get(0..4)?.try_into().unwrap()),
+            flags: u32::from_le_bytes(data.get(4..8)?.try_into().unwrap()),
+            entries: &data[8..],
+        })
+    }
+
+    /// Physical address of the local APICs, taking overrides into account.
+    pub fn local_apic_address(&self) -> u64 {
+        self.entries()
+            .find_map(|entry| match entry {
+                MadtEntry::LocalApicAddressOverride(entry) => Some(entry.address),
+                _ => None,
+            })
+            .unwrap_or(self.local_apic_address as u64)
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    pub fn entries(&self) -> MadtIter<'a> {
+        MadtIter {
+            entries: self.entries,
+        }
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+impl<'a> Iterator for MadtIter<'a> {
+    type Item = MadtEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let [type_, length, ..] = *self.entries else {
+            return None;
+        };
+        let Some(entry) = self.entries.get(2..length as usize) else {
+            // truncated or malformed, stop here
+            self.entries = &[];
+            return None;
+        };
+        self.entries = &self.entries[length as usize..];
+
+        Some(match type_ {
+            0 => MadtEntry::LocalApic(FromBytes::ref_from_prefix(entry).ok()?.0),
+            1 => MadtEntry::IoApic(FromBytes::ref_from_prefix(entry).ok()?.0),
+            2 => MadtEntry::InterruptSourceOverride(FromBytes::ref_from_prefix(entry).ok()?.0),
+            4 => MadtEntry::LocalApicNmi(FromBytes::ref_from_prefix(entry).ok()?.0),
+            5 => MadtEntry::LocalApicAddressOverride(FromBytes::ref_from_prefix(entry).ok()?.0),
+            9 => MadtEntry::LocalX2Apic(FromBytes::ref_from_prefix(entry).ok()?.0),
+            _ => MadtEntry::Unknown,
+        })
+    }
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acpi::sdt::tests::table;
+
+    fn madt(entries: &[&[u8]]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0xFEE0_0000u32.to_le_bytes());
+        data.extend_from_slice(&Madt::PCAT_COMPAT.to_le_bytes());
+        for entry in entries {
+            data.extend_from_slice(entry);
+        }
+        table(&Madt::SIGNATURE, &data)
+    }
+
+    #[test]
+    fn entries() {
+        let bytes = madt(&[
+            &[0, 8, 1, 2, 1, 0, 0, 0],
+            &[1, 12, 3, 0, 0x00, 0x00, 0xC0, 0xFE, 0, 0, 0, 0],
+            &[2, 10, 0, 0, 2, 0, 0, 0, 0x0F, 0x00],
+            &[0x7F, 4, 0, 0],
+            &[9, 16, 0, 0, 0x34, 0x12, 0, 0, 1, 0, 0, 0, 7, 0, 0, 0],
+        ]);
+        let madt = Madt::parse(Sdt::parse(&bytes).unwrap()).unwrap();
+        let mut entries = madt.entries();
+
+        assert_eq!(madt.flags(), Madt::PCAT_COMPAT);
+        assert_eq!(madt.local_apic_address(), 0xFEE0_0000);
+        let Some(MadtEntry::LocalApic(entry)) = entries.next() else {
+            panic!("expected a local APIC");
+        };
+        assert_eq!((entry.processor_id, entry.apic_id), (1, 2));
+        assert_eq!({ entry.flags }, MadtLocalApicFlags::ENABLED);
+        let Some(MadtEntry::IoApic(entry)) = entries.next() else {
+            panic!("expected an I/O APIC");
+        };
+        assert_eq!(({ entry.address }, { entry.gsi_base }), (0xFEC0_0000, 0));
+        let Some(MadtEntry::InterruptSourceOverride(entry)) = entries.next() else {
+            panic!("expected an interrupt source override");
+        };
+        assert_eq!((entry.source, { entry.gsi }), (0, 2));
+        assert_eq!(
+            { entry.flags },
+            MadtInterruptFlags::POLARITY_LOW | MadtInterruptFlags::TRIGGER_LEVEL
+        );
+        assert!(matches!(entries.next(), Some(MadtEntry::Unknown)));
+        let Some(MadtEntry::LocalX2Apic(entry)) = entries.next() else {
+            panic!("expected a local x2APIC");
+        };
+        assert_eq!(({ entry.x2apic_id }, { entry.processor_uid }), (0x1234, 7));
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn local_apic_address_override() {
+        let bytes = madt(&[&[5, 12, 0, 0, 0, 0, 0xE0, 0xFE, 1, 0, 0, 0]]);
+        let madt = Madt::parse(Sdt::parse(&bytes).unwrap()).unwrap();
+
+        assert_eq!(madt.local_apic_address(), 0x1_FEE0_0000);
+    }
+
+    #[test]
+    fn truncated_entry() {
+        let bytes = madt(&[&[0, 8, 1, 2, 1, 0, 0, 0], &[1, 12, 3, 0]]);
+        let madt = Madt::parse(Sdt::parse(&bytes).unwrap()).unwrap();
+        let mut entries = madt.entries();
+
+        assert!(matches!(entries.next(), Some(MadtEntry::LocalApic(_))));
+        assert!(entries.next().is_none());
+    }
+
+    #[test]
+    fn invalid_signature() {
+        let bytes = table(b"FACP", &[0; 8]);
+
+        assert!(Madt::parse(Sdt::parse(&bytes).unwrap()).is_none());
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{mem, slice};
+
+use zerocopy::{FromBytes, FromZeros, Immutable, IntoBytes, KnownLayout};
+
+use crate::{acpi::checksum, memory::KernelMemory};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Size of the RSDP as defined by ACPI 1.0, which lacks the XSDT address.
+const RSDP_V1_SIZE: usize = 20;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Root system description pointer
+#[derive(Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct Rsdp {
+    pub signature: [u8; 8],
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub revision: u8,
+    pub rsdt_address: u32,
+    pub length: u32,
+    pub xsdt_address: u64,
+    pub extended_checksum: u8,
+    pub reserved: [u8; 3],
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Rsdp {
+    pub const SIGNATURE: [u8; 8] = *b"RSD PTR ";
+
+    /// Parses a pointer from the start of `bytes`, the operation returns
+    /// `None` if the signature or one of the checksums doesn't match.
+    ///
+    /// Fields which are not present in an ACPI 1.0 pointer are zeroed.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let v1 = bytes.get(..RSDP_V1_SIZE)?;
+        if v1[..8] != Self::SIGNATURE || !checksum(v1) {
+            return None;
+        }
+
+        let mut this = Self::new_zeroed();
+        let size = if v1[15] >= 2 {
+            let length = u32::from_le_bytes(bytes.get(20..24)?.try_into().unwrap()) as usize;
+            if length < mem::size_of::<Self>() || !checksum(bytes.get(..length)?) {
+                return None;
+            }
+            mem::size_of::<Self>()
+        } else {
+            RSDP_V1_SIZE
+        };
+        this.as_mut_bytes()[..size].copy_from_slice(&bytes[..size]);
+        Some(this)
+    }
+
+    /// Searches the first KiB of the extended BIOS data area and the BIOS
+    /// read-only memory, as multiboot doesn't pass the pointer on.
+    pub fn find() -> Option<(usize, Self)> {
+        // segment of the EBDA is stored in the BIOS data area, without one
+        // only the BIOS area is searched
+        let ebda = scan(0x40E, 2, |bytes| {
+            Some((u16::from_le_bytes([bytes[0], bytes[1]]) as usize) << 4)
+        })
+        .unwrap_or(0);
+        [(ebda, 0x400), (0xE0000, 0x20000)]
+            .into_iter()
+            .filter(|(addr, _)| *addr != 0)
+            .find_map(|(addr, size)| {
+                scan(addr, size, |bytes| {
+                    (0..bytes.len())
+                        .step_by(16)
+                        .find_map(|offset| Some((addr + offset, Self::parse(&bytes[offset..])?)))
+                })
+            })
+    }
+
+    /// Returns the address of the root table and whether it is an XSDT.
+    pub fn sdt_address(&self) -> (usize, bool) {
+        let xsdt_address = self.xsdt_address;
+        if let Some(xsdt_address) = usize::try_from(xsdt_address)
+            .ok()
+            .filter(|addr| self.revision >= 2 && *addr != 0)
+        {
+            (xsdt_address, true)
+        } else {
+            (self.rsdt_address as usize, false)
+        }
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Temporarily maps a physical range and passes it to `f`.
+fn scan<T>(phys_addr: usize, size: usize, f: impl FnOnce(&[u8]) -> Option<T>) -> Option<T> {
+    let addr = KernelMemory::lock().map_physical(phys_addr, size, 0)?;
+    let result = f(unsafe { slice::from_raw_parts(addr as *const u8, size) });
+    KernelMemory::lock().unmap_physical(addr, size);
+    result
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fixes up both checksums of a pointer.
+    fn seal(bytes: &mut [u8]) {
+        let sum = |bytes: &[u8]| bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        bytes[8] = 0;
+        bytes[8] = 0u8.wrapping_sub(sum(&bytes[..RSDP_V1_SIZE]));
+        if bytes.len() > RSDP_V1_SIZE {
+            bytes[32] = 0;
+            let extended_checksum = 0u8.wrapping_sub(sum(bytes));
+            bytes[32] = extended_checksum;
+        }
+    }
+
+    fn rsdp_v1() -> Vec<u8> {
+        let mut bytes = vec![0; RSDP_V1_SIZE];
+        bytes[..8].copy_from_slice(&Rsdp::SIGNATURE);
+        bytes[16..20].copy_from_slice(&0x07FE_1000u32.to_le_bytes());
+        seal(&mut bytes);
+        bytes
+    }
+
+    fn rsdp_v2() -> Vec<u8> {
+        let mut bytes = vec![0; mem::size_of::<Rsdp>()];
+        bytes[..8].copy_from_slice(&Rsdp::SIGNATURE);
+        bytes[15] = 2;
+        bytes[16..20].copy_from_slice(&0x07FE_1000u32.to_le_bytes());
+        bytes[20..24].copy_from_slice(&(mem::size_of::<Rsdp>() as u32).to_le_bytes());
+        bytes[24..32].copy_from_slice(&0x07FE_2000u64.to_le_bytes());
+        seal(&mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn parse_v1() {
+        let rsdp = Rsdp::parse(&rsdp_v1()).unwrap();
+
+        assert_eq!(rsdp.revision, 0);
+        assert_eq!({ rsdp.xsdt_address }, 0);
+        assert_eq!(rsdp.sdt_address(), (0x07FE_1000, false));
+    }
+
+    #[test]
+    fn parse_v2() {
+        let rsdp = Rsdp::parse(&rsdp_v2()).unwrap();
+
+        assert_eq!(rsdp.revision, 2);
+        assert_eq!(rsdp.sdt_address(), (0x07FE_2000, true));
+    }
+
+    #[test]
+    fn parse_v2_without_xsdt() {
+        let mut bytes = rsdp_v2();
+        bytes[24..32].fill(0);
+        seal(&mut bytes);
+        let rsdp = Rsdp::parse(&bytes).unwrap();
+
+        assert_eq!(rsdp.sdt_address(), (0x07FE_1000, false));
+    }
+
+    #[test]
+    fn parse_invalid_signature() {
+        let mut bytes = rsdp_v1();
+        bytes[0] = b'X';
+        seal(&mut bytes);
+
+        assert!(Rsdp::parse(&bytes).is_none());
+    }
+
+    #[test]
+    fn parse_invalid_checksum() {
+        let mut bytes = rsdp_v1();
+        bytes[8] ^= 1;
+
+        assert!(Rsdp::parse(&bytes).is_none());
+    }
+
+    #[test]
+    fn parse_invalid_extended_checksum() {
+        let mut bytes = rsdp_v2();
+        bytes[32] ^= 1;
+
+        assert!(Rsdp::parse(&bytes).is_none());
+    }
+
+    #[test]
+    fn parse_truncated() {
+        assert!(Rsdp::parse(&rsdp_v1()[..RSDP_V1_SIZE - 1]).is_none());
+        assert!(Rsdp::parse(&rsdp_v2()[..RSDP_V1_SIZE + 4]).is_none());
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::mem;
+
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+#[allow(non_snake_case)]
+pub mod AddressSpace {
+    pub const SYSTEM_MEMORY: u8 = 0;
+    pub const SYSTEM_IO: u8 = 1;
+}
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Header common to all system description tables
+#[derive(Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+/// Generic address structure, describing a register in memory or I/O space
+#[derive(Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable)]
+#[repr(C, packed)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+/// Validated system description table
+#[derive(Clone, Copy)]
+pub struct Sdt<'a> {
+    bytes: &'a [u8],
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl<'a> Sdt<'a> {
+    /// Parses a table from the start of `bytes`, the operation returns `None`
+    /// if the table is truncated or its checksum doesn't match.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        let header = SdtHeader::ref_from_prefix(bytes).ok()?.0;
+        let bytes = bytes.get(..header.length as usize)?;
+        if bytes.len() < mem::size_of::<SdtHeader>() || !checksum(bytes) {
+            return None;
+        }
+
+        Some(Self { bytes })
+    }
+
+    pub fn header(&self) -> &'a SdtHeader {
+        SdtHeader::ref_from_prefix(self.bytes).unwrap().0
+    }
+
+    pub fn signature(&self) -> [u8; 4] {
+        self.header().signature
+    }
+
+    /// The whole table including its header.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// The table contents following the header.
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[mem::size_of::<SdtHeader>()..]
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Returns whether all bytes sum up to zero.
+pub fn checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Builds a table with a valid header and checksum around `data`.
+    pub(crate) fn table(signature: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let length = mem::size_of::<SdtHeader>() + data.len();
+        let mut bytes = vec![0; mem::size_of::<SdtHeader>()];
+        bytes[..4].copy_from_slice(signature);
+        bytes[4..8].copy_from_slice(&(length as u32).to_le_bytes());
+        bytes[8] = 1;
+        bytes.extend_from_slice(data);
+        let sum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        bytes[9] = 0u8.wrapping_sub(sum);
+        bytes
+    }
+
+    #[test]
+    fn parse() {
+        let mut bytes = table(b"TEST", &[1, 2, 3]);
+        // bytes beyond the length are not part of the table
+        bytes.push(0xFF);
+        let sdt = Sdt::parse(&bytes).unwrap();
+
+        assert_eq!(sdt.signature(), *b"TEST");
+        assert_eq!(sdt.bytes().len(), mem::size_of::<SdtHeader>() + 3);
+        assert_eq!(sdt.data(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn parse_invalid_checksum() {
+        let mut bytes = table(b"TEST", &[1, 2, 3]);
+        bytes[mem::size_of::<SdtHeader>()] ^= 1;
+
+        assert!(Sdt::parse(&bytes).is_none());
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let bytes = table(b"TEST", &[1, 2, 3]);
+
+        assert!(Sdt::parse(&bytes[..bytes.len() - 1]).is_none());
+        assert!(Sdt::parse(&bytes[..10]).is_none());
+    }
+
+    #[test]
+    fn parse_length_below_header() {
+        let mut bytes = table(b"TEST", &[]);
+        bytes[4] = 8;
+        bytes[9] = bytes[9].wrapping_add(mem::size_of::<SdtHeader>() as u8 - 8);
+
+        assert!(checksum(&bytes));
+        assert!(Sdt::parse(&bytes).is_none());
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{mem, slice};
+
+use crate::{
+    acpi::{Fadt, Hpet, Madt, Rsdp, Sdt, SdtHeader},
+    memory::KernelMemory,
+    process::System,
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+const MAX_TABLES: usize = 32;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// All validated tables referenced by the RSDT or XSDT
+pub struct AcpiTables {
+    tables: [&'static [u8]; MAX_TABLES],
+    table_count: usize,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl AcpiTables {
+    /// Locates the RSDP and maps all tables it references, tables with an
+    /// invalid checksum are skipped.
+    ///
+    /// The operation returns `None` if there is no RSDP or the root table is
+    /// invalid, in which case the system has to get by without ACPI.
+    pub fn new() -> Option<Self> {
+        let (_, rsdp) = Rsdp::find()?;
+        let (sdt_address, xsdt) = rsdp.sdt_address();
+        let sdt = Sdt::parse(map_table(sdt_address)?)?;
+
+        let mut this = Self {
+            tables: [&[]; MAX_TABLES],
+            table_count: 0,
+        };
+        for addr in sdt_entries(sdt, xsdt) {
+            let Some(table) = usize::try_from(addr).ok().and_then(map_table) else {
+                continue;
+            };
+            if Sdt::parse(table).is_none() {
+                continue;
+            }
+            if this.table_count == MAX_TABLES {
+                break;
+            }
+            this.tables[this.table_count] = table;
+            this.table_count += 1;
+        }
+
+        Some(this)
+    }
+
+    /// Returns the tables owned by the [`System`], if any.
+    pub fn get() -> Option<&'static Self> {
+        System::get().acpi()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Sdt<'static>> + '_ {
+        self.tables[..self.table_count]
+            .iter()
+            .filter_map(|table| Sdt::parse(table))
+    }
+
+    /// Returns the first table with the given signature.
+    pub fn find(&self, signature: [u8; 4]) -> Option<Sdt<'static>> {
+        self.iter().find(|sdt| sdt.signature() == signature)
+    }
+
+    pub fn madt(&self) -> Option<Madt<'static>> {
+        Madt::parse(self.find(Madt::SIGNATURE)?)
+    }
+
+    pub fn hpet(&self) -> Option<&'static Hpet> {
+        Hpet::parse(self.find(Hpet::SIGNATURE)?)
+    }
+
+    pub fn fadt(&self) -> Option<Fadt> {
+        Fadt::parse(self.find(Fadt::SIGNATURE)?)
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Returns the table addresses in the RSDT, or the XSDT if `xsdt` is set.
+fn sdt_entries(sdt: Sdt<'_>, xsdt: bool) -> impl Iterator<Item = u64> + '_ {
+    let entry_size = if xsdt { 8 } else { 4 };
+    sdt.data().chunks_exact(entry_size).map(move |entry| {
+        if xsdt {
+            u64::from_le_bytes(entry.try_into().unwrap())
+        } else {
+            u32::from_le_bytes(entry.try_into().unwrap()) as u64
+        }
+    })
+}
+
+/// Maps a whole table, determining its size from the header.
+fn map_table(phys_addr: usize) -> Option<&'static [u8]> {
+    let mut kernel_memory = KernelMemory::lock();
+
+    let header_size = mem::size_of::<SdtHeader>();
+    let addr = kernel_memory.map_physical(phys_addr, header_size, 0)?;
+    let length = unsafe { &*(addr as *const SdtHeader) }.length as usize;
+    kernel_memory.unmap_physical(addr, header_size);
+    if length < header_size {
+        return None;
+    }
+
+    let addr = kernel_memory.map_physical(phys_addr, length, 0)?;
+    Some(unsafe { slice::from_raw_parts(addr as *const u8, length) })
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acpi::sdt::tests::table;
+
+    #[test]
+    fn rsdt_entries() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0x1000u32.to_le_bytes());
+        data.extend_from_slice(&0xFEDC_0000u32.to_le_bytes());
+        let bytes = table(b"RSDT", &data);
+        let sdt = Sdt::parse(&bytes).unwrap();
+
+        assert!(sdt_entries(sdt, false).eq([0x1000, 0xFEDC_0000]));
+    }
+
+    #[test]
+    fn xsdt_entries() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0x1000u64.to_le_bytes());
+        data.extend_from_slice(&0x1_0000_2000u64.to_le_bytes());
+        // trailing bytes which don't form a whole entry are ignored
+        data.extend_from_slice(&[0; 4]);
+        let bytes = table(b"XSDT", &data);
+        let sdt = Sdt::parse(&bytes).unwrap();
+
+        assert!(sdt_entries(sdt, true).eq([0x1000, 0x1_0000_2000]));
+    }
+}
@@ -0,0 +1,363 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use abi::Error;
+use intrusive_collections::{LinkedList, UnsafeRef};
+
+use crate::{
+    ipc::PortBinding,
+    memory::ObjectPool,
+    process::{Scheduler, Thread, ThreadAdapter},
+    sync::SpinLock,
+};
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static ENDPOINT_POOL: ObjectPool<Endpoint> = ObjectPool::new();
+
+/// Protects the links between callers waiting for a reply and the threads
+/// owing it to them.
+static REPLIES: SpinLock<()> = SpinLock::new(());
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Rendezvous point for synchronous message passing
+///
+/// Messages are passed between the IPC buffers of the threads, a sender
+/// blocks until its message is received and a receiver until there is one.
+pub struct Endpoint {
+    /// Number of handles and threads using the endpoint.
+    refs: AtomicUsize,
+    queues: SpinLock<EndpointQueues>,
+}
+
+struct EndpointQueues {
+    senders: LinkedList<ThreadAdapter>,
+    receivers: LinkedList<ThreadAdapter>,
+    /// Port notified whenever a sender has to wait for a receiver.
+    binding: Option<PortBinding>,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Endpoint {
+    /// Creates an endpoint with a single reference, returns `None` if there
+    /// are too many.
+    pub fn create() -> Option<&'static Self> {
+        let endpoint = ENDPOINT_POOL.try_allocate(Self {
+            refs: AtomicUsize::new(1),
+            queues: SpinLock::new(EndpointQueues {
+                senders: LinkedList::new(ThreadAdapter::NEW),
+                receivers: LinkedList::new(ThreadAdapter::NEW),
+                binding: None,
+            }),
+        })?;
+        Some(endpoint)
+    }
+
+    pub fn acquire(&self) {
+        self.refs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Drops a reference, the endpoint is freed with the last one. Threads
+    /// still waiting in it fail with [`Error::Cancelled`].
+    pub fn release(&'static self) {
+        if self.refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            {
+                let mut queues = self.queues.lock();
+                if let Some(binding) = queues.binding.take() {
+                    binding.unbind();
+                }
+                let queues = &mut *queues;
+                for threads in [&mut queues.senders, &mut queues.receivers] {
+                    while let Some(thread) = threads.pop_front() {
+                        let thread = unsafe { &*UnsafeRef::into_raw(thread) };
+                        thread.ipc().interrupt(thread);
+                    }
+                }
+            }
+            ENDPOINT_POOL.deallocate(unsafe { NonNull::from(self).as_mut() });
+        }
+    }
+
+    /// Sends the message in the IPC buffer of the current thread, and returns
+    /// once it is received.
+    ///
+    /// Fails with [`Error::Cancelled`] if the process exits meanwhile, the
+    /// message is not received then.
+    pub fn send(&self) -> Result<(), Error> {
+        self.send_message(false)
+    }
+
+    /// Sends the message in the IPC buffer of the current thread, and returns
+    /// once the reply is in the buffer.
+    ///
+    /// Switches directly to a waiting receiver. Fails with
+    /// [`Error::Cancelled`] if the process exits or the receiver stops
+    /// owing the reply before it arrives.
+    pub fn call(&self) -> Result<(), Error> {
+        self.send_message(true)
+    }
+
+    /// Waits for a message, which is in the IPC buffer of the current thread
+    /// afterwards.
+    ///
+    /// Fails with [`Error::Cancelled`] if the process exits meanwhile.
+    pub fn receive(&self) -> Result<(), Error> {
+        let current = Scheduler::current();
+        let mut queues = self.queues.lock();
+        if let Some(sender) = claim_first(&mut queues.senders) {
+            take_message(current, sender);
+            return Ok(());
+        }
+        current.ipc().begin_wait(current);
+        queues
+            .receivers
+            .push_back(unsafe { UnsafeRef::from_raw(current) });
+        Scheduler::prepare_block();
+        drop(queues);
+        block(current, None);
+        self.leave(current, false);
+        current.ipc().end_wait()
+    }
+
+    /// Replies like [`reply`], and waits for the next message like
+    /// [`Endpoint::receive`].
+    ///
+    /// Switches directly to the caller if there is no message yet.
+    pub fn reply_receive(&self) -> Result<(), Error> {
+        let caller = deliver_reply()?;
+        let current = Scheduler::current();
+        let mut queues = self.queues.lock();
+        if let Some(sender) = claim_first(&mut queues.senders) {
+            take_message(current, sender);
+            drop(queues);
+            if let Some(caller) = caller {
+                Scheduler::wake(caller);
+            }
+            return Ok(());
+        }
+        current.ipc().begin_wait(current);
+        queues
+            .receivers
+            .push_back(unsafe { UnsafeRef::from_raw(current) });
+        Scheduler::prepare_block();
+        drop(queues);
+        block(current, caller);
+        self.leave(current, false);
+        current.ipc().end_wait()
+    }
+
+    /// Notifies a port about waiting senders from now on, replacing the
+    /// previous binding. Senders which already wait are posted right away.
+    pub fn bind(&self, binding: PortBinding) {
+        let mut queues = self.queues.lock();
+        if !queues.senders.is_empty() {
+            binding.post(1);
+        }
+        if let Some(previous) = queues.binding.replace(binding) {
+            previous.unbind();
+        }
+    }
+
+    fn send_message(&self, call: bool) -> Result<(), Error> {
+        let current = Scheduler::current();
+        let mut queues = self.queues.lock();
+        if let Some(receiver) = claim_first(&mut queues.receivers) {
+            move_message(current, receiver);
+            if !call {
+                set_reply_to(receiver, None);
+                Scheduler::wake(receiver);
+                return Ok(());
+            }
+            // the reply wakes the thread again
+            set_reply_to(receiver, Some(current));
+            current.ipc().begin_wait(current);
+            Scheduler::prepare_block();
+            drop(queues);
+            block(current, Some(receiver));
+            return finish_call(current);
+        }
+
+        current.ipc().set_calling(call);
+        current.ipc().begin_wait(current);
+        queues
+            .senders
+            .push_back(unsafe { UnsafeRef::from_raw(current) });
+        if let Some(binding) = &queues.binding {
+            binding.post(1);
+        }
+        Scheduler::prepare_block();
+        drop(queues);
+        block(current, None);
+        self.leave(current, true);
+        match call {
+            true => finish_call(current),
+            false => current.ipc().end_wait(),
+        }
+    }
+
+    /// Removes the current thread from the senders or receivers once it runs
+    /// again, where it is still queued if its wait was cancelled.
+    fn leave(&self, current: &Thread, sender: bool) {
+        let mut queues = self.queues.lock();
+        if current.is_queued() {
+            let threads = match sender {
+                true => &mut queues.senders,
+                false => &mut queues.receivers,
+            };
+            unsafe { threads.cursor_mut_from_ptr(current) }.remove();
+        }
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+unsafe impl Sync for Endpoint {}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Replies to the thread which called the current one with the message in
+/// the IPC buffer of the current thread.
+///
+/// The reply is dropped if the caller gave up waiting for it.
+pub fn reply() -> Result<(), Error> {
+    if let Some(caller) = deliver_reply()? {
+        Scheduler::wake(caller);
+    }
+    Ok(())
+}
+
+/// Fails the call of the thread waiting for a reply from the current one,
+/// which is about to exit.
+pub fn abandon_caller() {
+    set_reply_to(Scheduler::current(), None);
+}
+
+/// Removes the first thread from `threads` whose wait can still be claimed.
+///
+/// Threads whose wait was cancelled are dropped from the queue as well, they
+/// notice once they run again.
+fn claim_first(threads: &mut LinkedList<ThreadAdapter>) -> Option<&'static Thread> {
+    while let Some(thread) = threads.pop_front() {
+        let thread = unsafe { &*UnsafeRef::into_raw(thread) };
+        if thread.ipc().claim() {
+            return Some(thread);
+        }
+    }
+    None
+}
+
+/// Blocks the current thread after it prepared to wait, switching directly
+/// to `next` if there is one, unless the wait was cancelled meanwhile.
+fn block(current: &Thread, next: Option<&'static Thread>) {
+    if !current.ipc().should_block(current) {
+        Scheduler::cancel_block();
+        if let Some(next) = next {
+            Scheduler::wake(next);
+        }
+        return;
+    }
+    match next {
+        Some(next) => Scheduler::handoff(next),
+        None => Scheduler::schedule(),
+    }
+}
+
+/// Moves the message of the claimed `sender` into the IPC buffer of
+/// `receiver`, senders are woken right away and callers once the reply
+/// arrives.
+fn take_message(receiver: &'static Thread, sender: &'static Thread) {
+    move_message(sender, receiver);
+    if sender.ipc().is_calling() {
+        set_reply_to(receiver, Some(sender));
+        sender.ipc().begin_wait(sender);
+    } else {
+        set_reply_to(receiver, None);
+        Scheduler::wake(sender);
+    }
+}
+
+/// Copies the message in the IPC buffer of `from` into the one of `to`, and
+/// moves the capability along.
+fn move_message(from: &Thread, to: &Thread) {
+    let message = unsafe { from.ipc().message() };
+    unsafe { *to.ipc().message() = *message };
+    message.capability = None;
+}
+
+/// Makes `caller` wait for a reply from `receiver`, or no thread with `None`.
+/// A previous caller which still waits fails its call.
+fn set_reply_to(receiver: &'static Thread, caller: Option<&'static Thread>) {
+    let _replies = REPLIES.lock();
+    if let Some(previous) = receiver.ipc().reply_to() {
+        previous.ipc().set_replier(None);
+        previous.ipc().interrupt(previous);
+    }
+    receiver.ipc().set_reply_to(caller);
+    if let Some(caller) = caller {
+        caller.ipc().set_replier(Some(receiver));
+    }
+}
+
+/// Ends a call once the current thread runs again, and fails unless the reply
+/// arrived.
+fn finish_call(current: &Thread) -> Result<(), Error> {
+    let _replies = REPLIES.lock();
+    // still owed if the wait was cancelled
+    if let Some(receiver) = current.ipc().replier() {
+        receiver.ipc().set_reply_to(None);
+        current.ipc().set_replier(None);
+    }
+    current.ipc().end_wait()
+}
+
+/// Copies the reply into the IPC buffer of the caller of the current thread,
+/// and returns the caller, which still has to be woken. Returns `None` if the
+/// caller gave up waiting, the reply is dropped then.
+fn deliver_reply() -> Result<Option<&'static Thread>, Error> {
+    let current = Scheduler::current();
+    let _replies = REPLIES.lock();
+    let caller = current.ipc().reply_to().ok_or(Error::InvalidArgument)?;
+    current.ipc().set_reply_to(None);
+    caller.ipc().set_replier(None);
+    if !caller.ipc().claim() {
+        let message = unsafe { current.ipc().message() };
+        if let Some(capability) = message.capability.take() {
+            capability.object().release();
+        }
+        return Ok(None);
+    }
+    move_message(current, caller);
+    Ok(Some(caller))
+}
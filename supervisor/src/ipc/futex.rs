@@ -0,0 +1,209 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::cell::Cell;
+
+use abi::Error;
+use intrusive_collections::{intrusive_adapter, LinkedList, LinkedListLink, UnsafeRef};
+
+use crate::{
+    process::{Scheduler, Thread},
+    sync::SpinLock,
+    x86,
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Number of wait queues futexes are hashed into.
+const FUTEX_QUEUE_COUNT: usize = 64;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+/// Threads waiting on futexes, each queue is shared by all futexes with the
+/// same hash.
+static FUTEX_QUEUES: [SpinLock<LinkedList<FutexWaiterAdapter>>; FUTEX_QUEUE_COUNT] =
+    [const { SpinLock::new(LinkedList::new(FutexWaiterAdapter::NEW)) }; FUTEX_QUEUE_COUNT];
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Thread waiting on a futex, which lives on the kernel stack of the thread
+struct FutexWaiter {
+    link: LinkedListLink,
+    /// Physical address of the futex, changed by requeueing with both queues
+    /// locked.
+    key: Cell<usize>,
+    thread: &'static Thread,
+    woken: Cell<bool>,
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+// only accessed with its queue locked
+unsafe impl Sync for FutexWaiter {}
+
+intrusive_adapter!(FutexWaiterAdapter = UnsafeRef<FutexWaiter>: FutexWaiter { link: LinkedListLink });
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Blocks the current thread on the futex with the physical address `key`
+/// until it is woken or `deadline` passed, unless `check` returns `false`.
+/// Fails with [`Error::Cancelled`] if the process is exiting.
+///
+/// `check` runs with the queue locked, so that wakeups after the value it
+/// reads changed can't get lost.
+pub fn futex_wait(
+    key: usize,
+    deadline: Option<u64>,
+    check: impl FnOnce() -> Result<bool, Error>,
+) -> Result<(), Error> {
+    let thread = Scheduler::current();
+    let waiter = FutexWaiter {
+        link: LinkedListLink::new(),
+        key: Cell::new(key),
+        thread,
+        woken: Cell::new(false),
+    };
+    x86::without_interrupts(|| {
+        {
+            let mut queue = queue_of(key).lock();
+            if !check()? {
+                return Err(Error::WouldBlock);
+            }
+            queue.push_back(unsafe { UnsafeRef::from_raw(&waiter) });
+            Scheduler::prepare_block();
+        }
+        if let Some(deadline) = deadline {
+            thread.set_timeout(deadline);
+        }
+        Scheduler::block_unless_exiting();
+        Ok(())
+    })?;
+    let timed_out = deadline.is_some() && thread.clear_timeout();
+
+    // still queued unless it was woken through the futex, in the queue of
+    // another futex if it was requeued meanwhile
+    loop {
+        let key = waiter.key.get();
+        let mut queue = queue_of(key).lock();
+        if waiter.key.get() == key {
+            if waiter.link.is_linked() {
+                unsafe { queue.cursor_mut_from_ptr(&waiter) }.remove();
+            }
+            break;
+        }
+    }
+    match waiter.woken.get() {
+        true => Ok(()),
+        false if timed_out => Err(Error::TimedOut),
+        false if thread.is_exiting() => Err(Error::Cancelled),
+        false => Ok(()),
+    }
+}
+
+/// Wakes up to `count` threads waiting on the futex at `key`, and returns how
+/// many it woke.
+pub fn futex_wake(key: usize, count: usize) -> usize {
+    wake_waiters(&mut queue_of(key).lock(), key, count)
+}
+
+/// Wakes up to `wake_count` threads waiting on the futex at `key`, and moves
+/// up to `requeue_count` of the remaining ones to the futex at `target`,
+/// unless `check` returns `false`. Returns how many threads it woke and moved.
+///
+/// `check` runs with both queues locked.
+pub fn futex_requeue(
+    key: usize,
+    wake_count: usize,
+    target: usize,
+    requeue_count: usize,
+    check: impl FnOnce() -> Result<bool, Error>,
+) -> Result<usize, Error> {
+    let (source_index, target_index) = (index_of(key), index_of(target));
+    // locked in the order of their index
+    let mut first = FUTEX_QUEUES[source_index.min(target_index)].lock();
+    let mut second =
+        (source_index != target_index).then(|| FUTEX_QUEUES[source_index.max(target_index)].lock());
+    if !check()? {
+        return Err(Error::WouldBlock);
+    }
+    let (source_queue, mut target_queue) = match &mut second {
+        Some(second) if source_index > target_index => (&mut **second, Some(&mut *first)),
+        Some(second) => (&mut *first, Some(&mut **second)),
+        None => (&mut *first, None),
+    };
+
+    let woken = wake_waiters(source_queue, key, wake_count);
+    let mut moved = 0;
+    let mut cursor = source_queue.front_mut();
+    while moved < requeue_count {
+        let Some(waiter) = cursor.get() else {
+            break;
+        };
+        if waiter.key.get() != key {
+            cursor.move_next();
+            continue;
+        }
+        waiter.key.set(target);
+        moved += 1;
+        match &mut target_queue {
+            Some(target_queue) => target_queue.push_back(cursor.remove().unwrap()),
+            None => cursor.move_next(),
+        }
+    }
+    Ok(woken + moved)
+}
+
+fn wake_waiters(queue: &mut LinkedList<FutexWaiterAdapter>, key: usize, count: usize) -> usize {
+    let mut woken = 0;
+    let mut cursor = queue.front_mut();
+    while woken < count {
+        let Some(waiter) = cursor.get() else {
+            break;
+        };
+        if waiter.key.get() != key {
+            cursor.move_next();
+            continue;
+        }
+        // the waiter can't return before the queue is unlocked
+        waiter.woken.set(true);
+        Scheduler::wake(waiter.thread);
+        cursor.remove();
+        woken += 1;
+    }
+    woken
+}
+
+fn queue_of(key: usize) -> &'static SpinLock<LinkedList<FutexWaiterAdapter>> {
+    &FUTEX_QUEUES[index_of(key)]
+}
+
+/// Hashes a futex to its queue.
+fn index_of(key: usize) -> usize {
+    ((key >> 2) as u32).wrapping_mul(0x9E3779B1) as usize
+        >> (32 - FUTEX_QUEUE_COUNT.trailing_zeros())
+}
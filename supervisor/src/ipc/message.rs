@@ -0,0 +1,202 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    cell::{Cell, UnsafeCell},
+    mem,
+};
+
+use abi::{Error, MAX_MESSAGE_SIZE};
+
+use crate::{
+    process::{Capability, Pid, Scheduler, Thread},
+    sync::SpinLock,
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Number of message words passed in registers.
+pub const MESSAGE_WORDS: usize = 2;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Message passed through an endpoint
+#[derive(Clone, Copy)]
+pub struct Message {
+    /// Pid of the sending process, 0 for the kernel.
+    pub sender: Pid,
+    pub words: [usize; MESSAGE_WORDS],
+    /// Length of the used part of `data`.
+    pub len: usize,
+    pub data: [u8; MAX_MESSAGE_SIZE],
+    /// Capability moved to the receiver, which owns its reference meanwhile.
+    pub capability: Option<Capability>,
+}
+
+/// Progress of a thread blocked in an endpoint or waiting for a reply
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum IpcWait {
+    /// Not waiting.
+    None,
+    /// Waiting, the wait may still be cancelled.
+    Waiting,
+    /// Picked by the thread which completes the wait, which also wakes it.
+    Claimed,
+    /// Given up, the thread fails the wait once it runs again.
+    Cancelled,
+}
+
+/// IPC state of a thread
+pub struct IpcState {
+    /// Message to send while the thread waits for a receiver, or the message
+    /// it received.
+    message: UnsafeCell<Message>,
+    /// Whether the thread waits for a reply once its message is received.
+    calling: Cell<bool>,
+    /// Thread waiting for a reply to the message received last, protected by
+    /// the reply lock of the endpoints.
+    reply_to: Cell<Option<&'static Thread>>,
+    /// Thread owing a reply to this one while it waits for it, protected like
+    /// `reply_to`.
+    replier: Cell<Option<&'static Thread>>,
+    wait: SpinLock<IpcWait>,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Message {
+    pub const fn new() -> Self {
+        Self {
+            sender: 0,
+            words: [0; MESSAGE_WORDS],
+            len: 0,
+            data: [0; MAX_MESSAGE_SIZE],
+            capability: None,
+        }
+    }
+}
+
+impl IpcState {
+    pub const fn new() -> Self {
+        Self {
+            message: UnsafeCell::new(Message::new()),
+            calling: Cell::new(false),
+            reply_to: Cell::new(None),
+            replier: Cell::new(None),
+            wait: SpinLock::new(IpcWait::None),
+        }
+    }
+
+    /// Message buffer of the thread.
+    ///
+    /// # Safety
+    ///
+    /// Only the thread itself may access it, or the endpoint code delivering a
+    /// message while the thread is blocked.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn message(&self) -> &mut Message {
+        &mut *self.message.get()
+    }
+
+    pub fn is_calling(&self) -> bool {
+        self.calling.get()
+    }
+
+    pub fn set_calling(&self, calling: bool) {
+        self.calling.set(calling);
+    }
+
+    pub fn reply_to(&self) -> Option<&'static Thread> {
+        self.reply_to.get()
+    }
+
+    pub fn set_reply_to(&self, thread: Option<&'static Thread>) {
+        self.reply_to.set(thread);
+    }
+
+    pub fn replier(&self) -> Option<&'static Thread> {
+        self.replier.get()
+    }
+
+    pub fn set_replier(&self, thread: Option<&'static Thread>) {
+        self.replier.set(thread);
+    }
+
+    /// Starts a wait of `thread`, which owns this state, that may be cancelled
+    /// until it is claimed. It is cancelled right away if the process is
+    /// exiting.
+    pub fn begin_wait(&self, thread: &'static Thread) {
+        let mut wait = self.wait.lock();
+        if thread.is_exiting() {
+            *wait = IpcWait::Cancelled;
+            Scheduler::wake(thread);
+        } else {
+            *wait = IpcWait::Waiting;
+        }
+    }
+
+    /// Claims the waiting thread, and returns `false` if its wait was
+    /// cancelled. The claiming thread has to complete the wait and wake it.
+    pub fn claim(&self) -> bool {
+        let mut wait = self.wait.lock();
+        let waiting = *wait == IpcWait::Waiting;
+        if waiting {
+            *wait = IpcWait::Claimed;
+        }
+        waiting
+    }
+
+    /// Whether the current thread, which owns this state, should block for its
+    /// wait. It is cancelled instead if the process is exiting meanwhile.
+    pub fn should_block(&self, thread: &Thread) -> bool {
+        let mut wait = self.wait.lock();
+        if *wait == IpcWait::Waiting && thread.is_exiting() {
+            *wait = IpcWait::Cancelled;
+        }
+        *wait != IpcWait::Cancelled
+    }
+
+    /// Ends the wait of the thread owning this state once it runs again, and
+    /// fails unless it was completed.
+    pub fn end_wait(&self) -> Result<(), Error> {
+        match mem::replace(&mut *self.wait.lock(), IpcWait::None) {
+            IpcWait::Claimed => Ok(()),
+            _ => Err(Error::Cancelled),
+        }
+    }
+
+    /// Wakes `thread`, which owns this state, so that its current wait fails,
+    /// unless it was claimed already and is woken once the wait completes.
+    pub fn interrupt(&self, thread: &'static Thread) {
+        // woken with the lock held, the thread can't end the wait and exit in
+        // between
+        let mut wait = self.wait.lock();
+        match *wait {
+            IpcWait::Claimed => return,
+            IpcWait::Waiting => *wait = IpcWait::Cancelled,
+            IpcWait::None | IpcWait::Cancelled => {}
+        }
+        Scheduler::wake(thread);
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use abi::Error;
+
+use crate::{
+    ipc::PortBinding,
+    memory::ObjectPool,
+    sync::{SpinLock, WaitQueue},
+};
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static NOTIFICATION_POOL: ObjectPool<Notification> = ObjectPool::new();
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Word of bits which are set asynchronously, and cleared by waiting for them
+///
+/// A notification bound to a port passes its bits on to the port instead.
+pub struct Notification {
+    /// Number of handles and threads using the notification.
+    refs: AtomicUsize,
+    bits: AtomicUsize,
+    waiters: WaitQueue,
+    binding: SpinLock<Option<PortBinding>>,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Notification {
+    /// Creates a notification with a single reference, returns `None` if there
+    /// are too many.
+    pub fn create() -> Option<&'static Self> {
+        let notification = NOTIFICATION_POOL.try_allocate(Self {
+            refs: AtomicUsize::new(1),
+            bits: AtomicUsize::new(0),
+            waiters: WaitQueue::new(),
+            binding: SpinLock::new(None),
+        })?;
+        Some(notification)
+    }
+
+    pub fn acquire(&self) {
+        self.refs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Drops a reference, the notification is freed with the last one.
+    pub fn release(&'static self) {
+        if self.refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            if let Some(binding) = self.binding.lock().take() {
+                binding.unbind();
+            }
+            NOTIFICATION_POOL.deallocate(unsafe { NonNull::from(self).as_mut() });
+        }
+    }
+
+    /// Sets `bits`, may be called from interrupt handlers.
+    pub fn signal(&self, bits: usize) {
+        let binding = self.binding.lock();
+        match binding.as_ref() {
+            Some(binding) => binding.post(bits),
+            None => {
+                self.bits.fetch_or(bits, Ordering::Release);
+                drop(binding);
+                self.waiters.wake_all();
+            }
+        }
+    }
+
+    /// Waits until at least one bit is set, and returns and clears all bits.
+    ///
+    /// Fails with [`Error::Cancelled`] if the process is exiting.
+    pub fn wait(&self) -> Result<usize, Error> {
+        let mut bits = 0;
+        self.waiters.wait_interruptible(|| {
+            bits = self.bits.swap(0, Ordering::Acquire);
+            bits != 0
+        })?;
+        Ok(bits)
+    }
+
+    /// Passes the bits on to a port from now on, replacing the previous
+    /// binding. Bits which are already set are posted right away.
+    pub fn bind(&self, binding: PortBinding) {
+        let mut current = self.binding.lock();
+        let bits = self.bits.swap(0, Ordering::Acquire);
+        if bits != 0 {
+            binding.post(bits);
+        }
+        if let Some(previous) = current.replace(binding) {
+            previous.unbind();
+        }
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+unsafe impl Sync for Notification {}
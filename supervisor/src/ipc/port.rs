@@ -0,0 +1,248 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use abi::{Error, Event, EventKind};
+
+use crate::{
+    memory::ObjectPool,
+    sync::{SpinLock, WaitQueue},
+    time::{now, Timer},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Number of sources a single port can be bound to.
+pub const MAX_PORT_SOURCES: usize = 32;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static PORT_POOL: ObjectPool<Port> = ObjectPool::new();
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Event queue a thread waits on for multiple sources at once
+///
+/// Every source has a slot in the port, events of the same source are
+/// combined until they are delivered.
+pub struct Port {
+    /// Number of handles, bindings and threads using the port.
+    refs: AtomicUsize,
+    state: SpinLock<PortState>,
+    waiters: WaitQueue,
+    timer: Timer,
+}
+
+struct PortState {
+    sources: [Option<PortSource>; MAX_PORT_SOURCES],
+    /// Slot of the armed timer.
+    timer: Option<usize>,
+}
+
+#[derive(Clone, Copy)]
+struct PortSource {
+    key: usize,
+    kind: usize,
+    /// Data of the pending event, combined by OR.
+    data: usize,
+    pending: bool,
+    /// Cleared once the source is gone, the slot is freed after the pending
+    /// event is delivered.
+    bound: bool,
+}
+
+/// Connection of an event source to a port, which holds a reference to it
+pub struct PortBinding {
+    port: &'static Port,
+    slot: usize,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Port {
+    /// Creates a port with a single reference, returns `None` if there are too
+    /// many.
+    pub fn create() -> Option<&'static Self> {
+        let port = PORT_POOL.try_allocate(Self {
+            refs: AtomicUsize::new(1),
+            state: SpinLock::new(PortState {
+                sources: [None; MAX_PORT_SOURCES],
+                timer: None,
+            }),
+            waiters: WaitQueue::new(),
+            timer: Timer::new(expire, 0),
+        })?;
+        // the timer needs the final address of the port
+        port.timer = Timer::new(expire, port as *const Self as usize);
+        Some(port)
+    }
+
+    pub fn acquire(&self) {
+        self.refs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Drops a reference, the port is freed with the last one.
+    pub fn release(&'static self) {
+        if self.refs.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.timer.cancel();
+            PORT_POOL.deallocate(unsafe { NonNull::from(self).as_mut() });
+        }
+    }
+
+    /// Adds a source whose events are delivered with `key`.
+    pub fn bind(&'static self, key: usize, kind: usize) -> Result<PortBinding, Error> {
+        let slot = self.state.lock().allocate(key, kind)?;
+        self.acquire();
+        Ok(PortBinding { port: self, slot })
+    }
+
+    /// Delivers a timer event with `key` at `deadline` in nanoseconds since
+    /// boot, replacing the previous timer. A deadline of 0 only cancels it.
+    pub fn set_timer(&'static self, key: usize, deadline: u64) -> Result<(), Error> {
+        self.timer.cancel();
+        let mut state = self.state.lock();
+        if let Some(slot) = state.timer.take() {
+            state.unbind(slot);
+        }
+        if deadline != 0 {
+            state.timer = Some(state.allocate(key, EventKind::TIMER)?);
+            drop(state);
+            self.timer.arm(deadline, 0);
+        }
+        Ok(())
+    }
+
+    /// Waits for at least one event, and returns the number of events stored
+    /// in `events`.
+    ///
+    /// Fails with [`Error::Cancelled`] if the process is exiting.
+    pub fn wait(&self, events: &mut [Event]) -> Result<usize, Error> {
+        let mut count = 0;
+        self.waiters.wait_interruptible(|| {
+            count = self.state.lock().take_events(events);
+            count != 0 || events.is_empty()
+        })?;
+        Ok(count)
+    }
+
+    fn post(&self, slot: usize, data: usize) {
+        {
+            let mut state = self.state.lock();
+            let source = state.sources[slot].as_mut().unwrap();
+            source.data |= data;
+            source.pending = true;
+        }
+        self.waiters.wake_all();
+    }
+}
+
+impl PortState {
+    fn allocate(&mut self, key: usize, kind: usize) -> Result<usize, Error> {
+        let slot = self
+            .sources
+            .iter()
+            .position(Option::is_none)
+            .ok_or(Error::NoMemory)?;
+        self.sources[slot] = Some(PortSource {
+            key,
+            kind,
+            data: 0,
+            pending: false,
+            bound: true,
+        });
+        Ok(slot)
+    }
+
+    fn unbind(&mut self, slot: usize) {
+        let entry = &mut self.sources[slot];
+        match entry {
+            Some(source) if source.pending => source.bound = false,
+            _ => *entry = None,
+        }
+    }
+
+    /// Moves pending events into `events`, and returns their number.
+    fn take_events(&mut self, events: &mut [Event]) -> usize {
+        let mut count = 0;
+        for entry in self.sources.iter_mut() {
+            if count == events.len() {
+                break;
+            }
+            let Some(source) = entry.as_mut().filter(|source| source.pending) else {
+                continue;
+            };
+            events[count] = Event {
+                key: source.key,
+                kind: source.kind,
+                data: source.data,
+            };
+            count += 1;
+            source.data = 0;
+            source.pending = false;
+            if !source.bound {
+                *entry = None;
+            }
+        }
+        count
+    }
+}
+
+impl PortBinding {
+    /// Posts an event, `data` is combined with that of a pending one.
+    pub fn post(&self, data: usize) {
+        self.port.post(self.slot, data);
+    }
+
+    /// Removes the source from the port, a pending event is still delivered.
+    pub fn unbind(self) {
+        self.port.state.lock().unbind(self.slot);
+        self.port.release();
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+unsafe impl Sync for Port {}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Posts the timer event of a port, run when its timer expires.
+fn expire(port: usize) {
+    let port = unsafe { &*(port as *const Port) };
+    let slot = port.state.lock().timer.take();
+    if let Some(slot) = slot {
+        port.post(slot, now() as usize);
+        port.state.lock().unbind(slot);
+    }
+}
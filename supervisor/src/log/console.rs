@@ -0,0 +1,80 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::fmt::{self, Write};
+
+use crate::{
+    sync::SpinLock,
+    x86::{Serial, COM1},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+const CONSOLE_BAUD: u32 = 115200;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static CONSOLE: SpinLock<Serial> = SpinLock::new(Serial::new(COM1));
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Programs the serial port of the console, output before goes out with the
+/// settings left by the firmware.
+pub fn init_console() {
+    CONSOLE.lock().init(CONSOLE_BAUD);
+}
+
+/// Releases the console no matter who holds it.
+///
+/// # Safety
+///
+/// Only for reporting a panic, once all other CPUs are stopped.
+pub unsafe fn force_unlock_console() {
+    CONSOLE.force_unlock();
+}
+
+/// Writes to the console, whole calls don't interleave with other CPUs.
+#[doc(hidden)]
+pub fn print(args: fmt::Arguments) {
+    let _ = CONSOLE.lock().write_fmt(args);
+}
+
+/// Prints to the console.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::log::print(format_args!($($arg)*))
+    };
+}
+
+/// Prints to the console, with a newline.
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::log::print(format_args!("\n"))
+    };
+    ($($arg:tt)*) => {
+        $crate::log::print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
@@ -0,0 +1,333 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::fmt;
+
+use crate::{log::print, sync::SpinLock};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Number of per-module filters which can be configured.
+const MAX_LOG_FILTERS: usize = 8;
+
+/// Longest module path a filter can hold.
+const MAX_LOG_MODULE: usize = 32;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static LOG_FILTERS: SpinLock<LogFilters> = SpinLock::new(LogFilters::new());
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Severity of a log message, each level includes the ones before
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[repr(u8)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Level enabled for a module and everything below it
+#[derive(Clone, Copy)]
+struct LogFilter {
+    module: [u8; MAX_LOG_MODULE],
+    len: usize,
+    level: Level,
+}
+
+/// Levels enabled by default and for specific modules
+struct LogFilters {
+    default: Level,
+    filters: [Option<LogFilter>; MAX_LOG_FILTERS],
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Level {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(Self::Error),
+            "warn" => Some(Self::Warn),
+            "info" => Some(Self::Info),
+            "debug" => Some(Self::Debug),
+            "trace" => Some(Self::Trace),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Error => "ERROR",
+            Self::Warn => "WARN",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+            Self::Trace => "TRACE",
+        }
+    }
+}
+
+impl LogFilter {
+    fn module(&self) -> &str {
+        // only ever filled from a string, cut at a character boundary
+        unsafe { core::str::from_utf8_unchecked(&self.module[..self.len]) }
+    }
+
+    /// Whether the filter covers `module` or one of its parents.
+    fn covers(&self, module: &str) -> bool {
+        let prefix = self.module();
+        module
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    }
+}
+
+impl LogFilters {
+    const fn new() -> Self {
+        Self {
+            default: Level::Info,
+            filters: [None; MAX_LOG_FILTERS],
+        }
+    }
+
+    /// Applies a filter specification, see [`configure_log`].
+    fn configure(&mut self, spec: &str) {
+        for entry in spec.split(',') {
+            let Some((module, level)) = entry.split_once('=') else {
+                if let Some(level) = Level::parse(entry) {
+                    self.default = level;
+                }
+                continue;
+            };
+            let Some(level) = Level::parse(level) else {
+                continue;
+            };
+            if module.is_empty() || module.len() > MAX_LOG_MODULE {
+                continue;
+            }
+            let Some(slot) = self.filters.iter_mut().find(|filter| filter.is_none()) else {
+                break;
+            };
+            let mut filter = LogFilter {
+                module: [0; MAX_LOG_MODULE],
+                len: module.len(),
+                level,
+            };
+            filter.module[..module.len()].copy_from_slice(module.as_bytes());
+            *slot = Some(filter);
+        }
+    }
+
+    /// Level of the most specific filter covering `module`.
+    fn level(&self, module: &str) -> Level {
+        self.filters
+            .iter()
+            .flatten()
+            .filter(|filter| filter.covers(module))
+            .max_by_key(|filter| filter.len)
+            .map_or(self.default, |filter| filter.level)
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Sets the enabled levels from a comma-separated list of `level` entries for
+/// the default and `module=level` entries for the modules below a path
+/// relative to the crate, like `debug,memory::kernel=trace`.
+///
+/// Malformed entries and filters beyond [`MAX_LOG_FILTERS`] are ignored.
+pub fn configure_log(spec: &str) {
+    LOG_FILTERS.lock().configure(spec);
+}
+
+/// Whether messages of `level` from the module at `module_path` are printed.
+pub fn log_enabled(level: Level, module_path: &str) -> bool {
+    level <= LOG_FILTERS.lock().level(module(module_path))
+}
+
+/// Prints a message with its level and module unless it is filtered, used by
+/// [`log!`].
+#[doc(hidden)]
+pub fn log(level: Level, module_path: &str, args: fmt::Arguments) {
+    if log_enabled(level, module_path) {
+        print(format_args!(
+            "[{:<5}] {}: {}\n",
+            level,
+            module(module_path),
+            args
+        ));
+    }
+}
+
+/// Module path without the crate name.
+fn module(module_path: &str) -> &str {
+    match module_path.split_once("::") {
+        Some((_, module)) => module,
+        None => module_path,
+    }
+}
+
+/// Logs a message with the given [`Level`].
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        $crate::log::log($level, module_path!(), format_args!($($arg)+))
+    };
+}
+
+/// Logs a message with [`Level::Error`].
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Error, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Warn`].
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Warn, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Info`].
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Info, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Debug`].
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Debug, $($arg)+)
+    };
+}
+
+/// Logs a message with [`Level::Trace`].
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        $crate::log!($crate::log::Level::Trace, $($arg)+)
+    };
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters(spec: &str) -> LogFilters {
+        let mut filters = LogFilters::new();
+        filters.configure(spec);
+        filters
+    }
+
+    #[test]
+    fn default_level() {
+        assert_eq!(LogFilters::new().level("memory"), Level::Info);
+        assert_eq!(filters("debug").level("memory"), Level::Debug);
+        assert_eq!(filters("debug,warn").level("memory"), Level::Warn);
+    }
+
+    #[test]
+    fn most_specific_module_wins() {
+        let filters = filters("memory::kernel=trace,error,memory=warn");
+
+        assert_eq!(filters.level("memory::kernel::pool"), Level::Trace);
+        assert_eq!(filters.level("memory::kernel"), Level::Trace);
+        assert_eq!(filters.level("memory::mapping"), Level::Warn);
+        assert_eq!(filters.level("memory"), Level::Warn);
+        assert_eq!(filters.level("process"), Level::Error);
+    }
+
+    #[test]
+    fn later_filter_for_same_module_wins() {
+        assert_eq!(
+            filters("memory=trace,memory=error").level("memory"),
+            Level::Error
+        );
+    }
+
+    #[test]
+    fn module_boundaries() {
+        let filters = filters("mem=trace");
+
+        assert_eq!(filters.level("mem::kernel"), Level::Trace);
+        assert_eq!(filters.level("memory"), Level::Info);
+    }
+
+    #[test]
+    fn malformed_entries() {
+        let filters = filters("verbose,memory=loud,=debug,process=debug=trace");
+
+        assert_eq!(filters.level("memory"), Level::Info);
+        assert_eq!(filters.level("process"), Level::Info);
+        assert!(filters.filters.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn filter_limits() {
+        let long = "a".repeat(MAX_LOG_MODULE + 1);
+        assert!(filters(&format!("{long}=trace"))
+            .filters
+            .iter()
+            .all(Option::is_none));
+
+        let spec: Vec<_> = (0..=MAX_LOG_FILTERS)
+            .map(|index| format!("m{index}=trace"))
+            .collect();
+        let filters = filters(&spec.join(","));
+        assert_eq!(filters.level("m0"), Level::Trace);
+        assert_eq!(filters.level(&format!("m{MAX_LOG_FILTERS}")), Level::Info);
+    }
+
+    #[test]
+    fn module_without_crate() {
+        assert_eq!(module("supervisor::memory::kernel"), "memory::kernel");
+        assert_eq!(module("supervisor"), "supervisor");
+    }
+}
@@ -0,0 +1,461 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{fmt, mem, ops, slice, str};
+
+use multiboot::multiboot_info;
+use spin::Once;
+use zerocopy::{FromBytes, Immutable, KnownLayout};
+
+use crate::memory::{KernelMemory, PAGE_SIZE};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Section type of the symbol table.
+const SECTION_TYPE_SYMTAB: u32 = 2;
+
+/// Symbol type of functions, in the low nibble of the info field.
+const SYMBOL_TYPE_FUNC: u8 = 2;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static KERNEL_SYMBOLS: Once<KernelSymbols> = Once::new();
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+#[cfg(target_arch = "x86")]
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct SectionHeader {
+    name: u32,
+    type_: u32,
+    flags: u32,
+    addr: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+    addralign: u32,
+    entsize: u32,
+}
+
+#[cfg(target_arch = "x86_64")]
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct SectionHeader {
+    name: u32,
+    type_: u32,
+    flags: u64,
+    addr: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    addralign: u64,
+    entsize: u64,
+}
+
+#[cfg(target_arch = "x86")]
+#[repr(C)]
+struct Symbol {
+    name: u32,
+    value: u32,
+    size: u32,
+    info: u8,
+    other: u8,
+    shndx: u16,
+}
+
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+struct Symbol {
+    name: u32,
+    info: u8,
+    other: u8,
+    shndx: u16,
+    value: u64,
+    size: u64,
+}
+
+/// Physical location of the symbol and string table of the kernel, which have
+/// to be kept from being allocated
+pub struct SymbolTables {
+    symtab: ops::Range<usize>,
+    strtab: ops::Range<usize>,
+}
+
+/// Symbol and string table of the kernel, mapped into kernel memory
+struct KernelSymbols {
+    symbols: &'static [Symbol],
+    strings: &'static [u8],
+}
+
+/// Address printed together with the function it lies in, if it is known
+pub struct SymbolizedAddress {
+    addr: usize,
+    /// Address looked up, which differs for return addresses.
+    lookup: usize,
+}
+
+/// Legacy mangled Rust symbol, printed as its path without the hash
+struct Demangle<'a>(&'a str);
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl SymbolTables {
+    /// Finds the tables through the ELF section headers in the multiboot
+    /// information, which are only passed by boot loaders loading the kernel as
+    /// an ELF image.
+    pub fn locate(multiboot_info: &multiboot_info) -> Option<Self> {
+        if multiboot_info.flags & multiboot::MULTIBOOT_INFO_ELF_SHDR == 0 {
+            return None;
+        }
+        let elf_sec = unsafe { multiboot_info.u.elf_sec };
+        let size = elf_sec.size as usize;
+        if size < mem::size_of::<SectionHeader>() {
+            return None;
+        }
+        let headers = unsafe {
+            slice::from_raw_parts(
+                elf_sec.addr as usize as *const u8,
+                elf_sec.num as usize * size,
+            )
+        };
+        let header = |index: usize| {
+            let data = headers.get(index * size..)?;
+            SectionHeader::read_from_prefix(data)
+                .ok()
+                .map(|(header, _)| header)
+        };
+        let range = |header: &SectionHeader| {
+            let start = usize::try_from(header.addr).ok()?;
+            let end = start.checked_add(usize::try_from(header.size).ok()?)?;
+            (start != 0).then_some(start..end)
+        };
+
+        let symtab = (0..elf_sec.num as usize)
+            .filter_map(header)
+            .find(|header| header.type_ == SECTION_TYPE_SYMTAB)?;
+        if symtab.entsize as usize != mem::size_of::<Symbol>() {
+            return None;
+        }
+        let strtab = header(symtab.link as usize)?;
+        let this = Self {
+            symtab: range(&symtab)?,
+            strtab: range(&strtab)?,
+        };
+        this.symtab
+            .start
+            .is_multiple_of(mem::align_of::<Symbol>())
+            .then_some(this)
+    }
+
+    /// Page-aligned physical range spanning both tables.
+    pub fn range(&self) -> ops::Range<usize> {
+        let start = self.symtab.start.min(self.strtab.start) & !(PAGE_SIZE - 1);
+        let end = self.symtab.end.max(self.strtab.end);
+        start..end.next_multiple_of(PAGE_SIZE)
+    }
+}
+
+impl KernelSymbols {
+    /// Start and name of the function containing `addr`.
+    fn lookup(&self, addr: usize) -> Option<(usize, &'static str)> {
+        let symbol = self.symbols.iter().find(|symbol| {
+            let start = symbol.value as usize;
+            symbol.info & 0xF == SYMBOL_TYPE_FUNC
+                && (start..start.saturating_add(symbol.size as usize)).contains(&addr)
+        })?;
+        let name = self.strings.get(symbol.name as usize..)?;
+        let len = name.iter().position(|&byte| byte == 0)?;
+        Some((symbol.value as usize, str::from_utf8(&name[..len]).ok()?))
+    }
+}
+
+impl SymbolizedAddress {
+    pub fn new(addr: usize) -> Self {
+        Self { addr, lookup: addr }
+    }
+
+    /// Symbolizes a return address, which may point right past a call at the
+    /// end of a function.
+    pub fn return_address(addr: usize) -> Self {
+        Self {
+            addr,
+            lookup: addr.wrapping_sub(1),
+        }
+    }
+}
+
+impl Demangle<'_> {
+    /// Removes the next length-prefixed segment from `rest`.
+    fn segment<'a>(rest: &mut &'a str) -> Option<&'a str> {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let len = rest[..digits].parse::<usize>().ok()?;
+        let segment = rest.get(digits..digits + len)?;
+        *rest = &rest[digits + len..];
+        Some(segment)
+    }
+
+    /// Whether `segment` is the hash at the end of a path.
+    fn is_hash(segment: &str) -> bool {
+        segment.len() == 17
+            && segment.starts_with('h')
+            && segment[1..].bytes().all(|byte| byte.is_ascii_hexdigit())
+    }
+
+    /// Writes a segment with its escapes replaced.
+    fn write_segment(f: &mut fmt::Formatter<'_>, mut segment: &str) -> fmt::Result {
+        // a segment can't start with a dollar sign, which gets an underscore
+        if segment.starts_with("_$") {
+            segment = &segment[1..];
+        }
+        while !segment.is_empty() {
+            if let Some(rest) = segment.strip_prefix("..") {
+                f.write_str("::")?;
+                segment = rest;
+                continue;
+            }
+            let escape = segment
+                .strip_prefix('$')
+                .and_then(|rest| rest.split_once('$'))
+                .and_then(|(escape, rest)| {
+                    let replacement = match escape {
+                        "SP" => "@",
+                        "BP" => "*",
+                        "RF" => "&",
+                        "LT" => "<",
+                        "GT" => ">",
+                        "LP" => "(",
+                        "RP" => ")",
+                        "C" => ",",
+                        "u20" => " ",
+                        "u27" => "'",
+                        "u5b" => "[",
+                        "u5d" => "]",
+                        "u7b" => "{",
+                        "u7d" => "}",
+                        "u7e" => "~",
+                        _ => return None,
+                    };
+                    Some((replacement, rest))
+                });
+            let (output, rest) = match escape {
+                Some(escape) => escape,
+                None => segment.split_at(segment.chars().next().unwrap().len_utf8()),
+            };
+            f.write_str(output)?;
+            segment = rest;
+        }
+        Ok(())
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+impl fmt::Display for SymbolizedAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.addr)?;
+        if let Some((start, name)) = KERNEL_SYMBOLS
+            .get()
+            .and_then(|symbols| symbols.lookup(self.lookup))
+        {
+            write!(f, " {}+{:#x}", Demangle(name), self.addr - start)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Demangle<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(path) = self
+            .0
+            .strip_prefix("_ZN")
+            .and_then(|path| path.strip_suffix('E'))
+        else {
+            return f.write_str(self.0);
+        };
+        // checked as a whole first, anything unexpected is printed as is
+        let mut rest = path;
+        while !rest.is_empty() {
+            if Self::segment(&mut rest).is_none() {
+                return f.write_str(self.0);
+            }
+        }
+
+        let mut rest = path;
+        let mut first = true;
+        while let Some(segment) = Self::segment(&mut rest) {
+            if rest.is_empty() && Self::is_hash(segment) {
+                break;
+            }
+            if !first {
+                f.write_str("::")?;
+            }
+            Self::write_segment(f, segment)?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Maps the symbol and string table of the kernel for [`SymbolizedAddress`],
+/// once kernel memory is available and their memory is reserved.
+pub fn init_symbols(tables: SymbolTables) {
+    let range = tables.range();
+    let Some(addr) = KernelMemory::lock().map_physical(range.start, range.len(), 0) else {
+        return;
+    };
+    let table = |physical: &ops::Range<usize>| addr + physical.start - range.start;
+    KERNEL_SYMBOLS.call_once(|| unsafe {
+        KernelSymbols {
+            symbols: slice::from_raw_parts(
+                table(&tables.symtab) as *const Symbol,
+                tables.symtab.len() / mem::size_of::<Symbol>(),
+            ),
+            strings: slice::from_raw_parts(table(&tables.strtab) as *const u8, tables.strtab.len()),
+        }
+    });
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Symbol of the given type, whose name is at `name` in the strings.
+    fn symbol(name: u32, value: usize, size: usize, type_: u8) -> Symbol {
+        Symbol {
+            name,
+            value: value as _,
+            size: size as _,
+            info: type_,
+            other: 0,
+            shndx: 1,
+        }
+    }
+
+    fn symbols(symbols: Vec<Symbol>) -> KernelSymbols {
+        KernelSymbols {
+            symbols: Vec::leak(symbols),
+            strings: b"\0main\0DATA\0panic\0",
+        }
+    }
+
+    fn demangle(name: &str) -> String {
+        Demangle(name).to_string()
+    }
+
+    #[test]
+    fn lookup() {
+        let symbols = symbols(vec![
+            symbol(6, 0x1000, 0x100, 1),
+            symbol(1, 0x1000, 0x80, SYMBOL_TYPE_FUNC),
+            symbol(11, 0x1080, 0x20, SYMBOL_TYPE_FUNC),
+        ]);
+
+        assert_eq!(symbols.lookup(0x1000), Some((0x1000, "main")));
+        assert_eq!(symbols.lookup(0x107F), Some((0x1000, "main")));
+        assert_eq!(symbols.lookup(0x1080), Some((0x1080, "panic")));
+        assert_eq!(symbols.lookup(0x10A0), None);
+        assert_eq!(symbols.lookup(0xFFF), None);
+    }
+
+    #[test]
+    fn lookup_invalid_name() {
+        let symbols = symbols(vec![
+            symbol(100, 0x1000, 0x80, SYMBOL_TYPE_FUNC),
+            symbol(14, 0x2000, 0x80, SYMBOL_TYPE_FUNC),
+        ]);
+
+        assert_eq!(symbols.lookup(0x1000), None);
+        assert_eq!(symbols.lookup(0x2000), Some((0x2000, "ic")));
+    }
+
+    #[test]
+    fn lookup_at_end_of_memory() {
+        let symbols = symbols(vec![symbol(1, usize::MAX - 0xF, 0x100, SYMBOL_TYPE_FUNC)]);
+
+        assert_eq!(
+            symbols.lookup(usize::MAX - 1),
+            Some((usize::MAX - 0xF, "main"))
+        );
+    }
+
+    #[test]
+    fn return_address() {
+        let address = SymbolizedAddress::return_address(0x1080);
+
+        assert_eq!((address.addr, address.lookup), (0x1080, 0x107F));
+        assert_eq!(SymbolizedAddress::return_address(0).lookup, usize::MAX);
+    }
+
+    #[test]
+    fn demangle_path() {
+        assert_eq!(
+            demangle("_ZN10supervisor3x864apic9LocalApic3eoi17h0123456789abcdefE"),
+            "supervisor::x86::apic::LocalApic::eoi"
+        );
+        assert_eq!(
+            demangle("_ZN4core9panicking5panicE"),
+            "core::panicking::panic"
+        );
+    }
+
+    #[test]
+    fn demangle_escapes() {
+        assert_eq!(
+            demangle("_ZN61_$LT$supervisor..log..Level$u20$as$u20$core..fmt..Display$GT$3fmt17h0123456789abcdefE"),
+            "<supervisor::log::Level as core::fmt::Display>::fmt"
+        );
+    }
+
+    #[test]
+    fn demangle_unmangled() {
+        assert_eq!(demangle("interrupt_handler"), "interrupt_handler");
+        assert_eq!(demangle("_ZN4core6panicE"), "_ZN4core6panicE");
+        assert_eq!(demangle("_ZN99coreE"), "_ZN99coreE");
+    }
+
+    #[test]
+    fn range() {
+        let tables = SymbolTables {
+            symtab: 0x10_2010..0x10_3000,
+            strtab: 0x10_3000..0x10_3801,
+        };
+
+        assert_eq!(tables.range(), 0x10_2000..0x10_4000);
+    }
+}
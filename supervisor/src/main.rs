@@ -12,71 +12,81 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 
 //==================================================================================================
 // Imports
 //==================================================================================================
 
-use core::{hint, panic, slice};
+use core::panic;
 
-use memory::SystemMemory;
-use multiboot::{multiboot_mmap_entry, MULTIBOOT_MEMORY_AVAILABLE};
-use zerocopy::FromBytes;
+use process::System;
 
+mod acpi;
+mod ipc;
+mod log;
 mod memory;
 mod process;
+mod sync;
+mod syscall;
+mod time;
 mod x86;
 
 //==================================================================================================
-// Functions
+// Constants
 //==================================================================================================
 
-#[no_mangle]
-extern "C" fn main(multiboot_magic: u32, multiboot_info: u32) -> ! {
-    let mut system_memory = SystemMemory::new();
-
-    assert!(multiboot_magic == multiboot::MULTIBOOT_BOOTLOADER_MAGIC);
-    let multiboot_info = unsafe { &*(multiboot_info as usize as *const multiboot::multiboot_info) };
-
-    assert!(multiboot_info.flags & multiboot::MULTIBOOT_INFO_MEM_MAP != 0);
-    let mut multiboot_mmap = unsafe {
-        slice::from_raw_parts(
-            multiboot_info.mmap_addr as usize as *const u8,
-            multiboot_info.mmap_length as usize,
-        )
-    };
-    while !multiboot_mmap.is_empty() {
-        let multiboot_mmap_entry = multiboot_mmap_entry::ref_from_prefix(multiboot_mmap)
-            .unwrap()
-            .0;
-        multiboot_mmap = &multiboot_mmap[multiboot_mmap_entry.size as usize + 4..];
-        if multiboot_mmap_entry.type_ != MULTIBOOT_MEMORY_AVAILABLE {
-            continue;
-        }
+// the unit tests never enter the kernel, but refer to its entry points so that
+// they see the same dead code as the kernel itself
+#[cfg(test)]
+const _: () = {
+    let _ = main;
+    let _ = main_other;
+    let _ = panic;
+};
 
-        system_memory.deallocate(
-            multiboot_mmap_entry.addr as usize,
-            multiboot_mmap_entry.size as usize,
-        );
-    }
+//==================================================================================================
+// Functions
+//==================================================================================================
 
-    loop {
-        hint::spin_loop();
-    }
+// the test harness brings its own `main`
+#[cfg_attr(not(test), no_mangle)]
+extern "C" fn main(multiboot_magic: u32, multiboot_info: u32) -> ! {
+    System::get().boot(multiboot_magic, multiboot_info)
 }
 
 #[no_mangle]
 extern "C" fn main_other() -> ! {
-    loop {
-        hint::spin_loop();
-    }
+    System::get().boot_other()
 }
 
-#[panic_handler]
+/// Reports the panic with a backtrace on the console, and then halts or
+/// reboots as the `panic` boot option says.
+#[cfg_attr(not(test), panic_handler)]
 fn panic(info: &panic::PanicInfo) -> ! {
-    loop {
-        hint::spin_loop();
+    x86::disable_interrupts();
+    // a nested panic, or one on another CPU at the same time
+    if !x86::stop_other_cpus() {
+        x86::halt_forever();
+    }
+    unsafe { log::force_unlock_console() };
+
+    println!("Kernel panic: {}", info.message());
+    if let Some(location) = info.location() {
+        println!("  at {}", location);
+    }
+    println!("Backtrace:");
+    for (index, addr) in x86::Backtrace::current().enumerate() {
+        println!(
+            "  {:2}: {}",
+            index,
+            log::SymbolizedAddress::return_address(addr)
+        );
+    }
+
+    match System::get().reboot_on_panic() {
+        true => x86::reboot(),
+        false => x86::halt_forever(),
     }
 }
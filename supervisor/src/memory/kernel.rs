@@ -0,0 +1,273 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    ops, slice,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    memory::{
+        Mapping, PageTableEntryFlags, ProcessMemory, SystemMemory, MAX_KERNEL_REGIONS, PAGE_SIZE,
+    },
+    process::System,
+    sync::SpinLockGuard,
+    x86,
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Virtual address the kernel image is linked at.
+#[cfg(target_arch = "x86")]
+pub const KERNEL_VMA: usize = 0xC0000000;
+#[cfg(target_arch = "x86_64")]
+pub const KERNEL_VMA: usize = 0xFFFFFFFF80000000;
+
+/// Part of the kernel address space which is handed out dynamically.
+#[cfg(target_arch = "x86")]
+const KERNEL_MEMORY_RANGE: ops::Range<usize> = 0xC0400000..0xFFC00000;
+#[cfg(target_arch = "x86_64")]
+const KERNEL_MEMORY_RANGE: ops::Range<usize> = 0xFFFFFF8000000000..KERNEL_VMA;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+/// Physical address of the top-level table set up by the boot code.
+static KERNEL_ROOT: AtomicUsize = AtomicUsize::new(0);
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Physical memory together with the kernel part of the address space, which
+/// is shared by all processes.
+pub struct KernelMemory {
+    system: SystemMemory,
+    process: ProcessMemory,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl KernelMemory {
+    /// Takes over the system memory, which has to contain all available
+    /// physical memory at this point.
+    pub fn new(system: SystemMemory) -> Self {
+        KERNEL_ROOT.store(Mapping::current().root(), Ordering::Relaxed);
+        #[allow(unused_mut)]
+        let mut this = Self {
+            system,
+            process: ProcessMemory::new(KERNEL_MEMORY_RANGE, MAX_KERNEL_REGIONS, Mapping::kernel()),
+        };
+        // the kernel part spans many top-level entries on i386, which are
+        // copied into every new mapping and therefore may never change
+        #[cfg(target_arch = "x86")]
+        this.process
+            .mapping_mut()
+            .populate(
+                KERNEL_MEMORY_RANGE.start,
+                KERNEL_MEMORY_RANGE.len(),
+                &mut this.system,
+            )
+            .expect("Not enough memory for the kernel page tables");
+        this
+    }
+
+    /// Locks the kernel memory owned by the [`System`].
+    pub fn lock() -> SpinLockGuard<'static, Self> {
+        System::get().memory().lock()
+    }
+
+    /// Physical address of the top-level table of the boot mapping, which is
+    /// loaded while no process is running so that process mappings can be
+    /// destroyed.
+    pub fn root() -> usize {
+        KERNEL_ROOT.load(Ordering::Relaxed)
+    }
+
+    pub fn system(&mut self) -> &mut SystemMemory {
+        &mut self.system
+    }
+
+    /// Maps a physical range into the kernel address space and returns the
+    /// address corresponding to `phys_addr`.
+    pub fn map_physical(&mut self, phys_addr: usize, size: usize, flags: usize) -> Option<usize> {
+        let offset = phys_addr % PAGE_SIZE;
+        let size = (offset + size).next_multiple_of(PAGE_SIZE);
+        let addr = self.process.allocate(None, size)?;
+        let mapped = self.process.mapping_mut().map(
+            addr,
+            phys_addr - offset,
+            size,
+            flags | PageTableEntryFlags::G,
+            &mut self.system,
+        );
+        if mapped.is_none() {
+            self.unmap_physical(addr, size);
+            return None;
+        }
+        Some(addr + offset)
+    }
+
+    /// Unmaps a range previously returned by `map_physical`, the physical
+    /// memory itself is left untouched.
+    pub fn unmap_physical(&mut self, addr: usize, size: usize) {
+        let offset = addr % PAGE_SIZE;
+        let size = (offset + size).next_multiple_of(PAGE_SIZE);
+        self.process
+            .mapping_mut()
+            .unmap(addr - offset, size, |_| {});
+        self.process.deallocate(addr - offset, size);
+    }
+
+    /// Creates the mapping of a new process, which shares the kernel part with
+    /// all other mappings.
+    pub fn create_mapping(&mut self) -> Option<Mapping> {
+        let root = self.system.allocate(None, PAGE_SIZE)?;
+        let Some(table) = self.map_physical(root, PAGE_SIZE, PageTableEntryFlags::RW) else {
+            self.system.deallocate(root, PAGE_SIZE);
+            return None;
+        };
+        let mapping = unsafe { Mapping::new(root, table) };
+        self.unmap_physical(table, PAGE_SIZE);
+        Some(mapping)
+    }
+
+    /// Frees all user memory and the page tables of a process mapping created
+    /// by `create_mapping`, which must not be active on any CPU.
+    pub fn destroy_mapping(&mut self, mapping: &mut Mapping) {
+        // the tables are only reachable while the mapping is current
+        let previous = Mapping::current();
+        unsafe { x86::write_cr3(mapping.root()) };
+        mapping.clear_user(&mut self.system);
+        unsafe { x86::write_cr3(previous.root()) };
+        self.system.deallocate(mapping.root(), PAGE_SIZE);
+    }
+
+    /// Maps `size` bytes of new memory at the page-aligned user address `addr`
+    /// of `mapping`, which has to be current.
+    ///
+    /// Every page is zeroed and passed to `f` together with its user address
+    /// before it is mapped.
+    pub fn map_user(
+        &mut self,
+        mapping: &mut Mapping,
+        addr: usize,
+        size: usize,
+        flags: usize,
+        mut f: impl FnMut(usize, &mut [u8]),
+    ) -> Option<()> {
+        for page in (addr..addr + size).step_by(PAGE_SIZE) {
+            let phys_addr = self.system.allocate(None, PAGE_SIZE)?;
+            let Some(content) = self.map_physical(phys_addr, PAGE_SIZE, PageTableEntryFlags::RW)
+            else {
+                self.system.deallocate(phys_addr, PAGE_SIZE);
+                return None;
+            };
+            let content = unsafe { slice::from_raw_parts_mut(content as *mut u8, PAGE_SIZE) };
+            content.fill(0);
+            f(page, content);
+            self.unmap_physical(content.as_ptr() as usize, PAGE_SIZE);
+
+            let mapped = mapping.map(
+                page,
+                phys_addr,
+                PAGE_SIZE,
+                flags | PageTableEntryFlags::US,
+                &mut self.system,
+            );
+            if mapped.is_none() {
+                self.system.deallocate(phys_addr, PAGE_SIZE);
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    /// Maps the physical device memory at `phys_addr` uncached at the user
+    /// address `addr` of `mapping`, which has to be current. Both addresses
+    /// have to be page-aligned.
+    ///
+    /// The physical memory is not owned by the mapping, it has to be unmapped
+    /// with `unmap_device`.
+    pub fn map_device(
+        &mut self,
+        mapping: &mut Mapping,
+        addr: usize,
+        phys_addr: usize,
+        size: usize,
+    ) -> Option<()> {
+        #[allow(unused_mut)]
+        let mut flags = PageTableEntryFlags::RW
+            | PageTableEntryFlags::US
+            | PageTableEntryFlags::PCD
+            | PageTableEntryFlags::PWT
+            | PageTableEntryFlags::DEVICE;
+        #[cfg(target_arch = "x86_64")]
+        {
+            flags |= PageTableEntryFlags::NX;
+        }
+        mapping.map(addr, phys_addr, size, flags, &mut self.system)
+    }
+
+    /// Unmaps device memory mapped by `map_device`.
+    pub fn unmap_device(&mut self, mapping: &mut Mapping, addr: usize, size: usize) {
+        mapping.unmap(addr, size, |_| {});
+    }
+
+    /// Allocates and maps memory in the kernel address space, which must be
+    /// deallocated to be available again.
+    pub fn allocate(&mut self, size: usize, flags: usize) -> Option<usize> {
+        let size = size.next_multiple_of(PAGE_SIZE);
+        let addr = self.process.allocate(None, size)?;
+        for offset in (0..size).step_by(PAGE_SIZE) {
+            let Some(phys_addr) = self.system.allocate(None, PAGE_SIZE) else {
+                self.deallocate(addr, size);
+                return None;
+            };
+            let mapped = self.process.mapping_mut().map(
+                addr + offset,
+                phys_addr,
+                PAGE_SIZE,
+                flags | PageTableEntryFlags::G,
+                &mut self.system,
+            );
+            if mapped.is_none() {
+                self.system.deallocate(phys_addr, PAGE_SIZE);
+                self.deallocate(addr, size);
+                return None;
+            }
+        }
+        Some(addr)
+    }
+
+    /// Deallocates memory previously returned by `allocate` and makes it
+    /// available to subsequent `allocate` operations.
+    pub fn deallocate(&mut self, addr: usize, size: usize) {
+        let size = size.next_multiple_of(PAGE_SIZE);
+        let system = &mut self.system;
+        self.process.mapping_mut().unmap(addr, size, |phys_addr| {
+            system.deallocate(phys_addr, PAGE_SIZE)
+        });
+        self.process.deallocate(addr, size);
+    }
+}
@@ -16,22 +16,93 @@
 // Imports
 //==================================================================================================
 
-use core::{marker, ops};
+use core::{marker, mem, ops, ptr, slice};
+
+use crate::{memory::SystemMemory, x86};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+pub const PAGE_SIZE: usize = 4096;
+
+#[cfg(target_arch = "x86")]
+const LEVELS: u32 = 2;
+#[cfg(target_arch = "x86_64")]
+const LEVELS: u32 = 4;
+
+#[cfg(target_arch = "x86")]
+const LEVEL_BITS: u32 = 10;
+#[cfg(target_arch = "x86_64")]
+const LEVEL_BITS: u32 = 9;
+
+/// Index of the top-level entry referencing the top-level table itself, as set
+/// up by the boot code.
+#[cfg(target_arch = "x86")]
+const RECURSIVE_INDEX: usize = 0x3FF;
+#[cfg(target_arch = "x86_64")]
+const RECURSIVE_INDEX: usize = 0o776;
+
+/// Number of entries in a table.
+const ENTRIES: usize = 1 << LEVEL_BITS;
+
+/// First top-level entry of the kernel part, which is shared by all mappings.
+#[cfg(target_arch = "x86")]
+const KERNEL_INDEX: usize = 0x300;
+#[cfg(target_arch = "x86_64")]
+const KERNEL_INDEX: usize = 0o400;
+
+/// End of the user part, pages above are never accessible from user mode.
+const USER_END: usize = KERNEL_INDEX << (12 + LEVEL_BITS * (LEVELS - 1));
+
+#[cfg(target_arch = "x86")]
+const ADDR_MASK: usize = 0xFFFF_F000;
+#[cfg(target_arch = "x86_64")]
+const ADDR_MASK: usize = 0x000F_FFFF_FFFF_F000;
+
+#[allow(non_snake_case)]
+pub mod PageTableEntryFlags {
+    pub const P: usize = 1 << 0;
+    pub const RW: usize = 1 << 1;
+    pub const US: usize = 1 << 2;
+    pub const PWT: usize = 1 << 3;
+    pub const PCD: usize = 1 << 4;
+    pub const G: usize = 1 << 8;
+    /// Available to software, marks device memory which isn't owned by the
+    /// mapping.
+    pub const DEVICE: usize = 1 << 9;
+    #[cfg(target_arch = "x86_64")]
+    pub const NX: usize = 1 << 63;
+}
 
 //==================================================================================================
 // Structures
 //==================================================================================================
 
-pub struct Mapping {}
+/// Page table hierarchy
+///
+/// Entries are accessed through the recursive top-level entry, therefore only
+/// the currently active mapping can be inspected or modified.
+pub struct Mapping {
+    root: usize,
+    /// Whether only the kernel part is used, which is shared by all mappings
+    /// and therefore always current.
+    kernel: bool,
+}
 
 pub struct MappingIter<'this> {
+    addr: usize,
+    end: usize,
     this: marker::PhantomData<&'this ()>,
 }
 
 pub struct MappingIterMut<'this> {
-    this: marker::PhantomData<&'this ()>,
+    addr: usize,
+    end: usize,
+    this: marker::PhantomData<&'this mut ()>,
 }
 
+#[repr(transparent)]
 pub struct PageTableEntry(usize);
 
 //==================================================================================================
@@ -39,37 +110,330 @@ pub struct PageTableEntry(usize);
 //==================================================================================================
 
 impl Mapping {
-    fn iter(&self, addr: impl ops::RangeBounds<usize>) -> MappingIter {
+    /// Returns the mapping which is active on the current CPU.
+    pub fn current() -> Self {
+        Self {
+            root: x86::read_cr3() & ADDR_MASK,
+            kernel: false,
+        }
+    }
+
+    /// Returns the kernel part of the mapping which is active on the current
+    /// CPU.
+    pub fn kernel() -> Self {
+        Self {
+            root: x86::read_cr3() & ADDR_MASK,
+            kernel: true,
+        }
+    }
+
+    /// Creates a mapping without any user pages, whose kernel part is shared
+    /// with the current mapping.
+    ///
+    /// # Safety
+    ///
+    /// `table` must be a writable kernel mapping of the unused page at `root`.
+    pub unsafe fn new(root: usize, table: usize) -> Self {
+        let table = slice::from_raw_parts_mut(table as *mut PageTableEntry, ENTRIES);
+        let current = slice::from_raw_parts(entry(LEVELS, 0), ENTRIES);
+        for (index, entry) in table.iter_mut().enumerate() {
+            if index == RECURSIVE_INDEX {
+                entry.set(root, PageTableEntryFlags::P | PageTableEntryFlags::RW);
+            } else if index >= KERNEL_INDEX {
+                entry.0 = current[index].0;
+            } else {
+                entry.clear();
+            }
+        }
+        Self {
+            root,
+            kernel: false,
+        }
+    }
+
+    /// Physical address of the top-level table.
+    pub fn root(&self) -> usize {
+        self.root
+    }
+
+    pub fn is_current(&self) -> bool {
+        self.kernel || x86::read_cr3() & ADDR_MASK == self.root
+    }
+
+    /// Translates a virtual address into its physical address.
+    pub fn translate(&self, addr: usize) -> Option<usize> {
+        let (_, entry) = self.iter(addr..addr + 1).next()?;
+        entry
+            .is_present()
+            .then(|| entry.addr() | addr & (PAGE_SIZE - 1))
+    }
+
+    /// Maps `size` bytes starting at the page-aligned `addr` to `phys_addr`,
+    /// intermediate tables are allocated from `system_memory`.
+    ///
+    /// The operation may return `None` if there is not enough memory for the
+    /// intermediate tables, in which case the range is partially mapped.
+    pub fn map(
+        &mut self,
+        addr: usize,
+        phys_addr: usize,
+        size: usize,
+        flags: usize,
+        system_memory: &mut SystemMemory,
+    ) -> Option<()> {
+        debug_assert!(self.is_current());
+        debug_assert!(addr.is_multiple_of(PAGE_SIZE) && phys_addr.is_multiple_of(PAGE_SIZE));
+        assert!(flags & PageTableEntryFlags::US == 0 || is_user(addr, size));
+
+        for offset in (0..size).step_by(PAGE_SIZE) {
+            let page = addr + offset;
+            allocate_tables(page, flags, system_memory)?;
+
+            let entry = unsafe { &mut *entry(1, page) };
+            entry.set(phys_addr + offset, flags | PageTableEntryFlags::P);
+            x86::invlpg(page);
+        }
+
+        Some(())
+    }
+
+    /// Allocates all missing intermediate tables for `size` bytes starting at
+    /// `addr`, without mapping any page.
+    #[cfg(target_arch = "x86")]
+    pub fn populate(
+        &mut self,
+        addr: usize,
+        size: usize,
+        system_memory: &mut SystemMemory,
+    ) -> Option<()> {
+        debug_assert!(self.is_current());
+
+        let span = span(2);
+        let (addr, end) = page_range(addr..addr + size);
+        for page in (addr & !(span - 1)..end).step_by(span) {
+            allocate_tables(page, 0, system_memory)?;
+        }
+        Some(())
+    }
+
+    /// Unmaps `size` bytes starting at the page-aligned `addr`, and returns the
+    /// physical pages through `f`.
+    ///
+    /// The pages are still reachable through the TLBs of other CPUs while `f`
+    /// runs, so they may only be reused once this returns.
+    pub fn unmap(&mut self, addr: usize, size: usize, mut f: impl FnMut(usize)) {
+        debug_assert!(self.is_current());
+
+        let mut unmapped = false;
+        for (_, entry) in self.iter_mut(addr..addr + size) {
+            if entry.is_present() {
+                f(entry.addr());
+                entry.clear();
+                unmapped = true;
+            }
+        }
+        // the mapping may be current on other CPUs as well
+        if unmapped {
+            x86::flush_tlb(addr..addr + size);
+        }
+    }
+
+    /// Unmaps all user pages and frees them together with the intermediate
+    /// tables of the user part, device memory is left untouched.
+    ///
+    /// The TLB is not flushed, the mapping must be switched away from before
+    /// the freed memory is used again.
+    pub fn clear_user(&mut self, system_memory: &mut SystemMemory) {
+        debug_assert!(self.is_current() && !self.kernel);
+
+        for (_, entry) in self.iter_mut(..USER_END) {
+            if entry.is_present() {
+                if entry.flags() & PageTableEntryFlags::DEVICE == 0 {
+                    system_memory.deallocate(entry.addr(), PAGE_SIZE);
+                }
+                entry.clear();
+            }
+        }
+        // bottom-up, the tables above are needed to reach the entries
+        for level in 2..=LEVELS {
+            let mut addr = 0;
+            'outer: while addr < USER_END {
+                for upper in (level + 1..=LEVELS).rev() {
+                    if !unsafe { &*entry(upper, addr) }.is_present() {
+                        addr = (addr & !(span(upper) - 1)) + span(upper);
+                        continue 'outer;
+                    }
+                }
+                let entry = unsafe { &mut *entry(level, addr) };
+                if entry.is_present() {
+                    system_memory.deallocate(entry.addr(), PAGE_SIZE);
+                    entry.clear();
+                }
+                addr += span(level);
+            }
+        }
+    }
+
+    /// Iterates over all leaf entries in the range whose tables are present.
+    fn iter(&self, addr: impl ops::RangeBounds<usize>) -> MappingIter<'_> {
+        let (addr, end) = page_range(addr);
         MappingIter {
+            addr,
+            end,
             this: Default::default(),
         }
     }
 
-    fn iter_mut(&self, addr: impl ops::RangeBounds<usize>) -> MappingIterMut {
+    /// Iterates mutably over all leaf entries in the range whose tables are
+    /// present.
+    fn iter_mut(&mut self, addr: impl ops::RangeBounds<usize>) -> MappingIterMut<'_> {
+        let (addr, end) = page_range(addr);
         MappingIterMut {
+            addr,
+            end,
             this: Default::default(),
         }
     }
 }
 
-impl PageTableEntry {}
+impl PageTableEntry {
+    pub fn is_present(&self) -> bool {
+        self.0 & PageTableEntryFlags::P != 0
+    }
+
+    /// Physical address the entry refers to.
+    pub fn addr(&self) -> usize {
+        self.0 & ADDR_MASK
+    }
+
+    pub fn flags(&self) -> usize {
+        self.0 & !ADDR_MASK
+    }
+
+    pub fn set(&mut self, addr: usize, flags: usize) {
+        self.0 = addr & ADDR_MASK | flags;
+    }
+
+    pub fn clear(&mut self) {
+        self.0 = 0;
+    }
+}
 
 //==================================================================================================
 // Trait Implementations
 //==================================================================================================
 
 impl<'this> Iterator for MappingIter<'this> {
-    type Item = &'this PageTableEntry;
+    type Item = (usize, &'this PageTableEntry);
 
     fn next(&mut self) -> Option<Self::Item> {
-        None
+        let addr = next_present(&mut self.addr, self.end)?;
+        Some((addr, unsafe { &*entry(1, addr) }))
     }
 }
 
 impl<'this> Iterator for MappingIterMut<'this> {
-    type Item = &'this mut PageTableEntry;
+    type Item = (usize, &'this mut PageTableEntry);
 
     fn next(&mut self) -> Option<Self::Item> {
-        None
+        let addr = next_present(&mut self.addr, self.end)?;
+        Some((addr, unsafe { &mut *entry(1, addr) }))
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Allocates the missing intermediate tables for `page`, and grants user
+/// access to them if `flags` does.
+fn allocate_tables(page: usize, flags: usize, system_memory: &mut SystemMemory) -> Option<()> {
+    // intermediate tables inherit the user bit, the leaf entry decides
+    let table_flags =
+        PageTableEntryFlags::P | PageTableEntryFlags::RW | flags & PageTableEntryFlags::US;
+    for level in (2..=LEVELS).rev() {
+        let entry = unsafe { &mut *entry(level, page) };
+        if !entry.is_present() {
+            let table = system_memory.allocate(None, PAGE_SIZE)?;
+            entry.set(table, table_flags);
+            // the new table is now reachable one level below
+            let table = entry_of_table(level - 1, page);
+            x86::invlpg(table);
+            unsafe { ptr::write_bytes(table as *mut u8, 0, PAGE_SIZE) };
+        } else if flags & PageTableEntryFlags::US != 0 {
+            entry.0 |= PageTableEntryFlags::US;
+        }
+    }
+    Some(())
+}
+
+/// Whether the range lies completely within the user part.
+fn is_user(addr: usize, size: usize) -> bool {
+    addr.checked_add(size).is_some_and(|end| end <= USER_END)
+}
+
+/// Returns the address of the entry at `level` (1 being the leaf level) which
+/// is responsible for `addr`.
+fn entry(level: u32, addr: usize) -> *mut PageTableEntry {
+    let mut base = sign_extend(RECURSIVE_INDEX << (12 + LEVEL_BITS * (LEVELS - 1)));
+    for level in 1..level {
+        base += RECURSIVE_INDEX << (12 + LEVEL_BITS * (LEVELS - 1 - level));
+    }
+    let index_bits = LEVEL_BITS * (LEVELS - level + 1);
+    let index = (addr >> (12 + LEVEL_BITS * (level - 1))) & ((1 << index_bits) - 1);
+    (base + index * mem::size_of::<PageTableEntry>()) as *mut PageTableEntry
+}
+
+/// Returns the address of the table at `level` which is responsible for
+/// `addr`.
+fn entry_of_table(level: u32, addr: usize) -> usize {
+    entry(level, addr) as usize & !(PAGE_SIZE - 1)
+}
+
+/// Advances `addr` to the next page whose leaf table is present and returns it.
+fn next_present(addr: &mut usize, end: usize) -> Option<usize> {
+    'outer: while *addr < end {
+        for level in (2..=LEVELS).rev() {
+            if !unsafe { &*entry(level, *addr) }.is_present() {
+                // skip the whole area covered by the missing table
+                let span = span(level);
+                *addr = (*addr & !(span - 1)).checked_add(span).unwrap_or(end);
+                continue 'outer;
+            }
+        }
+
+        let page = *addr;
+        *addr += PAGE_SIZE;
+        return Some(page);
     }
+    None
+}
+
+/// Size of the area covered by an entry at `level`.
+fn span(level: u32) -> usize {
+    1 << (12 + LEVEL_BITS * (level - 1))
+}
+
+fn page_range(addr: impl ops::RangeBounds<usize>) -> (usize, usize) {
+    let start = match addr.start_bound() {
+        ops::Bound::Included(&start) => start,
+        ops::Bound::Excluded(&start) => start + 1,
+        ops::Bound::Unbounded => 0,
+    };
+    let end = match addr.end_bound() {
+        ops::Bound::Included(&end) => end + 1,
+        ops::Bound::Excluded(&end) => end,
+        ops::Bound::Unbounded => usize::MAX,
+    };
+    (start & !(PAGE_SIZE - 1), end)
+}
+
+#[cfg(target_arch = "x86")]
+const fn sign_extend(addr: usize) -> usize {
+    addr
+}
+
+#[cfg(target_arch = "x86_64")]
+const fn sign_extend(addr: usize) -> usize {
+    ((addr << 16) as isize >> 16) as usize
 }
@@ -16,12 +16,16 @@
 // Imports
 //==================================================================================================
 
+mod kernel;
 mod mapping;
 mod object;
 mod process;
 mod system;
+mod user;
 
-use mapping::*;
+pub use kernel::*;
+pub use mapping::*;
 pub use object::*;
 pub use process::*;
 pub use system::*;
+pub use user::*;
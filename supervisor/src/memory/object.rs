@@ -16,46 +16,61 @@
 // Imports
 //==================================================================================================
 
-use spin::Mutex;
+use core::ptr;
+
+use crate::sync::SpinLock;
 
 //==================================================================================================
 // Structures
 //==================================================================================================
 
-/// Pool of objects
-pub struct ObjectPool<T>(Mutex<[Option<T>; 32]>);
+/// Pool of `N` objects
+pub struct ObjectPool<T, const N: usize = 32>(SpinLock<[Option<T>; N]>);
 
 //==================================================================================================
 // Implementations
 //==================================================================================================
 
-impl<T> ObjectPool<T> {
+impl<T, const N: usize> ObjectPool<T, N> {
     /// Creates a new memory pool.
     pub const fn new() -> Self {
-        Self(Mutex::new([
-            None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-            None, None, None, None, None, None, None, None, None, None, None, None, None, None,
-            None, None, None, None,
-        ]))
+        Self(SpinLock::new([const { None }; N]))
     }
 
     /// Allocates an object from the pool which must be deallocated to be
     /// available again.
     #[allow(clippy::mut_from_ref)]
     pub fn allocate(&self, value: T) -> &mut T {
+        self.try_allocate(value).unwrap()
+    }
+
+    /// Like [`ObjectPool::allocate`], but returns `None` if the pool is full.
+    #[allow(clippy::mut_from_ref)]
+    pub fn try_allocate(&self, value: T) -> Option<&mut T> {
         let mut pool = self.0.lock();
-        let entry = pool.iter_mut().find(|entry| entry.is_none()).unwrap();
+        let entry = pool.iter_mut().find(|entry| entry.is_none())?;
         *entry = Some(value);
-        unsafe { &mut *(entry.as_mut().unwrap() as *mut _) }
+        Some(unsafe { &mut *(entry.as_mut().unwrap() as *mut _) })
     }
 
     /// Deallocates an object and makes it available to subsequent
     /// `allocate` operations.
-    pub fn deallocate(&self, reference: &mut T) {}
+    pub fn deallocate(&self, reference: &mut T) {
+        let mut pool = self.0.lock();
+        let entry = pool
+            .iter_mut()
+            .find(|entry| {
+                entry
+                    .as_ref()
+                    .is_some_and(|value| ptr::eq(value, reference))
+            })
+            .unwrap();
+        *entry = None;
+    }
 }
 
 //==================================================================================================
 // Trait Implementations
 //==================================================================================================
 
-unsafe impl<T> Sync for ObjectPool<T> {}
+unsafe impl<T, const N: usize> Sync for ObjectPool<T, N> {}
@@ -16,22 +16,50 @@
 // Imports
 //==================================================================================================
 
-use intrusive_collections::{intrusive_adapter, KeyAdapter, RBTree, RBTreeLink, UnsafeRef};
+use core::ops;
 
-use crate::memory::{Mapping, ObjectPool};
+use intrusive_collections::{intrusive_adapter, Bound, KeyAdapter, RBTree, RBTreeLink, UnsafeRef};
+
+use crate::{
+    memory::{Mapping, ObjectPool},
+    process::MAX_PROCESSES,
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Part of the address space available to user processes, the first page is
+/// left out to catch null pointers.
+#[cfg(target_arch = "x86")]
+pub const USER_MEMORY_RANGE: ops::Range<usize> = 0x1000..0xC0000000;
+#[cfg(target_arch = "x86_64")]
+pub const USER_MEMORY_RANGE: ops::Range<usize> = 0x1000..0x0000_8000_0000_0000;
+
+/// Number of allocated ranges a user address space may have.
+pub const MAX_USER_REGIONS: usize = 64;
+
+/// Number of allocated ranges the kernel address space may have.
+pub const MAX_KERNEL_REGIONS: usize = 512;
+
+const MAX_REGIONS: usize = MAX_PROCESSES * MAX_USER_REGIONS + MAX_KERNEL_REGIONS;
 
 //==================================================================================================
 // Variables
 //==================================================================================================
 
-static PROCESS_MEMORY_USED_POOL: ObjectPool<ProcessMemoryUsed> = ObjectPool::new();
+static PROCESS_MEMORY_USED_POOL: ObjectPool<ProcessMemoryUsed, MAX_REGIONS> = ObjectPool::new();
 
 //==================================================================================================
 // Structures
 //==================================================================================================
 
+/// Virtual memory bookkeeping of a single address space
 pub struct ProcessMemory {
+    range: ops::Range<usize>,
     used: RBTree<ProcessMemoryUsedAdapter>,
+    used_count: usize,
+    max_regions: usize,
 
     mapping: Mapping,
 }
@@ -47,17 +75,112 @@ struct ProcessMemoryUsed {
 //==================================================================================================
 
 impl ProcessMemory {
+    /// Creates a new virtual memory bookkeeping structure for the given range
+    /// with the default state being that everything is available, at most
+    /// `max_regions` ranges can be allocated at a time.
+    pub fn new(range: ops::Range<usize>, max_regions: usize, mapping: Mapping) -> Self {
+        Self {
+            range,
+            used: RBTree::new(ProcessMemoryUsedAdapter::NEW),
+            used_count: 0,
+            max_regions,
+            mapping,
+        }
+    }
+
+    /// Allocates a range of addresses which must be deallocated to be available
+    /// again.
+    ///
+    /// The operation may return `None` if the requested `addr` is already in
+    /// use, there is no gap large enough to encompass `size` or too many
+    /// ranges are allocated.
     pub fn allocate(&mut self, addr: Option<usize>, size: usize) -> Option<usize> {
-        None
+        if self.used_count == self.max_regions {
+            return None;
+        }
+
+        let addr = if let Some(addr) = addr {
+            if addr < self.range.start || addr.checked_add(size)? > self.range.end {
+                return None;
+            }
+
+            let cursor = self.used.upper_bound(Bound::Included(&addr));
+            if let Some(used) = cursor.get() {
+                if used.addr + used.size > addr {
+                    return None;
+                }
+            }
+            if let Some(used) = cursor.peek_next().get() {
+                if used.addr < addr + size {
+                    return None;
+                }
+            }
+            addr
+        } else {
+            // first-fit
+            let mut addr = self.range.start;
+            for used in self.used.iter() {
+                if used.addr - addr >= size {
+                    break;
+                }
+                addr = used.addr + used.size;
+            }
+            if self.range.end - addr < size {
+                return None;
+            }
+            addr
+        };
+
+        let used = PROCESS_MEMORY_USED_POOL.try_allocate(ProcessMemoryUsed {
+            link: Default::default(),
+            addr,
+            size,
+        })?;
+        self.used.insert(unsafe { UnsafeRef::from_raw(used) });
+        self.used_count += 1;
+        Some(addr)
     }
 
-    pub fn deallocate(&mut self, addr: usize, size: usize) {}
+    /// Deallocates a range of addresses previously returned by `allocate` and
+    /// makes it available to subsequent `allocate` operations.
+    pub fn deallocate(&mut self, addr: usize, size: usize) {
+        let mut cursor = self.used.find_mut(&addr);
+        let used = unsafe { &mut *UnsafeRef::into_raw(cursor.remove().unwrap()) };
+        debug_assert!(used.size == size);
+        PROCESS_MEMORY_USED_POOL.deallocate(used);
+        self.used_count -= 1;
+    }
+
+    /// Returns the allocated range containing `addr`.
+    pub fn find(&self, addr: usize) -> Option<ops::Range<usize>> {
+        let used = self.used.upper_bound(Bound::Included(&addr)).get()?;
+        (addr < used.addr + used.size).then_some(used.addr..used.addr + used.size)
+    }
+
+    pub fn mapping(&self) -> &Mapping {
+        &self.mapping
+    }
+
+    pub fn mapping_mut(&mut self) -> &mut Mapping {
+        &mut self.mapping
+    }
 }
 
 //==================================================================================================
 // Trait Implementations
 //==================================================================================================
 
+unsafe impl Send for ProcessMemory {}
+
+impl Drop for ProcessMemory {
+    fn drop(&mut self) {
+        let mut cursor = self.used.front_mut();
+        while let Some(used) = cursor.remove() {
+            PROCESS_MEMORY_USED_POOL.deallocate(unsafe { &mut *UnsafeRef::into_raw(used) });
+        }
+    }
+}
+
 intrusive_adapter!(ProcessMemoryUsedAdapter = UnsafeRef<ProcessMemoryUsed>: ProcessMemoryUsed { link: RBTreeLink });
 
 impl KeyAdapter<'_> for ProcessMemoryUsedAdapter {
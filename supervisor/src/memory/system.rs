@@ -108,7 +108,7 @@ impl SystemMemory {
                     }
                     return Some(addr);
                 }
-                cursor.move_prev();
+                cursor.move_next();
             }
         }
 
@@ -164,6 +164,8 @@ impl SystemMemory {
 // Trait Implementations
 //==================================================================================================
 
+unsafe impl Send for SystemMemory {}
+
 intrusive_adapter!(SystemMemoryFreeAdapter = UnsafeRef<SystemMemoryFree>: SystemMemoryFree { link: RBTreeLink });
 
 impl KeyAdapter<'_> for SystemMemoryFreeAdapter {
@@ -0,0 +1,144 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{marker::PhantomData, mem, mem::MaybeUninit};
+
+use abi::Error;
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+use crate::{memory::USER_MEMORY_RANGE, process::Scheduler, x86};
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Pointer to a `T` in the address space of the current process
+pub struct UserPtr<T> {
+    addr: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+/// Byte range in the address space of the current process
+#[derive(Clone, Copy)]
+pub struct UserSlice {
+    addr: usize,
+    len: usize,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl<T> UserPtr<T> {
+    pub fn new(addr: usize) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.addr == 0
+    }
+
+    fn as_slice(&self) -> UserSlice {
+        UserSlice::new(self.addr, mem::size_of::<T>())
+    }
+}
+
+impl<T: FromBytes> UserPtr<T> {
+    pub fn read(&self) -> Result<T, Error> {
+        let mut value = MaybeUninit::<T>::uninit();
+        let buffer = unsafe {
+            core::slice::from_raw_parts_mut(value.as_mut_ptr().cast::<u8>(), mem::size_of::<T>())
+        };
+        self.as_slice().read(buffer)?;
+        // any bytes are a valid `T`
+        Ok(unsafe { value.assume_init() })
+    }
+}
+
+impl<T: IntoBytes + Immutable> UserPtr<T> {
+    pub fn write(&self, value: &T) -> Result<(), Error> {
+        self.as_slice().write(value.as_bytes())
+    }
+}
+
+impl UserSlice {
+    pub fn new(addr: usize, len: usize) -> Self {
+        Self { addr, len }
+    }
+
+    /// Copies the first `buffer.len()` bytes of the slice into `buffer`.
+    pub fn read(&self, buffer: &mut [u8]) -> Result<(), Error> {
+        if buffer.len() > self.len {
+            return Err(Error::InvalidArgument);
+        }
+        self.check(buffer.len())?;
+        match unsafe { x86::copy_user(buffer.as_mut_ptr(), self.addr as *const u8, buffer.len()) } {
+            true => Ok(()),
+            false => Err(Error::Fault),
+        }
+    }
+
+    /// Copies `data` to the start of the slice.
+    pub fn write(&self, data: &[u8]) -> Result<(), Error> {
+        if data.len() > self.len {
+            return Err(Error::InvalidArgument);
+        }
+        self.check(data.len())?;
+        match unsafe { x86::copy_user(self.addr as *mut u8, data.as_ptr(), data.len()) } {
+            true => Ok(()),
+            false => Err(Error::Fault),
+        }
+    }
+
+    /// Checks that the first `len` bytes are allocated in the current process.
+    ///
+    /// The memory may still change until the copy, which is why it recovers
+    /// from faults anyway.
+    fn check(&self, len: usize) -> Result<(), Error> {
+        let end = self.addr.checked_add(len).ok_or(Error::Fault)?;
+        if len == 0 {
+            return Ok(());
+        }
+        if self.addr < USER_MEMORY_RANGE.start || end > USER_MEMORY_RANGE.end {
+            return Err(Error::Fault);
+        }
+
+        let process = Scheduler::current().process().ok_or(Error::Fault)?;
+        let memory = process.memory();
+        let mut addr = self.addr;
+        while addr < end {
+            addr = memory.find(addr).ok_or(Error::Fault)?.end;
+        }
+        Ok(())
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+impl<T> Clone for UserPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for UserPtr<T> {}
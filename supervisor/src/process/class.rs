@@ -0,0 +1,339 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::cmp::Ordering;
+
+use intrusive_collections::{KeyAdapter, LinkedList, RBTree, UnsafeRef};
+
+use crate::process::{DeadlineAdapter, Thread, ThreadAdapter};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Number of levels of the fixed-priority class, higher values run first.
+pub const PRIORITY_LEVELS: usize = 32;
+
+/// Priority of threads which don't ask for anything else.
+pub const DEFAULT_PRIORITY: u8 = PRIORITY_LEVELS as u8 / 2;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Scheduling policy of a thread, ordered by precedence
+///
+/// Ready threads of a class only run if all classes before it have nothing to
+/// run.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum SchedulingPolicy {
+    /// Earliest deadline first.
+    Deadline,
+    /// Highest priority first, round-robin within the same priority.
+    FixedPriority,
+    /// Round-robin among all threads, for background work.
+    RoundRobin,
+}
+
+/// Ready threads of one scheduling policy on a single CPU
+pub trait SchedulingClass {
+    /// Adds a ready thread.
+    fn enqueue(&mut self, thread: &'static Thread);
+
+    /// Removes a thread which has been added before.
+    fn remove(&mut self, thread: &Thread);
+
+    /// Removes and returns the thread to run next.
+    fn pick_next(&mut self) -> Option<&'static Thread>;
+
+    /// Removes and returns a thread which may run on another CPU.
+    fn steal(&mut self, cpu_id: u32) -> Option<&'static Thread>;
+
+    fn is_empty(&self) -> bool;
+
+    /// Whether `thread` which just became ready should run instead of
+    /// `current`, both being of this class.
+    fn should_preempt(&self, current: &Thread, thread: &Thread) -> bool;
+}
+
+/// All scheduling classes of a single CPU
+pub struct SchedulingClasses {
+    deadline: DeadlineClass,
+    fixed_priority: FixedPriorityClass,
+    round_robin: RoundRobinClass,
+}
+
+pub struct DeadlineClass {
+    threads: RBTree<DeadlineAdapter>,
+}
+
+pub struct FixedPriorityClass {
+    levels: [LinkedList<ThreadAdapter>; PRIORITY_LEVELS],
+    /// One bit for each non-empty level.
+    present: u32,
+}
+
+pub struct RoundRobinClass {
+    threads: LinkedList<ThreadAdapter>,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl SchedulingClasses {
+    pub const fn new() -> Self {
+        Self {
+            deadline: DeadlineClass::new(),
+            fixed_priority: FixedPriorityClass::new(),
+            round_robin: RoundRobinClass::new(),
+        }
+    }
+
+    pub fn get(&self, policy: SchedulingPolicy) -> &dyn SchedulingClass {
+        match policy {
+            SchedulingPolicy::Deadline => &self.deadline,
+            SchedulingPolicy::FixedPriority => &self.fixed_priority,
+            SchedulingPolicy::RoundRobin => &self.round_robin,
+        }
+    }
+
+    pub fn get_mut(&mut self, policy: SchedulingPolicy) -> &mut dyn SchedulingClass {
+        match policy {
+            SchedulingPolicy::Deadline => &mut self.deadline,
+            SchedulingPolicy::FixedPriority => &mut self.fixed_priority,
+            SchedulingPolicy::RoundRobin => &mut self.round_robin,
+        }
+    }
+
+    pub fn enqueue(&mut self, thread: &'static Thread) {
+        self.get_mut(thread.policy()).enqueue(thread);
+    }
+
+    pub fn remove(&mut self, thread: &Thread) {
+        self.get_mut(thread.policy()).remove(thread);
+    }
+
+    /// Removes and returns the thread to run next from the first class which
+    /// has one.
+    pub fn pick_next(&mut self) -> Option<&'static Thread> {
+        self.iter_mut()
+            .into_iter()
+            .find_map(|class| class.pick_next())
+    }
+
+    /// Removes and returns a thread which may run on another CPU, preferring
+    /// classes of higher precedence.
+    pub fn steal(&mut self, cpu_id: u32) -> Option<&'static Thread> {
+        self.iter_mut()
+            .into_iter()
+            .find_map(|class| class.steal(cpu_id))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deadline.is_empty() && self.fixed_priority.is_empty() && self.round_robin.is_empty()
+    }
+
+    /// Whether `thread` which just became ready should run instead of
+    /// `current`.
+    pub fn should_preempt(&self, current: &Thread, thread: &Thread) -> bool {
+        match thread.policy().cmp(&current.policy()) {
+            Ordering::Less => true,
+            Ordering::Greater => false,
+            Ordering::Equal => self.get(thread.policy()).should_preempt(current, thread),
+        }
+    }
+
+    /// Classes in order of precedence.
+    fn iter_mut(&mut self) -> [&mut dyn SchedulingClass; 3] {
+        [
+            &mut self.deadline,
+            &mut self.fixed_priority,
+            &mut self.round_robin,
+        ]
+    }
+}
+
+impl DeadlineClass {
+    pub const fn new() -> Self {
+        Self {
+            threads: RBTree::new(DeadlineAdapter::NEW),
+        }
+    }
+}
+
+impl FixedPriorityClass {
+    pub const fn new() -> Self {
+        Self {
+            levels: [const { LinkedList::new(ThreadAdapter::NEW) }; PRIORITY_LEVELS],
+            present: 0,
+        }
+    }
+}
+
+impl RoundRobinClass {
+    pub const fn new() -> Self {
+        Self {
+            threads: LinkedList::new(ThreadAdapter::NEW),
+        }
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+impl SchedulingClass for DeadlineClass {
+    fn enqueue(&mut self, thread: &'static Thread) {
+        self.threads.insert(unsafe { UnsafeRef::from_raw(thread) });
+    }
+
+    fn remove(&mut self, thread: &Thread) {
+        unsafe { self.threads.cursor_mut_from_ptr(thread) }.remove();
+    }
+
+    fn pick_next(&mut self) -> Option<&'static Thread> {
+        let thread = self.threads.front_mut().remove()?;
+        Some(unsafe { &*UnsafeRef::into_raw(thread) })
+    }
+
+    fn steal(&mut self, cpu_id: u32) -> Option<&'static Thread> {
+        let mut cursor = self.threads.front_mut();
+        while let Some(thread) = cursor.get() {
+            if thread.can_run_on(cpu_id) {
+                return Some(unsafe { &*UnsafeRef::into_raw(cursor.remove()?) });
+            }
+            cursor.move_next();
+        }
+        None
+    }
+
+    fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    fn should_preempt(&self, current: &Thread, thread: &Thread) -> bool {
+        thread.deadline() < current.deadline()
+    }
+}
+
+impl SchedulingClass for FixedPriorityClass {
+    fn enqueue(&mut self, thread: &'static Thread) {
+        let level = level(thread);
+        self.levels[level].push_back(unsafe { UnsafeRef::from_raw(thread) });
+        self.present |= 1 << level;
+    }
+
+    fn remove(&mut self, thread: &Thread) {
+        let level = level(thread);
+        unsafe { self.levels[level].cursor_mut_from_ptr(thread) }.remove();
+        if self.levels[level].is_empty() {
+            self.present &= !(1 << level);
+        }
+    }
+
+    fn pick_next(&mut self) -> Option<&'static Thread> {
+        if self.present == 0 {
+            return None;
+        }
+        let level = (u32::BITS - 1 - self.present.leading_zeros()) as usize;
+        let thread = self.levels[level].pop_front()?;
+        if self.levels[level].is_empty() {
+            self.present &= !(1 << level);
+        }
+        Some(unsafe { &*UnsafeRef::into_raw(thread) })
+    }
+
+    fn steal(&mut self, cpu_id: u32) -> Option<&'static Thread> {
+        for level in (0..PRIORITY_LEVELS).rev() {
+            if let Some(thread) = steal_from(&mut self.levels[level], cpu_id) {
+                if self.levels[level].is_empty() {
+                    self.present &= !(1 << level);
+                }
+                return Some(thread);
+            }
+        }
+        None
+    }
+
+    fn is_empty(&self) -> bool {
+        self.present == 0
+    }
+
+    fn should_preempt(&self, current: &Thread, thread: &Thread) -> bool {
+        level(thread) > level(current)
+    }
+}
+
+impl SchedulingClass for RoundRobinClass {
+    fn enqueue(&mut self, thread: &'static Thread) {
+        self.threads
+            .push_back(unsafe { UnsafeRef::from_raw(thread) });
+    }
+
+    fn remove(&mut self, thread: &Thread) {
+        unsafe { self.threads.cursor_mut_from_ptr(thread) }.remove();
+    }
+
+    fn pick_next(&mut self) -> Option<&'static Thread> {
+        let thread = self.threads.pop_front()?;
+        Some(unsafe { &*UnsafeRef::into_raw(thread) })
+    }
+
+    fn steal(&mut self, cpu_id: u32) -> Option<&'static Thread> {
+        steal_from(&mut self.threads, cpu_id)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    fn should_preempt(&self, _current: &Thread, _thread: &Thread) -> bool {
+        false
+    }
+}
+
+impl KeyAdapter<'_> for DeadlineAdapter {
+    type Key = u64;
+
+    fn get_key(
+        &self,
+        value: &'_ <Self::PointerOps as intrusive_collections::PointerOps>::Value,
+    ) -> Self::Key {
+        value.deadline()
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+fn level(thread: &Thread) -> usize {
+    (thread.priority() as usize).min(PRIORITY_LEVELS - 1)
+}
+
+fn steal_from(threads: &mut LinkedList<ThreadAdapter>, cpu_id: u32) -> Option<&'static Thread> {
+    let mut cursor = threads.front_mut();
+    while let Some(thread) = cursor.get() {
+        if thread.can_run_on(cpu_id) {
+            return Some(unsafe { &*UnsafeRef::into_raw(cursor.remove()?) });
+        }
+        cursor.move_next();
+    }
+    None
+}
@@ -0,0 +1,704 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::mem;
+
+use zerocopy::{FromBytes, Immutable, KnownLayout};
+
+use crate::{
+    memory::{
+        KernelMemory, Mapping, PageTableEntryFlags, ProcessMemory, MAX_USER_REGIONS, PAGE_SIZE,
+        USER_MEMORY_RANGE,
+    },
+    process::{Process, Scheduler, System, DEFAULT_PRIORITY},
+    x86,
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+const ELF_MAGIC: [u8; 4] = *b"\x7FELF";
+
+/// Little-endian data encoding.
+const ELF_DATA_LSB: u8 = 1;
+
+#[allow(non_snake_case)]
+mod ElfClass {
+    pub const ELF32: u8 = 1;
+    pub const ELF64: u8 = 2;
+}
+
+#[allow(non_snake_case)]
+mod ElfType {
+    pub const EXEC: u16 = 2;
+}
+
+#[allow(non_snake_case)]
+mod ElfMachine {
+    #[cfg(target_arch = "x86")]
+    pub const I386: u16 = 3;
+    #[cfg(target_arch = "x86_64")]
+    pub const X86_64: u16 = 62;
+}
+
+#[allow(non_snake_case)]
+mod ElfSegmentType {
+    pub const LOAD: u32 = 1;
+    pub const PHDR: u32 = 6;
+    pub const TLS: u32 = 7;
+}
+
+#[allow(non_snake_case)]
+mod ElfSegmentFlags {
+    pub const X: u32 = 1 << 0;
+    pub const W: u32 = 1 << 1;
+}
+
+#[allow(non_snake_case)]
+mod AuxvType {
+    pub const NULL: usize = 0;
+    pub const PHDR: usize = 3;
+    pub const PHENT: usize = 4;
+    pub const PHNUM: usize = 5;
+    pub const PAGESZ: usize = 6;
+    pub const ENTRY: usize = 9;
+}
+
+#[cfg(target_arch = "x86")]
+const NATIVE_CLASS: u8 = ElfClass::ELF32;
+#[cfg(target_arch = "x86_64")]
+const NATIVE_CLASS: u8 = ElfClass::ELF64;
+
+#[cfg(target_arch = "x86")]
+const NATIVE_MACHINE: u16 = ElfMachine::I386;
+#[cfg(target_arch = "x86_64")]
+const NATIVE_MACHINE: u16 = ElfMachine::X86_64;
+
+/// Size of the stack of the main thread, which ends at the top of the user
+/// address space.
+const USER_STACK_SIZE: usize = 16 * PAGE_SIZE;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct ElfIdent {
+    magic: [u8; 4],
+    class: u8,
+    data: u8,
+    version: u8,
+    os_abi: u8,
+    padding: [u8; 8],
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct Elf32Header {
+    ident: ElfIdent,
+    type_: u16,
+    machine: u16,
+    version: u32,
+    entry: u32,
+    phoff: u32,
+    shoff: u32,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct Elf64Header {
+    ident: ElfIdent,
+    type_: u16,
+    machine: u16,
+    version: u32,
+    entry: u64,
+    phoff: u64,
+    shoff: u64,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct Elf32ProgramHeader {
+    type_: u32,
+    offset: u32,
+    vaddr: u32,
+    paddr: u32,
+    filesz: u32,
+    memsz: u32,
+    flags: u32,
+    align: u32,
+}
+
+#[derive(FromBytes, KnownLayout, Immutable)]
+#[repr(C)]
+struct Elf64ProgramHeader {
+    type_: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    filesz: u64,
+    memsz: u64,
+    align: u64,
+}
+
+/// ELF image of either class
+pub struct Elf<'a> {
+    data: &'a [u8],
+    class: u8,
+    type_: u16,
+    machine: u16,
+    entry: usize,
+    phoff: usize,
+    phentsize: usize,
+    phnum: usize,
+}
+
+/// Program header, independent of the class
+#[derive(Clone, Copy)]
+pub struct ElfSegment {
+    pub type_: u32,
+    pub flags: u32,
+    pub offset: usize,
+    pub vaddr: usize,
+    pub filesz: usize,
+    pub memsz: usize,
+    pub align: usize,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl<'a> Elf<'a> {
+    /// Parses the file header, returns `None` if `data` is no little-endian ELF
+    /// image or the program headers are out of bounds.
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let (ident, _) = ElfIdent::read_from_prefix(data).ok()?;
+        if ident.magic != ELF_MAGIC || ident.data != ELF_DATA_LSB {
+            return None;
+        }
+
+        let (this, phentsize) = match ident.class {
+            ElfClass::ELF32 => {
+                let (header, _) = Elf32Header::read_from_prefix(data).ok()?;
+                let this = Self {
+                    data,
+                    class: ident.class,
+                    type_: header.type_,
+                    machine: header.machine,
+                    entry: header.entry as usize,
+                    phoff: header.phoff as usize,
+                    phentsize: header.phentsize as usize,
+                    phnum: header.phnum as usize,
+                };
+                (this, mem::size_of::<Elf32ProgramHeader>())
+            }
+            ElfClass::ELF64 => {
+                let (header, _) = Elf64Header::read_from_prefix(data).ok()?;
+                let this = Self {
+                    data,
+                    class: ident.class,
+                    type_: header.type_,
+                    machine: header.machine,
+                    entry: usize::try_from(header.entry).ok()?,
+                    phoff: usize::try_from(header.phoff).ok()?,
+                    phentsize: header.phentsize as usize,
+                    phnum: header.phnum as usize,
+                };
+                (this, mem::size_of::<Elf64ProgramHeader>())
+            }
+            _ => return None,
+        };
+
+        let phend = this
+            .phoff
+            .checked_add(this.phentsize.checked_mul(this.phnum)?)?;
+        if this.phentsize < phentsize || phend > data.len() {
+            return None;
+        }
+        Some(this)
+    }
+
+    /// Whether the image is an executable for the architecture of the kernel.
+    pub fn is_native(&self) -> bool {
+        self.class == NATIVE_CLASS && self.machine == NATIVE_MACHINE && self.type_ == ElfType::EXEC
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = ElfSegment> + '_ {
+        (0..self.phnum).map(|index| {
+            let data = &self.data[self.phoff + index * self.phentsize..];
+            if self.class == ElfClass::ELF32 {
+                let (header, _) = Elf32ProgramHeader::read_from_prefix(data).unwrap();
+                ElfSegment {
+                    type_: header.type_,
+                    flags: header.flags,
+                    offset: header.offset as usize,
+                    vaddr: header.vaddr as usize,
+                    filesz: header.filesz as usize,
+                    memsz: header.memsz as usize,
+                    align: header.align as usize,
+                }
+            } else {
+                let (header, _) = Elf64ProgramHeader::read_from_prefix(data).unwrap();
+                ElfSegment {
+                    type_: header.type_,
+                    flags: header.flags,
+                    offset: header.offset as usize,
+                    vaddr: header.vaddr as usize,
+                    filesz: header.filesz as usize,
+                    memsz: header.memsz as usize,
+                    align: header.align as usize,
+                }
+            }
+        })
+    }
+
+    /// File contents of a segment, `None` if it is out of bounds.
+    pub fn segment_data(&self, segment: &ElfSegment) -> Option<&'a [u8]> {
+        self.data
+            .get(segment.offset..segment.offset.checked_add(segment.filesz)?)
+    }
+
+    /// User address of the program headers once loaded, 0 if they aren't part
+    /// of any loaded segment.
+    fn phdr_addr(&self) -> usize {
+        if let Some(phdr) = self
+            .segments()
+            .find(|segment| segment.type_ == ElfSegmentType::PHDR)
+        {
+            return phdr.vaddr;
+        }
+        self.segments()
+            .find(|segment| {
+                segment.type_ == ElfSegmentType::LOAD
+                    && segment
+                        .offset
+                        .checked_add(segment.filesz)
+                        .is_some_and(|end| (segment.offset..end).contains(&self.phoff))
+            })
+            .and_then(|segment| segment.vaddr.checked_add(self.phoff - segment.offset))
+            .unwrap_or(0)
+    }
+
+    /// Creates a process running this image in a single thread, `argv` and
+    /// `envp` are passed on its stack.
+    ///
+    /// Returns `None` if the image can't be loaded or there is not enough
+    /// memory.
+    pub fn spawn(
+        &self,
+        parent: Option<&Process>,
+        argv: &[&str],
+        envp: &[&str],
+    ) -> Option<&'static Process> {
+        if !self.is_native() {
+            return None;
+        }
+
+        let mut kernel = KernelMemory::lock();
+        let mut memory = ProcessMemory::new(
+            USER_MEMORY_RANGE,
+            MAX_USER_REGIONS,
+            kernel.create_mapping()?,
+        );
+        // user pages can only be mapped into the current mapping, it isn't
+        // switched away from as the kernel memory lock disables interrupts
+        let previous = Mapping::current();
+        unsafe { x86::write_cr3(memory.mapping().root()) };
+        let loaded = self.load(&mut kernel, &mut memory, argv, envp);
+        unsafe { x86::write_cr3(previous.root()) };
+        let Some((sp, thread_pointer)) = loaded else {
+            kernel.destroy_mapping(memory.mapping_mut());
+            return None;
+        };
+        drop(kernel);
+
+        let processes = System::get().processes();
+        let process = match processes.create(parent, memory) {
+            Ok(process) => process,
+            Err(mut memory) => {
+                KernelMemory::lock().destroy_mapping(memory.mapping_mut());
+                return None;
+            }
+        };
+        process.set_entry(self.entry);
+        process.set_thread_pointer(thread_pointer);
+        if process
+            .spawn_thread(main_thread, sp, DEFAULT_PRIORITY)
+            .is_none()
+        {
+            process.exit(-1);
+            processes.exit(process);
+            return None;
+        }
+        Some(process)
+    }
+
+    /// Maps all loadable segments, the stack and the TLS into `memory`, which
+    /// has to be current, and returns the initial stack and thread pointer.
+    fn load(
+        &self,
+        kernel: &mut KernelMemory,
+        memory: &mut ProcessMemory,
+        argv: &[&str],
+        envp: &[&str],
+    ) -> Option<(usize, usize)> {
+        for segment in self.segments() {
+            if segment.type_ != ElfSegmentType::LOAD || segment.memsz == 0 {
+                continue;
+            }
+            let data = self.segment_data(&segment)?;
+            if segment.filesz > segment.memsz {
+                return None;
+            }
+
+            let start = segment.vaddr & !(PAGE_SIZE - 1);
+            let end = segment
+                .vaddr
+                .checked_add(segment.memsz)?
+                .checked_next_multiple_of(PAGE_SIZE)?;
+            memory.allocate(Some(start), end - start)?;
+            let flags = page_flags(segment.flags);
+            kernel.map_user(
+                memory.mapping_mut(),
+                start,
+                end - start,
+                flags,
+                |page, content| {
+                    // the part of the file contents falling into this page, the
+                    // rest stays zeroed
+                    let from = page.max(segment.vaddr);
+                    let to = (page + PAGE_SIZE).min(segment.vaddr + segment.filesz);
+                    if from < to {
+                        content[from - page..to - page]
+                            .copy_from_slice(&data[from - segment.vaddr..to - segment.vaddr]);
+                    }
+                },
+            )?;
+        }
+
+        let stack = USER_MEMORY_RANGE.end - USER_STACK_SIZE;
+        memory.allocate(Some(stack), USER_STACK_SIZE)?;
+        let thread_pointer = match self
+            .segments()
+            .find(|segment| segment.type_ == ElfSegmentType::TLS)
+        {
+            Some(tls) => self.load_tls(kernel, memory, &tls)?,
+            None => 0,
+        };
+        let auxv = [
+            (AuxvType::PHDR, self.phdr_addr()),
+            (AuxvType::PHENT, self.phentsize),
+            (AuxvType::PHNUM, self.phnum),
+            (AuxvType::PAGESZ, PAGE_SIZE),
+            (AuxvType::ENTRY, self.entry),
+            (AuxvType::NULL, 0),
+        ];
+        let top = USER_MEMORY_RANGE.end - PAGE_SIZE;
+        let mut sp = None;
+        let flags = page_flags(ElfSegmentFlags::W);
+        kernel.map_user(
+            memory.mapping_mut(),
+            stack,
+            USER_STACK_SIZE,
+            flags,
+            |page, content| {
+                if page == top {
+                    sp = write_arguments(content, page, argv, envp, &auxv);
+                }
+            },
+        )?;
+        Some((sp?, thread_pointer))
+    }
+
+    /// Maps a TLS block initialized from the `PT_TLS` segment `tls` into
+    /// `memory`, which has to be current, and returns its thread pointer.
+    fn load_tls(
+        &self,
+        kernel: &mut KernelMemory,
+        memory: &mut ProcessMemory,
+        tls: &ElfSegment,
+    ) -> Option<usize> {
+        let data = self.segment_data(tls)?;
+        if tls.filesz > tls.memsz || tls.align > PAGE_SIZE {
+            return None;
+        }
+
+        // the variables end right below the thread pointer, which points to
+        // itself
+        let offset = x86::tls_offset(tls.memsz, tls.align);
+        let size = (offset + x86::TCB_SIZE).next_multiple_of(PAGE_SIZE);
+        let addr = memory.allocate(None, size)?;
+        let thread_pointer = addr + offset;
+        let flags = page_flags(ElfSegmentFlags::W);
+        kernel.map_user(memory.mapping_mut(), addr, size, flags, |page, content| {
+            let from = page.max(addr);
+            let to = (page + PAGE_SIZE).min(addr + tls.filesz);
+            if from < to {
+                content[from - page..to - page].copy_from_slice(&data[from - addr..to - addr]);
+            }
+            if (page..page + PAGE_SIZE).contains(&thread_pointer) {
+                let offset = thread_pointer - page;
+                content[offset..offset + x86::TCB_SIZE]
+                    .copy_from_slice(&thread_pointer.to_ne_bytes());
+            }
+        })?;
+        Some(thread_pointer)
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Runs the main thread of a process created from an ELF image, `sp` being
+/// its initial user stack pointer.
+fn main_thread(sp: usize) {
+    let thread = Scheduler::current();
+    let process = thread.process().unwrap();
+    thread.set_thread_pointer(process.thread_pointer());
+    x86::enter_user(process.entry(), sp);
+}
+
+/// Page table entry flags for a segment, which is always readable.
+fn page_flags(flags: u32) -> usize {
+    let mut page_flags = 0;
+    if flags & ElfSegmentFlags::W != 0 {
+        page_flags |= PageTableEntryFlags::RW;
+    }
+    #[cfg(target_arch = "x86_64")]
+    if flags & ElfSegmentFlags::X == 0 {
+        page_flags |= PageTableEntryFlags::NX;
+    }
+    page_flags
+}
+
+/// Lays out argc, `argv`, `envp` and `auxv` as expected by the System V ABI
+/// in the topmost stack page `content`, which is mapped at `addr`, and
+/// returns the stack pointer.
+///
+/// Returns `None` if they don't fit into the page.
+fn write_arguments(
+    content: &mut [u8],
+    addr: usize,
+    argv: &[&str],
+    envp: &[&str],
+    auxv: &[(usize, usize)],
+) -> Option<usize> {
+    let strings_size: usize = argv.iter().chain(envp).map(|string| string.len() + 1).sum();
+    let words = 1 + argv.len() + 1 + envp.len() + 1 + auxv.len() * 2;
+    let mut string = content.len().checked_sub(strings_size)?;
+    let sp = string.checked_sub(words * mem::size_of::<usize>())? & !0xF;
+
+    let mut word = sp;
+    write_word(content, &mut word, argv.len());
+    for strings in [argv, envp] {
+        for value in strings {
+            write_word(content, &mut word, addr + string);
+            // the page is zeroed, which already terminates the string
+            content[string..string + value.len()].copy_from_slice(value.as_bytes());
+            string += value.len() + 1;
+        }
+        write_word(content, &mut word, 0);
+    }
+    for &(type_, value) in auxv {
+        write_word(content, &mut word, type_);
+        write_word(content, &mut word, value);
+    }
+    Some(addr + sp)
+}
+
+fn write_word(content: &mut [u8], offset: &mut usize, value: usize) {
+    content[*offset..*offset + mem::size_of::<usize>()].copy_from_slice(&value.to_ne_bytes());
+    *offset += mem::size_of::<usize>();
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER_SIZE: usize = mem::size_of::<Elf64Header>();
+    const PROGRAM_HEADER_SIZE: usize = mem::size_of::<Elf64ProgramHeader>();
+
+    /// Little-endian ELF64 executable with the program headers right after
+    /// the file header, given as (type, offset, vaddr, filesz).
+    fn elf(segments: &[(u32, u64, u64, u64)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&ELF_MAGIC);
+        data.extend_from_slice(&[ElfClass::ELF64, ELF_DATA_LSB, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&ElfType::EXEC.to_le_bytes());
+        data.extend_from_slice(&NATIVE_MACHINE.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&0x40_1000u64.to_le_bytes());
+        data.extend_from_slice(&(HEADER_SIZE as u64).to_le_bytes());
+        data.extend_from_slice(&0u64.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        data.extend_from_slice(&(PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+        data.extend_from_slice(&(segments.len() as u16).to_le_bytes());
+        data.extend_from_slice(&[0; 6]);
+        for &(type_, offset, vaddr, filesz) in segments {
+            data.extend_from_slice(&type_.to_le_bytes());
+            data.extend_from_slice(&ElfSegmentFlags::X.to_le_bytes());
+            data.extend_from_slice(&offset.to_le_bytes());
+            data.extend_from_slice(&vaddr.to_le_bytes());
+            data.extend_from_slice(&vaddr.to_le_bytes());
+            data.extend_from_slice(&filesz.to_le_bytes());
+            data.extend_from_slice(&filesz.to_le_bytes());
+            data.extend_from_slice(&(PAGE_SIZE as u64).to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn parse() {
+        let bytes = elf(&[(ElfSegmentType::LOAD, 0, 0x40_0000, 0x200)]);
+        let elf = Elf::parse(&bytes).unwrap();
+        let segment = elf.segments().next().unwrap();
+
+        assert!(elf.is_native());
+        assert_eq!(elf.entry, 0x40_1000);
+        assert_eq!(elf.segments().count(), 1);
+        assert_eq!(segment.type_, ElfSegmentType::LOAD);
+        assert_eq!((segment.offset, segment.vaddr), (0, 0x40_0000));
+        assert_eq!((segment.filesz, segment.memsz), (0x200, 0x200));
+        assert_eq!(segment.align, PAGE_SIZE);
+    }
+
+    #[test]
+    fn invalid_header() {
+        let mut bytes = elf(&[]);
+        bytes[5] = 2;
+        assert!(Elf::parse(&bytes).is_none());
+
+        let mut bytes = elf(&[]);
+        bytes[0] = 0;
+        assert!(Elf::parse(&bytes).is_none());
+
+        assert!(Elf::parse(&elf(&[])[..HEADER_SIZE - 1]).is_none());
+    }
+
+    #[test]
+    fn program_headers_out_of_bounds() {
+        let bytes = elf(&[(ElfSegmentType::LOAD, 0, 0x40_0000, 0x200)]);
+        assert!(Elf::parse(&bytes[..bytes.len() - 1]).is_none());
+
+        let mut bytes = elf(&[(ElfSegmentType::LOAD, 0, 0x40_0000, 0x200)]);
+        bytes[54..56].copy_from_slice(&(PROGRAM_HEADER_SIZE as u16 - 1).to_le_bytes());
+        assert!(Elf::parse(&bytes).is_none());
+    }
+
+    #[test]
+    fn segment_data_out_of_bounds() {
+        let bytes = elf(&[
+            (ElfSegmentType::LOAD, 0, 0x40_0000, 0x20),
+            (ElfSegmentType::LOAD, 0x10, 0x40_0000, 0x1000),
+            (ElfSegmentType::LOAD, u64::MAX, 0x40_0000, 2),
+        ]);
+        let elf = Elf::parse(&bytes).unwrap();
+        let segments: Vec<_> = elf.segments().collect();
+
+        assert_eq!(elf.segment_data(&segments[0]), Some(&bytes[..0x20]));
+        assert!(elf.segment_data(&segments[1]).is_none());
+        assert!(elf.segment_data(&segments[2]).is_none());
+    }
+
+    #[test]
+    fn phdr_addr() {
+        let bytes = elf(&[(ElfSegmentType::LOAD, 0, 0x40_0000, 0x200)]);
+        assert_eq!(
+            Elf::parse(&bytes).unwrap().phdr_addr(),
+            0x40_0000 + HEADER_SIZE
+        );
+
+        let bytes = elf(&[
+            (ElfSegmentType::LOAD, 0, 0x40_0000, 0x200),
+            (ElfSegmentType::PHDR, HEADER_SIZE as u64, 0x50_0000, 0x38),
+        ]);
+        assert_eq!(Elf::parse(&bytes).unwrap().phdr_addr(), 0x50_0000);
+    }
+
+    #[test]
+    fn phdr_addr_overflow() {
+        let bytes = elf(&[
+            (ElfSegmentType::LOAD, 1, 0x40_0000, u64::MAX),
+            (ElfSegmentType::LOAD, 0, u64::MAX, 0x200),
+        ]);
+
+        assert_eq!(Elf::parse(&bytes).unwrap().phdr_addr(), 0);
+    }
+
+    #[test]
+    fn arguments() {
+        let mut content = vec![0; PAGE_SIZE];
+        let addr = 0x7FFF_F000;
+        let sp =
+            write_arguments(&mut content, addr, &["init", "-v"], &["A=1"], &[(6, 4096)]).unwrap();
+        let word = |index: usize| {
+            let offset = sp - addr + index * mem::size_of::<usize>();
+            usize::from_ne_bytes(
+                content[offset..][..mem::size_of::<usize>()]
+                    .try_into()
+                    .unwrap(),
+            )
+        };
+        let string = |ptr: usize| {
+            let start = ptr - addr;
+            let end = start + content[start..].iter().position(|&byte| byte == 0).unwrap();
+            core::str::from_utf8(&content[start..end]).unwrap()
+        };
+
+        assert_eq!(sp % 16, 0);
+        assert_eq!(word(0), 2);
+        assert_eq!((string(word(1)), string(word(2))), ("init", "-v"));
+        assert_eq!(word(3), 0);
+        assert_eq!(string(word(4)), "A=1");
+        assert_eq!(word(5), 0);
+        assert_eq!((word(6), word(7)), (6, 4096));
+        assert!(word(1) >= sp + 8 * mem::size_of::<usize>());
+    }
+
+    #[test]
+    fn arguments_too_large() {
+        let mut content = vec![0; PAGE_SIZE];
+        let argument = "x".repeat(PAGE_SIZE);
+
+        assert!(write_arguments(&mut content, 0x7FFF_F000, &[&argument], &[], &[]).is_none());
+    }
+}
@@ -0,0 +1,366 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use abi::{Error, Handle, Rights};
+
+use crate::{
+    ipc::{Endpoint, Notification, Port},
+    process::{Pid, Resource},
+    sync::RwLock,
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+pub const MAX_HANDLES: usize = 64;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Kernel object a handle refers to
+///
+/// Copies don't count as references, see [`KernelObject::acquire`].
+#[derive(Clone, Copy)]
+pub enum KernelObject {
+    /// Processes are referred to by pid, the handle becomes invalid once the
+    /// process is reaped.
+    Process(Pid),
+    Endpoint(&'static Endpoint),
+    Notification(&'static Notification),
+    Port(&'static Port),
+    /// Resources are plain ranges without references.
+    Resource(Resource),
+}
+
+/// Reference to a kernel object together with the operations it allows
+#[derive(Clone, Copy)]
+pub struct Capability {
+    object: KernelObject,
+    rights: u32,
+}
+
+/// Capabilities of a process, indexed by handle
+///
+/// Only used by threads of the process, which may sleep while another one
+/// changes the table.
+pub struct HandleTable {
+    capabilities: RwLock<[Option<Capability>; MAX_HANDLES]>,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl KernelObject {
+    /// Takes another reference to the object, which keeps it alive.
+    pub fn acquire(&self) {
+        match self {
+            Self::Process(_) | Self::Resource(_) => {}
+            Self::Endpoint(endpoint) => endpoint.acquire(),
+            Self::Notification(notification) => notification.acquire(),
+            Self::Port(port) => port.acquire(),
+        }
+    }
+
+    /// Drops a reference taken by [`KernelObject::acquire`] or on creation.
+    pub fn release(&self) {
+        match self {
+            Self::Process(_) | Self::Resource(_) => {}
+            Self::Endpoint(endpoint) => endpoint.release(),
+            Self::Notification(notification) => notification.release(),
+            Self::Port(port) => port.release(),
+        }
+    }
+}
+
+impl Capability {
+    pub fn new(object: KernelObject, rights: u32) -> Self {
+        Self { object, rights }
+    }
+
+    pub fn object(&self) -> KernelObject {
+        self.object
+    }
+
+    pub fn rights(&self) -> u32 {
+        self.rights
+    }
+
+    /// Whether the capability allows all of `rights`.
+    pub fn allows(&self, rights: u32) -> bool {
+        self.rights & rights == rights
+    }
+
+    /// Returns a copy restricted to `rights`, which must be a subset.
+    pub fn reduce(&self, rights: u32) -> Result<Self, Error> {
+        if rights & !Rights::ALL != 0 || !self.allows(rights) {
+            return Err(Error::AccessDenied);
+        }
+        Ok(Self::new(self.object, rights))
+    }
+}
+
+impl HandleTable {
+    pub const fn new() -> Self {
+        Self {
+            capabilities: RwLock::new([None; MAX_HANDLES]),
+        }
+    }
+
+    /// Adds a capability, and returns its handle. The table takes over the
+    /// reference to the object.
+    pub fn insert(&self, capability: Capability) -> Result<Handle, Error> {
+        let mut capabilities = self.capabilities.write();
+        let index = capabilities
+            .iter()
+            .position(Option::is_none)
+            .ok_or(Error::NoMemory)?;
+        capabilities[index] = Some(capability);
+        Ok(index as Handle + 1)
+    }
+
+    /// Returns the capability of `handle` if it allows all of `rights`,
+    /// together with a reference to the object the caller has to release.
+    pub fn get(&self, handle: Handle, rights: u32) -> Result<Capability, Error> {
+        let capability = self.capabilities.read()[index(handle)?].ok_or(Error::InvalidHandle)?;
+        match capability.allows(rights) {
+            true => {
+                capability.object.acquire();
+                Ok(capability)
+            }
+            false => Err(Error::AccessDenied),
+        }
+    }
+
+    /// Removes `handle`, and releases its reference to the object.
+    pub fn close(&self, handle: Handle) -> Result<(), Error> {
+        let capability = self.capabilities.write()[index(handle)?]
+            .take()
+            .ok_or(Error::InvalidHandle)?;
+        capability.object.release();
+        Ok(())
+    }
+
+    /// Creates a new handle to the object of `handle` with `rights`.
+    pub fn duplicate(&self, handle: Handle, rights: u32) -> Result<Handle, Error> {
+        let capability = self.get(handle, Rights::DUPLICATE)?;
+        capability
+            .reduce(rights)
+            .and_then(|capability| self.insert(capability))
+            .inspect_err(|_| capability.object.release())
+    }
+
+    /// Restricts `handle` to `rights`.
+    pub fn reduce(&self, handle: Handle, rights: u32) -> Result<(), Error> {
+        let mut capabilities = self.capabilities.write();
+        let entry = &mut capabilities[index(handle)?];
+        let capability = entry.ok_or(Error::InvalidHandle)?.reduce(rights)?;
+        *entry = Some(capability);
+        Ok(())
+    }
+
+    /// Removes `handle` to send its capability to another process, which
+    /// requires [`Rights::TRANSFER`]. The reference to the object moves with
+    /// the capability.
+    pub fn take(&self, handle: Handle) -> Result<Capability, Error> {
+        let mut capabilities = self.capabilities.write();
+        let entry = &mut capabilities[index(handle)?];
+        match entry.ok_or(Error::InvalidHandle)?.allows(Rights::TRANSFER) {
+            true => Ok(entry.take().unwrap()),
+            false => Err(Error::AccessDenied),
+        }
+    }
+
+    /// Puts a capability taken from `handle` back, under another handle if
+    /// `handle` was reused in the meantime, and releases it if there is no
+    /// free entry left.
+    pub fn restore(&self, handle: Handle, capability: Capability) {
+        let mut capabilities = self.capabilities.write();
+        let index = index(handle)
+            .ok()
+            .filter(|&index| capabilities[index].is_none())
+            .or_else(|| capabilities.iter().position(Option::is_none));
+        match index {
+            Some(index) => capabilities[index] = Some(capability),
+            None => {
+                drop(capabilities);
+                capability.object.release();
+            }
+        }
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+impl Drop for HandleTable {
+    fn drop(&mut self) {
+        for capability in self.capabilities.get_mut().iter().flatten() {
+            capability.object.release();
+        }
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+fn index(handle: Handle) -> Result<usize, Error> {
+    match (handle as usize).wrapping_sub(1) {
+        index if index < MAX_HANDLES => Ok(index),
+        _ => Err(Error::InvalidHandle),
+    }
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pid(capability: Capability) -> Pid {
+        match capability.object() {
+            KernelObject::Process(pid) => pid,
+            _ => panic!("expected a process"),
+        }
+    }
+
+    #[test]
+    fn reduce() {
+        let table = HandleTable::new();
+        let handle = table
+            .insert(Capability::new(KernelObject::Process(7), Rights::ALL))
+            .unwrap();
+
+        table.reduce(handle, Rights::READ | Rights::WRITE).unwrap();
+        assert_eq!(
+            table.get(handle, 0).unwrap().rights(),
+            Rights::READ | Rights::WRITE
+        );
+        assert_eq!(table.get(handle, Rights::WRITE).map(pid), Ok(7));
+        assert_eq!(
+            table.get(handle, Rights::TRANSFER).err(),
+            Some(Error::AccessDenied)
+        );
+    }
+
+    #[test]
+    fn reduce_never_widens() {
+        let table = HandleTable::new();
+        let handle = table
+            .insert(Capability::new(KernelObject::Process(7), Rights::READ))
+            .unwrap();
+
+        assert_eq!(
+            table.reduce(handle, Rights::READ | Rights::WRITE),
+            Err(Error::AccessDenied)
+        );
+        assert_eq!(table.reduce(handle, 1 << 31), Err(Error::AccessDenied));
+        assert_eq!(table.get(handle, 0).unwrap().rights(), Rights::READ);
+        assert_eq!(table.reduce(0, 0), Err(Error::InvalidHandle));
+        assert_eq!(table.reduce(handle + 1, 0), Err(Error::InvalidHandle));
+    }
+
+    #[test]
+    fn duplicate() {
+        let table = HandleTable::new();
+        let handle = table
+            .insert(Capability::new(
+                KernelObject::Process(7),
+                Rights::READ | Rights::DUPLICATE,
+            ))
+            .unwrap();
+
+        let copy = table.duplicate(handle, Rights::READ).unwrap();
+        assert_ne!(copy, handle);
+        assert_eq!(table.get(copy, 0).unwrap().rights(), Rights::READ);
+        assert_eq!(
+            table.duplicate(copy, Rights::READ),
+            Err(Error::AccessDenied)
+        );
+        assert_eq!(
+            table.duplicate(handle, Rights::WRITE),
+            Err(Error::AccessDenied)
+        );
+    }
+
+    #[test]
+    fn take_requires_transfer() {
+        let table = HandleTable::new();
+        let handle = table
+            .insert(Capability::new(KernelObject::Process(7), Rights::READ))
+            .unwrap();
+
+        assert_eq!(table.take(handle).err(), Some(Error::AccessDenied));
+        assert!(table.get(handle, 0).is_ok());
+    }
+
+    #[test]
+    fn take_and_restore() {
+        let table = HandleTable::new();
+        let handle = table
+            .insert(Capability::new(KernelObject::Process(7), Rights::TRANSFER))
+            .unwrap();
+
+        let capability = table.take(handle).unwrap();
+        assert_eq!(table.get(handle, 0).err(), Some(Error::InvalidHandle));
+        table.restore(handle, capability);
+        assert_eq!(table.get(handle, Rights::TRANSFER).map(pid), Ok(7));
+    }
+
+    #[test]
+    fn restore_reused_handle() {
+        let table = HandleTable::new();
+        let handle = table
+            .insert(Capability::new(KernelObject::Process(7), Rights::TRANSFER))
+            .unwrap();
+
+        let capability = table.take(handle).unwrap();
+        let reused = table
+            .insert(Capability::new(KernelObject::Process(8), Rights::READ))
+            .unwrap();
+        assert_eq!(reused, handle);
+        table.restore(handle, capability);
+        assert_eq!(table.get(reused, 0).map(pid), Ok(8));
+        assert_eq!(table.get(handle + 1, 0).map(pid), Ok(7));
+    }
+
+    #[test]
+    fn full_table() {
+        let table = HandleTable::new();
+        for _ in 0..MAX_HANDLES {
+            table
+                .insert(Capability::new(KernelObject::Process(7), Rights::TRANSFER))
+                .unwrap();
+        }
+
+        let capability = Capability::new(KernelObject::Process(8), Rights::READ);
+        assert_eq!(table.insert(capability).err(), Some(Error::NoMemory));
+        let capability = table.take(1).unwrap();
+        table
+            .insert(Capability::new(KernelObject::Process(8), Rights::READ))
+            .unwrap();
+        table.restore(1, capability);
+        assert_eq!(table.get(1, 0).map(pid), Ok(8));
+    }
+}
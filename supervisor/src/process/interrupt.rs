@@ -11,3 +11,122 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use abi::Error;
+
+use crate::{
+    ipc::Notification,
+    process::Pid,
+    sync::SpinLock,
+    x86::{
+        register_interrupt_handler, InterruptFrame, IoApic, LocalApic, PerCpu, IRQ_VECTOR_BASE,
+        MAX_IRQS,
+    },
+};
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Notification bits set by a delegated interrupt
+#[derive(Clone, Copy)]
+struct IrqBinding {
+    /// Process which bound the interrupt, the only one which may handle it.
+    owner: Pid,
+    notification: &'static Notification,
+    bits: usize,
+}
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+/// Delegated interrupts, each binding holds a reference to its notification.
+static IRQ_BINDINGS: SpinLock<[Option<IrqBinding>; MAX_IRQS]> = SpinLock::new([None; MAX_IRQS]);
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Delegates `irq` to the process `owner`, which sets `bits` of
+/// `notification` whenever it fires. The interrupt is masked from then on
+/// until [`ack_irq`].
+pub fn bind_irq(
+    owner: Pid,
+    irq: u32,
+    notification: &'static Notification,
+    bits: usize,
+) -> Result<(), Error> {
+    if !IoApic::is_valid(irq) {
+        return Err(Error::InvalidArgument);
+    }
+    let mut bindings = IRQ_BINDINGS.lock();
+    let binding = &mut bindings[irq as usize];
+    if binding.is_some() {
+        return Err(Error::Busy);
+    }
+    notification.acquire();
+    *binding = Some(IrqBinding {
+        owner,
+        notification,
+        bits,
+    });
+    // the handler stays registered, an interrupt may still be pending after
+    // the binding is gone
+    register_interrupt_handler(IRQ_VECTOR_BASE + irq as u8, handle_irq);
+    IoApic::route(irq, PerCpu::current().apic_id());
+    IoApic::unmask(irq);
+    Ok(())
+}
+
+/// Masks `irq` again and drops its binding, which has to belong to `owner`.
+pub fn unbind_irq(owner: Pid, irq: u32) -> Result<(), Error> {
+    let binding = {
+        let mut bindings = IRQ_BINDINGS.lock();
+        let binding = bindings.get_mut(irq as usize).ok_or(Error::NotFound)?;
+        check_owner(binding, owner)?;
+        binding.take().unwrap()
+    };
+    IoApic::mask(irq);
+    binding.notification.release();
+    Ok(())
+}
+
+/// Drops all bindings of `owner`, once the process exits.
+pub fn unbind_irqs(owner: Pid) {
+    for irq in 0..MAX_IRQS as u32 {
+        // only fails for interrupts bound by other processes
+        let _ = unbind_irq(owner, irq);
+    }
+}
+
+/// Unmasks `irq` once `owner`, which bound it, handled it.
+pub fn ack_irq(owner: Pid, irq: u32) -> Result<(), Error> {
+    let bindings = IRQ_BINDINGS.lock();
+    check_owner(bindings.get(irq as usize).ok_or(Error::NotFound)?, owner)?;
+    IoApic::unmask(irq);
+    Ok(())
+}
+
+/// Checks that `binding` exists and belongs to `owner`.
+fn check_owner(binding: &Option<IrqBinding>, owner: Pid) -> Result<(), Error> {
+    match binding {
+        Some(binding) if binding.owner == owner => Ok(()),
+        Some(_) => Err(Error::AccessDenied),
+        None => Err(Error::NotFound),
+    }
+}
+
+fn handle_irq(frame: &mut InterruptFrame) {
+    let irq = frame.vector - IRQ_VECTOR_BASE as usize;
+    // masked first, level-triggered interrupts would fire again right away
+    IoApic::mask(irq as u32);
+    if let Some(binding) = IRQ_BINDINGS.lock()[irq] {
+        binding.notification.signal(binding.bits);
+    }
+    LocalApic::eoi();
+}
@@ -16,12 +16,27 @@
 // Imports
 //==================================================================================================
 
+mod class;
+mod elf;
+mod handle;
 mod interrupt;
+#[allow(clippy::module_inception)]
 mod process;
+mod resource;
+mod scheduler;
+mod signal;
 mod system;
+mod table;
 mod thread;
 
-use interrupt::*;
+pub use class::*;
+pub use elf::*;
+pub use handle::*;
+pub use interrupt::*;
 pub use process::*;
+pub use resource::*;
+pub use scheduler::*;
+pub use signal::*;
 pub use system::*;
+pub use table::*;
 pub use thread::*;
@@ -16,18 +16,263 @@
 // Imports
 //==================================================================================================
 
-use crate::memory::ProcessMemory;
+use core::{
+    cell::Cell,
+    slice,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use abi::Error;
+
+use intrusive_collections::{LinkedList, UnsafeRef};
+
+use crate::{
+    ipc::PortBinding,
+    memory::{KernelMemory, PageTableEntryFlags, ProcessMemory},
+    process::{unbind_irqs, HandleTable, ProcessSignals, ProcessThreadAdapter, Scheduler, Thread},
+    sync::{SpinLock, SpinLockGuard},
+    x86::IO_BITMAP_SIZE,
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Process every orphan is re-parented to, and which must never exit.
+pub const INIT_PID: Pid = 1;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+/// Source of I/O permission bitmap generations, which are unique across
+/// processes since a freed bitmap may be reused at the same address.
+static IO_BITMAP_GENERATION: AtomicUsize = AtomicUsize::new(1);
 
 //==================================================================================================
 // Structures
 //==================================================================================================
 
+/// Process identifier, 0 is never used so that it can stand for "no process".
+pub type Pid = u32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ProcessState {
+    /// Has at least one thread.
+    Alive,
+    /// All threads exited, waiting to be reaped by the parent.
+    Zombie,
+}
+
 pub struct Process {
-    memory: ProcessMemory,
+    pid: Pid,
+    /// Pid of the parent, 0 if there is none. Protected by the process table
+    /// lock, like `state` and `exit_code`.
+    parent: Cell<Pid>,
+    state: Cell<ProcessState>,
+    exit_code: Cell<i32>,
+    /// Set once the process was asked to exit.
+    exiting: AtomicBool,
+    /// User address the main thread starts at.
+    entry: Cell<usize>,
+    /// User thread pointer of the main thread, 0 if it has no TLS.
+    thread_pointer: Cell<usize>,
+    /// Physical address of the top-level page table, loaded on every switch
+    /// to one of the threads.
+    page_table: usize,
+    memory: SpinLock<ProcessMemory>,
+    threads: SpinLock<LinkedList<ProcessThreadAdapter>>,
+    handles: HandleTable,
+    /// Port notified on exit, protected by the process table lock.
+    exit_binding: Cell<Option<PortBinding>>,
+    /// Kernel address of the I/O permission bitmap, 0 until ports are enabled,
+    /// and its generation.
+    io_bitmap: SpinLock<(usize, usize)>,
+    signals: SpinLock<ProcessSignals>,
 }
 
 //==================================================================================================
 // Implementations
 //==================================================================================================
 
-impl Process {}
+impl Process {
+    pub fn new(pid: Pid, parent: Pid, memory: ProcessMemory) -> Self {
+        Self {
+            pid,
+            parent: Cell::new(parent),
+            state: Cell::new(ProcessState::Alive),
+            exit_code: Cell::new(0),
+            exiting: AtomicBool::new(false),
+            entry: Cell::new(0),
+            thread_pointer: Cell::new(0),
+            page_table: memory.mapping().root(),
+            memory: SpinLock::new(memory),
+            threads: SpinLock::new(LinkedList::new(ProcessThreadAdapter::NEW)),
+            handles: HandleTable::new(),
+            exit_binding: Cell::new(None),
+            io_bitmap: SpinLock::new((0, 0)),
+            signals: SpinLock::new(ProcessSignals::new()),
+        }
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    pub fn parent(&self) -> Pid {
+        self.parent.get()
+    }
+
+    pub fn set_parent(&self, parent: Pid) {
+        self.parent.set(parent);
+    }
+
+    pub fn state(&self) -> ProcessState {
+        self.state.get()
+    }
+
+    pub fn set_state(&self, state: ProcessState) {
+        self.state.set(state);
+    }
+
+    /// Exit code, only meaningful once the process is a zombie.
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code.get()
+    }
+
+    pub fn entry(&self) -> usize {
+        self.entry.get()
+    }
+
+    pub fn set_entry(&self, entry: usize) {
+        self.entry.set(entry);
+    }
+
+    pub fn thread_pointer(&self) -> usize {
+        self.thread_pointer.get()
+    }
+
+    pub fn set_thread_pointer(&self, thread_pointer: usize) {
+        self.thread_pointer.set(thread_pointer);
+    }
+
+    pub fn page_table(&self) -> usize {
+        self.page_table
+    }
+
+    pub fn memory(&self) -> SpinLockGuard<'_, ProcessMemory> {
+        self.memory.lock()
+    }
+
+    pub fn handles(&self) -> &HandleTable {
+        &self.handles
+    }
+
+    pub fn signals(&self) -> SpinLockGuard<'_, ProcessSignals> {
+        self.signals.lock()
+    }
+
+    /// Port binding for the exit of the process, only to be used with the
+    /// process table locked.
+    pub fn exit_binding(&self) -> &Cell<Option<PortBinding>> {
+        &self.exit_binding
+    }
+
+    /// Kernel address and generation of the I/O permission bitmap, to be
+    /// passed to [`PerCpu::load_io_bitmap`](crate::x86::PerCpu::load_io_bitmap).
+    pub fn io_bitmap(&self) -> (usize, usize) {
+        *self.io_bitmap.lock()
+    }
+
+    /// Allows user mode access to `count` ports from `base`. Threads running
+    /// on other CPUs only get access once they are switched to again.
+    pub fn enable_io_ports(&self, base: usize, count: usize) -> Result<(), Error> {
+        let mut io_bitmap = self.io_bitmap.lock();
+        if io_bitmap.0 == 0 {
+            let addr = KernelMemory::lock()
+                .allocate(IO_BITMAP_SIZE, PageTableEntryFlags::RW)
+                .ok_or(Error::NoMemory)?;
+            unsafe { slice::from_raw_parts_mut(addr as *mut u8, IO_BITMAP_SIZE) }.fill(0xFF);
+            io_bitmap.0 = addr;
+        }
+        let bitmap = unsafe { slice::from_raw_parts_mut(io_bitmap.0 as *mut u8, IO_BITMAP_SIZE) };
+        for port in base..base + count {
+            bitmap[port / 8] &= !(1 << (port % 8));
+        }
+        io_bitmap.1 = IO_BITMAP_GENERATION.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Creates a thread in this process which calls `entry(arg)`.
+    ///
+    /// Returns `None` if there is no memory or no thread slot left, or if the
+    /// process is exiting.
+    pub fn spawn_thread(
+        &'static self,
+        entry: fn(usize),
+        arg: usize,
+        priority: u8,
+    ) -> Option<&'static Thread> {
+        if self.is_exiting() {
+            return None;
+        }
+        Scheduler::spawn(Some(self), entry, arg, priority)
+    }
+
+    pub fn add_thread(&self, thread: &'static Thread) {
+        self.threads
+            .lock()
+            .push_back(unsafe { UnsafeRef::from_raw(thread) });
+    }
+
+    /// Removes an exited thread, and returns whether it was the last one.
+    pub fn remove_thread(&self, thread: &Thread) -> bool {
+        let mut threads = self.threads.lock();
+        unsafe { threads.cursor_mut_from_ptr(thread) }.remove();
+        threads.is_empty()
+    }
+
+    /// Requests the whole process to exit with `code`, only the first request
+    /// decides the exit code.
+    ///
+    /// The process becomes a zombie once its last thread exited, the other
+    /// threads terminate the next time they would return to user mode. Those
+    /// which are blocked are woken, their waits fail with
+    /// [`Error::Cancelled`]. Delegated interrupts are unbound right away.
+    pub fn exit(&self, code: i32) {
+        if !self.exiting.swap(true, Ordering::AcqRel) {
+            self.exit_code.set(code);
+            unbind_irqs(self.pid);
+            for thread in self.threads.lock().iter() {
+                // threads stay allocated while they are in the list
+                let thread = unsafe { &*(thread as *const Thread) };
+                thread.ipc().interrupt(thread);
+            }
+        }
+    }
+
+    pub fn is_exiting(&self) -> bool {
+        self.exiting.load(Ordering::Acquire)
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+unsafe impl Sync for Process {}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        // threads which were still running after the exit may have bound
+        // interrupts again
+        unbind_irqs(self.pid);
+        let mut kernel = KernelMemory::lock();
+        // no thread is left which could have the mapping loaded
+        kernel.destroy_mapping(self.memory.get_mut().mapping_mut());
+        let (io_bitmap, _) = *self.io_bitmap.get_mut();
+        if io_bitmap != 0 {
+            kernel.deallocate(io_bitmap, IO_BITMAP_SIZE);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use abi::{Error, ResourceKind};
+
+use crate::x86::{IO_BITMAP_SIZE, MAX_IRQS};
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Range of hardware of one [`ResourceKind`] a process may access
+#[derive(Clone, Copy)]
+pub struct Resource {
+    kind: usize,
+    base: usize,
+    size: usize,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Resource {
+    pub const fn new(kind: usize, base: usize, size: usize) -> Self {
+        Self { kind, base, size }
+    }
+
+    /// Resource covering all hardware of `kind`.
+    pub fn root(kind: usize) -> Self {
+        let size = match kind {
+            ResourceKind::IO_PORTS => IO_BITMAP_SIZE * 8,
+            ResourceKind::IRQ => MAX_IRQS,
+            _ => usize::MAX,
+        };
+        Self::new(kind, 0, size)
+    }
+
+    /// Whether the resource is of `kind` and covers `size` units from `base`.
+    pub fn covers(&self, kind: usize, base: usize, size: usize) -> bool {
+        let Some(offset) = base.checked_sub(self.base) else {
+            return false;
+        };
+        self.kind == kind && offset <= self.size && size <= self.size - offset
+    }
+
+    /// Creates a resource for part of this one.
+    pub fn subrange(&self, base: usize, size: usize) -> Result<Self, Error> {
+        match self.covers(self.kind, base, size) {
+            true => Ok(Self::new(self.kind, base, size)),
+            false => Err(Error::InvalidArgument),
+        }
+    }
+}
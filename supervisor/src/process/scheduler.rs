@@ -0,0 +1,499 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    cell::Cell,
+    mem,
+    ptr::{self, NonNull},
+    sync::atomic::{self, AtomicBool, Ordering},
+};
+
+use crate::{
+    ipc,
+    memory::KernelMemory,
+    process::{Process, SchedulingClasses, SchedulingPolicy, System, Thread, ThreadState},
+    sync::{SpinLock, SpinLockGuard},
+    time::{now, Timer},
+    x86::{
+        self, register_interrupt_handler, InterruptFrame, LocalApic, LocalApicIcr, PerCpu,
+        RESCHEDULE_VECTOR,
+    },
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Time a thread may run before it is preempted, in nanoseconds.
+const TIME_SLICE: u64 = 10_000_000;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Threads which are ready to run on a single CPU
+///
+/// The lock is held across context switches, it is taken by the thread
+/// switching away and released by the thread switched to. It also protects
+/// the state of all threads which last ran on this CPU.
+pub struct RunQueue {
+    classes: SpinLock<SchedulingClasses>,
+    /// Thread which exited and whose stack is still in use until the switch
+    /// away from it is complete.
+    exited: Cell<Option<NonNull<Thread>>>,
+    /// Thread which may no longer run on this CPU, and is moved to another one
+    /// once the switch away from it is complete.
+    migrating: Cell<Option<NonNull<Thread>>>,
+    need_resched: AtomicBool,
+    preempt_timer: Timer,
+}
+
+/// Preemptive scheduler with pluggable scheduling classes
+///
+/// Every CPU schedules its own threads, threads move to another CPU when
+/// their affinity demands it or when an idle CPU steals them.
+pub struct Scheduler;
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl RunQueue {
+    pub const fn new() -> Self {
+        Self {
+            classes: SpinLock::new(SchedulingClasses::new()),
+            exited: Cell::new(None),
+            migrating: Cell::new(None),
+            need_resched: AtomicBool::new(false),
+            preempt_timer: Timer::new(preempt, 0),
+        }
+    }
+
+    /// Returns and clears whether the current thread should be preempted.
+    pub fn take_need_resched(&self) -> bool {
+        self.need_resched.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Scheduler {
+    /// Turns the calling flow of execution into the idle thread of the
+    /// current CPU and starts preempting threads.
+    ///
+    /// Must be called once on every CPU after [`PerCpu::init`] and the timers.
+    pub fn init() {
+        let per_cpu = PerCpu::current();
+        let idle: &Thread = System::get()
+            .threads()
+            .allocate(Thread::bootstrap(per_cpu.id()));
+        per_cpu.set_idle_thread(Some(idle.into()));
+        per_cpu.set_current_thread(Some(idle.into()));
+
+        per_cpu
+            .run_queue()
+            .preempt_timer
+            .arm(now() + TIME_SLICE, TIME_SLICE);
+        register_interrupt_handler(RESCHEDULE_VECTOR, reschedule_interrupt);
+    }
+
+    /// Runs the idle loop of the current CPU, which steals threads from other
+    /// CPUs and halts whenever there is nothing to run.
+    pub fn idle() -> ! {
+        loop {
+            x86::disable_interrupts();
+            let per_cpu = PerCpu::current();
+            if per_cpu.run_queue().classes.lock().is_empty() {
+                match Self::steal(per_cpu) {
+                    Some(thread) => Self::enqueue(per_cpu, thread),
+                    None => x86::wait_for_interrupt(),
+                }
+            }
+            x86::enable_interrupts();
+            Self::schedule();
+        }
+    }
+
+    /// Creates a thread which calls `entry(arg)`, and runs it on the current
+    /// CPU.
+    ///
+    /// Returns `None` if there is no memory or no thread slot left.
+    pub fn spawn(
+        process: Option<&'static Process>,
+        entry: fn(usize),
+        arg: usize,
+        priority: u8,
+    ) -> Option<&'static Thread> {
+        let thread = Thread::new(process, entry, arg, priority)?;
+        let thread = System::get().threads().try_allocate(thread)?;
+        thread.init_timeout();
+        let thread: &'static Thread = thread;
+        if let Some(process) = process {
+            process.add_thread(thread);
+        }
+        Self::preempt_if_needed(|| {
+            thread.set_cpu(PerCpu::current().id());
+            Self::enqueue(Self::select_cpu(thread), thread);
+        });
+        Some(thread)
+    }
+
+    /// Returns the thread running on the current CPU.
+    pub fn current() -> &'static Thread {
+        unsafe { PerCpu::current().current_thread().unwrap().as_ref() }
+    }
+
+    /// Gives up the CPU in favor of other ready threads.
+    pub fn yield_now() {
+        Self::schedule();
+    }
+
+    /// Marks the current thread as blocked, it keeps running until the next
+    /// call to [`Scheduler::schedule`], and is not picked again until it is
+    /// passed to [`Scheduler::wake`].
+    ///
+    /// Wakeups which happen in between cancel the block, therefore the
+    /// condition to wait for has to be checked after this call and before
+    /// scheduling.
+    pub fn prepare_block() {
+        x86::without_interrupts(|| {
+            let _classes = PerCpu::current().run_queue().classes.lock();
+            Self::current().set_state(ThreadState::Blocked);
+        });
+    }
+
+    /// Undoes [`Scheduler::prepare_block`] if the thread hasn't been woken in
+    /// the meantime.
+    pub fn cancel_block() {
+        x86::without_interrupts(|| {
+            let _classes = PerCpu::current().run_queue().classes.lock();
+            let current = Self::current();
+            if current.state() == ThreadState::Blocked {
+                current.set_state(ThreadState::Running);
+            }
+        });
+    }
+
+    /// Switches away from the current thread after
+    /// [`Scheduler::prepare_block`] like [`Scheduler::schedule`], unless its
+    /// process is exiting, and returns whether it blocked.
+    ///
+    /// [`Process::exit`] wakes all threads of the process, but would miss a
+    /// thread which only prepares to block afterwards.
+    pub fn block_unless_exiting() -> bool {
+        if Self::current().is_exiting() {
+            Self::cancel_block();
+            return false;
+        }
+        Self::schedule();
+        true
+    }
+
+    /// Makes a blocked thread ready again, does nothing if it isn't blocked.
+    pub fn wake(thread: &'static Thread) {
+        Self::preempt_if_needed(|| {
+            let (per_cpu, classes) = Self::lock_thread(thread);
+            if thread.state() != ThreadState::Blocked {
+                return;
+            }
+            thread.set_state(ThreadState::Ready);
+            // a thread which didn't get to switch away yet keeps running
+            if per_cpu.current_thread() == Some(thread.into()) {
+                return;
+            }
+            drop(classes);
+            Self::enqueue(Self::select_cpu(thread), thread);
+        });
+    }
+
+    /// Changes the scheduling policy, priority, absolute deadline and affinity
+    /// of a thread, the affinity has to allow at least one CPU.
+    pub fn set_scheduling(
+        thread: &'static Thread,
+        policy: SchedulingPolicy,
+        priority: u8,
+        deadline: u64,
+        affinity: u32,
+    ) {
+        Self::modify(thread, |thread| {
+            thread.set_policy(policy);
+            thread.set_priority(priority);
+            thread.set_deadline(deadline);
+            thread.set_affinity(affinity);
+        });
+    }
+
+    /// Changes the scheduling parameters of a thread through `f`, moving it
+    /// within or between run queues as necessary.
+    fn modify(thread: &'static Thread, f: impl FnOnce(&Thread)) {
+        Self::preempt_if_needed(|| {
+            let (per_cpu, mut classes) = Self::lock_thread(thread);
+            let queued = thread.state() == ThreadState::Ready && thread.is_queued();
+            if queued {
+                classes.remove(thread);
+            }
+            f(thread);
+            assert!(
+                (0..PerCpu::count()).any(|id| thread.can_run_on(id)),
+                "Thread may not run on any CPU"
+            );
+
+            if queued {
+                drop(classes);
+                Self::enqueue(Self::select_cpu(thread), thread);
+            } else if per_cpu.current_thread() == Some(thread.into()) {
+                // reevaluate the running thread, which also moves it away if
+                // it may no longer run on this CPU
+                request_resched(per_cpu);
+            }
+        });
+    }
+
+    /// Terminates the current thread, its process becomes a zombie once the
+    /// last thread exited.
+    pub fn exit() -> ! {
+        let current = Self::current();
+        ipc::abandon_caller();
+        x86::disable_interrupts();
+        if let Some(process) = current.process() {
+            // the mapping is destroyed as soon as the last thread is gone, and
+            // isn't loaded again without interrupts
+            unsafe { x86::write_cr3(KernelMemory::root()) };
+            if process.remove_thread(current) {
+                System::get().processes().exit(process);
+            }
+        }
+
+        current.set_state(ThreadState::Exited);
+        Self::schedule();
+        unreachable!("Exited thread was scheduled again");
+    }
+
+    /// Switches to the next ready thread of the current CPU, the current thread
+    /// is put back into the run queue unless it blocked or exited.
+    pub fn schedule() {
+        let interrupts_enabled = x86::interrupts_enabled();
+        x86::disable_interrupts();
+
+        let per_cpu = PerCpu::current();
+        let idle = unsafe { per_cpu.idle_thread().unwrap().as_ref() };
+        let mut classes = per_cpu.run_queue().classes.lock();
+        Self::put_back(per_cpu, &mut classes);
+        let next = classes.pick_next().unwrap_or(idle);
+        Self::switch_to(per_cpu, classes, next);
+
+        if interrupts_enabled {
+            x86::enable_interrupts();
+        }
+    }
+
+    /// Switches directly to the blocked thread `next` without considering the
+    /// run queue, like [`Scheduler::schedule`] does otherwise.
+    ///
+    /// Falls back to waking `next` and scheduling if it last ran on another
+    /// CPU, or may not run on this one.
+    pub fn handoff(next: &'static Thread) {
+        let interrupts_enabled = x86::interrupts_enabled();
+        x86::disable_interrupts();
+
+        let per_cpu = PerCpu::current();
+        let mut classes = per_cpu.run_queue().classes.lock();
+        if next.state() == ThreadState::Blocked
+            && next.cpu() == per_cpu.id()
+            && next.can_run_on(per_cpu.id())
+        {
+            Self::put_back(per_cpu, &mut classes);
+            Self::switch_to(per_cpu, classes, next);
+        } else {
+            drop(classes);
+            Self::wake(next);
+            Self::schedule();
+        }
+
+        if interrupts_enabled {
+            x86::enable_interrupts();
+        }
+    }
+
+    /// Completes a context switch, must be called first thing by every thread
+    /// which has been switched to.
+    pub fn finish_switch() {
+        let run_queue = PerCpu::current().run_queue();
+        unsafe { run_queue.classes.force_unlock() };
+        if let Some(mut thread) = run_queue.exited.take() {
+            System::get()
+                .threads()
+                .deallocate(unsafe { thread.as_mut() });
+        }
+        if let Some(thread) = run_queue.migrating.take() {
+            let thread = unsafe { thread.as_ref() };
+            Self::enqueue(Self::select_cpu(thread), thread);
+        }
+    }
+
+    /// Puts the current thread back into the run queue before switching away,
+    /// unless it blocked or exited.
+    fn put_back(per_cpu: &PerCpu, classes: &mut SchedulingClasses) {
+        let run_queue = per_cpu.run_queue();
+        let idle = unsafe { per_cpu.idle_thread().unwrap().as_ref() };
+        let current = Self::current();
+
+        run_queue.need_resched.store(false, Ordering::Relaxed);
+        match current.state() {
+            ThreadState::Running | ThreadState::Ready if ptr::eq(current, idle) => {
+                current.set_state(ThreadState::Ready);
+            }
+            ThreadState::Running | ThreadState::Ready => {
+                current.set_state(ThreadState::Ready);
+                if current.can_run_on(per_cpu.id()) {
+                    classes.enqueue(current);
+                } else {
+                    run_queue.migrating.set(Some(current.into()));
+                }
+            }
+            ThreadState::Blocked => {}
+            ThreadState::Exited => run_queue.exited.set(Some(current.into())),
+        }
+    }
+
+    /// Switches from the current thread to `next`, the run queue lock is
+    /// released once the switch is complete.
+    fn switch_to(
+        per_cpu: &PerCpu,
+        classes: SpinLockGuard<'_, SchedulingClasses>,
+        next: &'static Thread,
+    ) {
+        let current = Self::current();
+        if ptr::eq(next, current) {
+            current.set_state(ThreadState::Running);
+        } else {
+            // released by the thread switched to in finish_switch
+            mem::forget(classes);
+            next.set_cpu(per_cpu.id());
+            unsafe { current.switch(next) };
+            Self::finish_switch();
+        }
+    }
+
+    /// Locks the run queue of the CPU the thread last ran on.
+    fn lock_thread(
+        thread: &Thread,
+    ) -> (&'static PerCpu, SpinLockGuard<'static, SchedulingClasses>) {
+        loop {
+            let per_cpu = PerCpu::get(thread.cpu()).unwrap();
+            let classes = per_cpu.run_queue().classes.lock();
+            // the thread might have been moved while waiting for the lock
+            if thread.cpu() == per_cpu.id() {
+                return (per_cpu, classes);
+            }
+        }
+    }
+
+    /// Adds a ready thread to the run queue of a CPU, and requests preemption
+    /// of the thread running there if the new one takes precedence.
+    fn enqueue(per_cpu: &PerCpu, thread: &'static Thread) {
+        let mut classes = per_cpu.run_queue().classes.lock();
+        thread.set_cpu(per_cpu.id());
+        classes.enqueue(thread);
+
+        // nothing is preempted before the scheduler runs on the CPU
+        let preempt = per_cpu.current_thread().is_some_and(|current| {
+            Some(current) == per_cpu.idle_thread()
+                || classes.should_preempt(unsafe { current.as_ref() }, thread)
+        });
+        if preempt {
+            request_resched(per_cpu);
+        }
+    }
+
+    /// Returns the CPU a ready thread should run on, preferring the one it
+    /// last ran on.
+    fn select_cpu(thread: &Thread) -> &'static PerCpu {
+        let id = if thread.can_run_on(thread.cpu()) {
+            thread.cpu()
+        } else {
+            (0..PerCpu::count())
+                .find(|&id| thread.can_run_on(id))
+                .expect("Thread may not run on any CPU")
+        };
+        PerCpu::get(id).unwrap()
+    }
+
+    /// Takes a ready thread from another CPU which may run on this one.
+    ///
+    /// Busy run queues are skipped instead of waiting for them.
+    fn steal(per_cpu: &PerCpu) -> Option<&'static Thread> {
+        (0..PerCpu::count())
+            .filter(|&id| id != per_cpu.id())
+            .filter_map(PerCpu::get)
+            .find_map(|victim| victim.run_queue().classes.try_lock()?.steal(per_cpu.id()))
+    }
+
+    /// Runs `f` with interrupts disabled, and switches threads afterwards if
+    /// it requested preemption of the current thread.
+    fn preempt_if_needed(f: impl FnOnce()) {
+        let interrupts_enabled = x86::interrupts_enabled();
+        x86::without_interrupts(f);
+        // in interrupt context the switch happens on return from the handler
+        if interrupts_enabled && PerCpu::current().run_queue().take_need_resched() {
+            Self::schedule();
+        }
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+unsafe impl Send for RunQueue {}
+
+unsafe impl Sync for RunQueue {}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Requests preemption of the thread running on a CPU, other CPUs are
+/// interrupted to notice it.
+fn request_resched(per_cpu: &PerCpu) {
+    per_cpu
+        .run_queue()
+        .need_resched
+        .store(true, Ordering::Relaxed);
+    if per_cpu.id() != PerCpu::current().id() {
+        // sending the IPI isn't ordered with stores in x2APIC mode
+        atomic::fence(Ordering::SeqCst);
+        LocalApic::send_ipi(
+            per_cpu.apic_id(),
+            LocalApicIcr::FIXED | RESCHEDULE_VECTOR as u32,
+        );
+    }
+}
+
+/// Preempts the current thread on return from the interrupt, the request is
+/// set by the sender.
+fn reschedule_interrupt(_frame: &mut InterruptFrame) {
+    LocalApic::eoi();
+}
+
+/// Requests preemption of the current thread at the end of the timer
+/// interrupt.
+fn preempt(_data: usize) {
+    PerCpu::current()
+        .run_queue()
+        .need_resched
+        .store(true, Ordering::Relaxed);
+}
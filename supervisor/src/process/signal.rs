@@ -0,0 +1,328 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::mem;
+
+use abi::{
+    signal_set, Error, Signal, SignalAction, SignalCode, SignalContext, SignalFlags, SignalHandler,
+    SignalInfo, SignalSet, SIGNAL_COUNT,
+};
+use zerocopy::{FromBytes, Immutable, IntoBytes};
+
+use crate::{
+    memory::{UserPtr, UserSlice, USER_MEMORY_RANGE},
+    process::{Pid, Process, ProcessState, Scheduler, Thread},
+    x86::{self, Fpu, InterruptFrame},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Signals which always have their default action and are never blocked.
+pub const UNBLOCKABLE: SignalSet = signal_set(Signal::KILL) | signal_set(Signal::STOP);
+
+/// Signals which are ignored by default, all others terminate the process.
+const IGNORED_BY_DEFAULT: SignalSet = signal_set(Signal::CHLD)
+    | signal_set(Signal::CONT)
+    | signal_set(Signal::STOP)
+    | signal_set(Signal::TSTP);
+
+/// Part of the stack below the stack pointer that leaf functions may use on
+/// x86_64.
+#[cfg(target_arch = "x86_64")]
+const RED_ZONE: usize = 128;
+
+/// Alignment of the save area of the extended state.
+const FPU_STATE_ALIGN: usize = 64;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Signal actions and pending signals of a process
+pub struct ProcessSignals {
+    actions: [SignalAction; SIGNAL_COUNT],
+    pending: SignalSet,
+    /// Details of each pending signal, a signal pending already isn't queued
+    /// again.
+    info: [SignalInfo; SIGNAL_COUNT],
+}
+
+/// Layout of the user stack a signal handler is called with
+#[derive(FromBytes, IntoBytes, Immutable)]
+#[repr(C)]
+struct SignalFrame {
+    /// Return address of the handler.
+    restorer: usize,
+    /// Arguments of the handler, which are passed in registers on x86_64.
+    #[cfg(target_arch = "x86")]
+    args: [usize; 3],
+    info: SignalInfo,
+    context: SignalContext,
+    /// User address of the saved extended state.
+    fpu_state: usize,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl ProcessSignals {
+    pub const fn new() -> Self {
+        const DEFAULT: SignalAction = SignalAction {
+            handler: SignalHandler::DEFAULT,
+            mask: 0,
+            flags: 0,
+            restorer: 0,
+        };
+        const NONE: SignalInfo = SignalInfo {
+            signal: 0,
+            code: 0,
+            sender: 0,
+            addr: 0,
+        };
+        Self {
+            actions: [DEFAULT; SIGNAL_COUNT],
+            pending: 0,
+            info: [NONE; SIGNAL_COUNT],
+        }
+    }
+
+    pub fn action(&self, signal: usize) -> Result<SignalAction, Error> {
+        check_signal(signal)?;
+        Ok(self.actions[signal])
+    }
+
+    /// Replaces the action of `signal`, and returns the previous one.
+    ///
+    /// Pending instances of the signal are discarded if it is ignored now.
+    pub fn set_action(
+        &mut self,
+        signal: usize,
+        action: SignalAction,
+    ) -> Result<SignalAction, Error> {
+        check_signal(signal)?;
+        if signal_set(signal) & UNBLOCKABLE != 0 {
+            return Err(Error::InvalidArgument);
+        }
+        let is_special = matches!(
+            action.handler,
+            SignalHandler::DEFAULT | SignalHandler::IGNORE
+        );
+        // the handler is entered through the interrupt return, which faults
+        // in the kernel on non-canonical addresses
+        if !is_special && (!USER_MEMORY_RANGE.contains(&action.handler) || action.restorer == 0) {
+            return Err(Error::InvalidArgument);
+        }
+        let previous = mem::replace(&mut self.actions[signal], action);
+        if self.is_ignored(signal) {
+            self.pending &= !signal_set(signal);
+        }
+        Ok(previous)
+    }
+
+    /// Makes a signal pending, unless it would be ignored anyway.
+    pub fn post(&mut self, info: SignalInfo) {
+        if !self.is_ignored(info.signal) && self.pending & signal_set(info.signal) == 0 {
+            self.pending |= signal_set(info.signal);
+            self.info[info.signal] = info;
+        }
+    }
+
+    /// Takes the pending signal with the lowest number not blocked by `mask`.
+    fn take(&mut self, mask: SignalSet) -> Option<SignalInfo> {
+        let ready = self.pending & !mask;
+        if ready == 0 {
+            return None;
+        }
+        let signal = ready.trailing_zeros() as usize;
+        self.pending &= !signal_set(signal);
+        Some(self.info[signal])
+    }
+
+    fn is_ignored(&self, signal: usize) -> bool {
+        match self.actions[signal].handler {
+            SignalHandler::IGNORE => true,
+            SignalHandler::DEFAULT => signal_set(signal) & IGNORED_BY_DEFAULT != 0,
+            _ => false,
+        }
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Sends `signal` to `process` on behalf of the process `sender`.
+pub fn send_signal(process: &Process, signal: usize, sender: Pid) -> Result<(), Error> {
+    check_signal(signal)?;
+    if process.state() == ProcessState::Zombie {
+        return Ok(());
+    }
+    if signal == Signal::KILL {
+        process.exit(-(signal as i32));
+        return Ok(());
+    }
+    process.signals().post(SignalInfo {
+        signal,
+        code: SignalCode::USER,
+        sender: sender as usize,
+        addr: 0,
+    });
+    Ok(())
+}
+
+/// Raises `signal` for a fault at `addr` in the current thread, which handles
+/// it before returning to user mode.
+///
+/// The process is terminated if the signal is blocked or ignored, since the
+/// fault would only happen again.
+pub fn raise_fault(signal: usize, addr: usize) {
+    let thread = Scheduler::current();
+    let Some(process) = thread.process() else {
+        return;
+    };
+    let blocked = thread.signal_mask() & signal_set(signal) != 0;
+    if blocked || process.signals().actions[signal].handler == SignalHandler::IGNORE {
+        process.exit(-(signal as i32));
+        return;
+    }
+    thread.pending_fault().set(Some(SignalInfo {
+        signal,
+        code: SignalCode::FAULT,
+        sender: 0,
+        addr,
+    }));
+}
+
+/// Carries out the actions of the signals pending for the current thread,
+/// which is about to return to user mode through `frame`, up to the first one
+/// with a handler.
+pub fn deliver_signals(frame: &mut InterruptFrame) {
+    let thread = Scheduler::current();
+    let Some(process) = thread.process() else {
+        return;
+    };
+    while !process.is_exiting() {
+        let mut signals = process.signals();
+        let Some(info) = thread
+            .pending_fault()
+            .take()
+            .or_else(|| signals.take(thread.signal_mask()))
+        else {
+            return;
+        };
+        let action = signals.actions[info.signal];
+        match action.handler {
+            SignalHandler::IGNORE => {}
+            SignalHandler::DEFAULT if signal_set(info.signal) & IGNORED_BY_DEFAULT != 0 => {}
+            SignalHandler::DEFAULT => process.exit(-(info.signal as i32)),
+            _ => {
+                if action.flags & SignalFlags::RESETHAND != 0 {
+                    signals.actions[info.signal].handler = SignalHandler::DEFAULT;
+                }
+                drop(signals);
+                if enter_handler(thread, frame, &action, &info).is_err() {
+                    process.exit(-(Signal::SEGV as i32));
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// Continues the code interrupted by a signal, once its handler returned
+/// through the restorer with the user stack of `frame`.
+pub fn return_from_signal(frame: &mut InterruptFrame) {
+    if restore_context(Scheduler::current(), frame).is_err() {
+        raise_fault(Signal::SEGV, frame.sp());
+    }
+}
+
+/// Builds the stack of a signal handler below the user stack of `frame`, and
+/// makes `frame` call it.
+fn enter_handler(
+    thread: &Thread,
+    frame: &mut InterruptFrame,
+    action: &SignalAction,
+    info: &SignalInfo,
+) -> Result<(), Error> {
+    #[cfg(target_arch = "x86")]
+    let sp = frame.sp();
+    #[cfg(target_arch = "x86_64")]
+    let sp = frame.sp().wrapping_sub(RED_ZONE);
+    let fpu_size = Fpu::get().size();
+    let fpu_state = sp.wrapping_sub(fpu_size) & !(FPU_STATE_ALIGN - 1);
+    // aligned as after a call, which pushed the return address
+    let addr = (fpu_state.wrapping_sub(mem::size_of::<SignalFrame>()) & !15)
+        .wrapping_sub(mem::size_of::<usize>());
+    let info_addr = addr.wrapping_add(mem::offset_of!(SignalFrame, info));
+    let context_addr = addr.wrapping_add(mem::offset_of!(SignalFrame, context));
+
+    x86::without_interrupts(|| {
+        Fpu::save_current(thread);
+        UserSlice::new(fpu_state, fpu_size).write(unsafe { (*thread.fpu_state()).as_bytes() })
+    })?;
+    let signal_frame = SignalFrame {
+        restorer: action.restorer,
+        #[cfg(target_arch = "x86")]
+        args: [info.signal, info_addr, context_addr],
+        info: *info,
+        context: frame.save_context(thread.signal_mask()),
+        fpu_state,
+    };
+    UserPtr::new(addr).write(&signal_frame)?;
+
+    let mut mask = thread.signal_mask() | action.mask;
+    if action.flags & SignalFlags::NODEFER == 0 {
+        mask |= signal_set(info.signal);
+    }
+    thread.set_signal_mask(mask);
+    frame.call_user(action.handler, addr, [info.signal, info_addr, context_addr]);
+    Ok(())
+}
+
+/// Restores the state saved by `enter_handler`.
+fn restore_context(thread: &Thread, frame: &mut InterruptFrame) -> Result<(), Error> {
+    // the return of the handler popped the return address
+    let addr = frame.sp().wrapping_sub(mem::size_of::<usize>());
+    let signal_frame = UserPtr::<SignalFrame>::new(addr).read()?;
+    if !frame.restore_context(&signal_frame.context) {
+        return Err(Error::Fault);
+    }
+    thread.set_signal_mask(signal_frame.context.mask as SignalSet);
+
+    x86::without_interrupts(|| {
+        let fpu_state = unsafe { &mut *thread.fpu_state() };
+        let result = UserSlice::new(signal_frame.fpu_state, Fpu::get().size())
+            .read(fpu_state.as_bytes_mut());
+        // even a partial copy has to be safe to load
+        fpu_state.sanitize();
+        Fpu::restore_current(thread);
+        result
+    })
+}
+
+fn check_signal(signal: usize) -> Result<(), Error> {
+    match signal {
+        0 => Err(Error::InvalidArgument),
+        signal if signal >= SIGNAL_COUNT => Err(Error::InvalidArgument),
+        _ => Ok(()),
+    }
+}
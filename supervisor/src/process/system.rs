@@ -16,19 +16,519 @@
 // Imports
 //==================================================================================================
 
-use crate::{memory::SystemMemory, process::Process};
+use core::{
+    ffi::{c_char, CStr},
+    mem, ops, ptr, slice,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+use abi::{ResourceKind, Rights};
+use multiboot::{
+    multiboot_info, multiboot_mmap_entry, multiboot_module_t, MULTIBOOT_MEMORY_AVAILABLE,
+};
+use spin::Once;
+use zerocopy::FromBytes;
+
+use crate::{
+    acpi::{AcpiTables, MadtEntry, MadtLocalApicFlags},
+    info,
+    log::{self, SymbolTables},
+    memory::{KernelMemory, ObjectPool, SystemMemory, KERNEL_VMA, PAGE_SIZE},
+    process::{Capability, Elf, KernelObject, ProcessTable, Resource, Scheduler, Thread},
+    sync::SpinLock,
+    time::{Clock, TimerQueue},
+    warn,
+    x86::{self, CpuFeatureNames, CpuInfo, Fpu, IoApic, LocalApic, PerCpu},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+pub const MAX_CPUS: usize = 32;
+
+/// Maximum number of arguments passed to a boot module.
+const MAX_MODULE_ARGS: usize = 16;
+
+/// Maximum number of RAM ranges kept from the memory map.
+const MAX_RAM_RANGES: usize = 32;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+extern "C" {
+    static __init_start: u8;
+    static __bss_end: u8;
+}
+
+static SYSTEM: System = System::new();
 
 //==================================================================================================
 // Structures
 //==================================================================================================
 
+/// Stages the bootstrap processor goes through, in order
+#[derive(Clone, Copy, Debug)]
+pub enum BootStage {
+    /// CPU features, per-CPU data and exceptions.
+    Early,
+    /// Physical memory and the kernel address space.
+    Memory,
+    /// ACPI, local APIC, clock and timers.
+    Interrupts,
+    /// Other CPUs.
+    Smp,
+    /// Initial user processes.
+    Userspace,
+    /// Boot is complete.
+    Running,
+}
+
+/// Root object of the kernel, owning everything which exists once
 pub struct System {
-    memory: SystemMemory,
-    process: Process,
+    /// Physical address of the multiboot information.
+    multiboot_info: AtomicUsize,
+    /// Whether the `panic=reboot` boot option is given.
+    reboot_on_panic: AtomicBool,
+    memory: Once<SpinLock<KernelMemory>>,
+    /// Available memory from the memory map, which may not be mapped as
+    /// device memory.
+    ram: Once<[ops::Range<usize>; MAX_RAM_RANGES]>,
+    acpi: Once<Option<AcpiTables>>,
+    clock: Once<Clock>,
+    cpus: [AtomicPtr<PerCpu>; MAX_CPUS],
+    threads: ObjectPool<Thread>,
+    processes: ProcessTable,
 }
 
 //==================================================================================================
 // Implementations
 //==================================================================================================
 
-impl System {}
+impl System {
+    const fn new() -> Self {
+        Self {
+            multiboot_info: AtomicUsize::new(0),
+            reboot_on_panic: AtomicBool::new(false),
+            memory: Once::new(),
+            ram: Once::new(),
+            acpi: Once::new(),
+            clock: Once::new(),
+            cpus: [const { AtomicPtr::new(ptr::null_mut()) }; MAX_CPUS],
+            threads: ObjectPool::new(),
+            processes: ProcessTable::new(),
+        }
+    }
+
+    pub fn get() -> &'static Self {
+        &SYSTEM
+    }
+
+    /// Boots the system on the bootstrap processor and turns into its idle
+    /// thread.
+    pub fn boot(&'static self, multiboot_magic: u32, multiboot_info: u32) -> ! {
+        self.init_early(multiboot_magic, multiboot_info);
+        self.init_memory();
+        self.init_interrupts();
+        self.init_smp();
+        self.init_userspace();
+
+        self.enter(BootStage::Running);
+        info!("Running on {} CPUs", PerCpu::count());
+        Scheduler::init();
+        Scheduler::idle();
+    }
+
+    /// Brings up another CPU once it has been started by the bootstrap
+    /// processor, and turns into its idle thread.
+    pub fn boot_other(&'static self) -> ! {
+        CpuInfo::init();
+        Fpu::init();
+        let per_cpu = PerCpu::init();
+        #[cfg(target_arch = "x86_64")]
+        per_cpu.init_interrupt_stacks();
+        per_cpu.init_io_bitmap();
+        per_cpu.init_tls();
+        x86::load_idt();
+        x86::enable_tlb_shootdown();
+        x86::enable_syscalls();
+        LocalApic::init();
+        x86::enable_interrupts();
+
+        Scheduler::init();
+        Scheduler::idle();
+    }
+
+    pub fn multiboot_info(&self) -> &'static multiboot_info {
+        let addr = self.multiboot_info.load(Ordering::Relaxed);
+        unsafe { &*(addr as *const multiboot_info) }
+    }
+
+    /// Value of the `name=value` option on the kernel command line, empty for
+    /// a bare `name`, and `None` if it isn't given or the command line isn't
+    /// known yet.
+    pub fn boot_option(&self, name: &str) -> Option<&'static str> {
+        if self.multiboot_info.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let multiboot_info = self.multiboot_info();
+        if multiboot_info.flags & multiboot::MULTIBOOT_INFO_CMDLINE == 0 {
+            return None;
+        }
+        let cmdline = unsafe { CStr::from_ptr(multiboot_info.cmdline as usize as *const c_char) };
+        // the first word is the path of the kernel
+        cmdline
+            .to_str()
+            .ok()?
+            .split_whitespace()
+            .skip(1)
+            .find_map(|option| match option.split_once('=') {
+                Some((key, value)) => (key == name).then_some(value),
+                None => (option == name).then_some(""),
+            })
+    }
+
+    /// Whether a panic reboots the system instead of halting it, which can
+    /// be asked for under any mapping.
+    pub fn reboot_on_panic(&self) -> bool {
+        self.reboot_on_panic.load(Ordering::Relaxed)
+    }
+
+    /// Modules loaded by the boot loader, located by physical address.
+    pub fn modules(&self) -> &'static [multiboot_module_t] {
+        let multiboot_info = self.multiboot_info();
+        if multiboot_info.flags & multiboot::MULTIBOOT_INFO_MODS == 0 {
+            return &[];
+        }
+        unsafe {
+            slice::from_raw_parts(
+                multiboot_info.mods_addr as usize as *const multiboot_module_t,
+                multiboot_info.mods_count as usize,
+            )
+        }
+    }
+
+    /// Physical memory and the kernel address space, available from the
+    /// memory stage on.
+    pub fn memory(&self) -> &SpinLock<KernelMemory> {
+        self.memory.get().unwrap()
+    }
+
+    /// Whether any of `size` bytes from the physical address `base` is RAM,
+    /// always true before the memory stage.
+    pub fn is_ram(&self, base: usize, size: usize) -> bool {
+        let Some(ram) = self.ram.get() else {
+            return true;
+        };
+        let end = base.saturating_add(size);
+        ram.iter()
+            .any(|range| range.start < end && base < range.end)
+    }
+
+    /// ACPI tables, `None` if the firmware doesn't provide them.
+    pub fn acpi(&self) -> Option<&AcpiTables> {
+        self.acpi.get()?.as_ref()
+    }
+
+    /// System clock, available from the interrupts stage on.
+    pub fn clock(&self) -> &Clock {
+        self.clock.get().unwrap()
+    }
+
+    /// Returns the per-CPU data area of the CPU with the given id.
+    pub fn cpu(&self, id: u32) -> Option<&'static PerCpu> {
+        let cpu = self.cpus.get(id as usize)?.load(Ordering::Acquire);
+        unsafe { cpu.as_ref() }
+    }
+
+    /// Makes a CPU reachable through [`System::cpu`], called once by every CPU
+    /// while it is brought up.
+    pub fn add_cpu(&self, cpu: &'static PerCpu) {
+        self.cpus[cpu.id() as usize].store(cpu as *const _ as *mut _, Ordering::Release);
+    }
+
+    /// Storage of all threads.
+    pub fn threads(&self) -> &ObjectPool<Thread> {
+        &self.threads
+    }
+
+    /// Table of all processes.
+    pub fn processes(&self) -> &ProcessTable {
+        &self.processes
+    }
+
+    fn enter(&self, stage: BootStage) {
+        info!("Entering {:?} stage", stage);
+    }
+
+    /// Brings up the console first, so that everything after can print.
+    fn init_early(&self, multiboot_magic: u32, multiboot_info: u32) {
+        log::init_console();
+        self.enter(BootStage::Early);
+        let cpu_info = CpuInfo::init();
+        info!(
+            "{} family {:#x} model {:#x} stepping {}: {}",
+            cpu_info.vendor(),
+            cpu_info.family(),
+            cpu_info.model(),
+            cpu_info.stepping(),
+            CpuFeatureNames(cpu_info.features())
+        );
+        Fpu::init();
+        PerCpu::init();
+        x86::load_idt();
+        x86::enable_syscalls();
+
+        assert!(multiboot_magic == multiboot::MULTIBOOT_BOOTLOADER_MAGIC);
+        self.multiboot_info
+            .store(multiboot_info as usize, Ordering::Relaxed);
+        if let Some(spec) = self.boot_option("log") {
+            log::configure_log(spec);
+        }
+    }
+
+    /// Hands all available memory above 1 MiB, except for the kernel image and
+    /// the boot information, to the kernel memory.
+    fn init_memory(&self) {
+        self.enter(BootStage::Memory);
+        let multiboot_info = self.multiboot_info();
+        let mut system_memory = SystemMemory::new();
+
+        // the command line is only reachable under the boot mapping
+        self.reboot_on_panic.store(
+            self.boot_option("panic") == Some("reboot"),
+            Ordering::Relaxed,
+        );
+        let mut ram = [const { 0..0 }; MAX_RAM_RANGES];
+        let mut ram_count = 0;
+
+        assert!(multiboot_info.flags & multiboot::MULTIBOOT_INFO_MEM_MAP != 0);
+        let mut multiboot_mmap = unsafe {
+            slice::from_raw_parts(
+                multiboot_info.mmap_addr as usize as *const u8,
+                multiboot_info.mmap_length as usize,
+            )
+        };
+        while !multiboot_mmap.is_empty() {
+            let multiboot_mmap_entry = multiboot_mmap_entry::ref_from_prefix(multiboot_mmap)
+                .unwrap()
+                .0;
+            multiboot_mmap = &multiboot_mmap[multiboot_mmap_entry.size as usize + 4..];
+            if multiboot_mmap_entry.type_ != MULTIBOOT_MEMORY_AVAILABLE {
+                continue;
+            }
+            let Ok(addr) = usize::try_from(multiboot_mmap_entry.addr) else {
+                continue;
+            };
+            let end = usize::try_from(multiboot_mmap_entry.addr + multiboot_mmap_entry.len)
+                .unwrap_or(usize::MAX);
+
+            // further ranges are merged into the last one, which only takes
+            // the holes in between away from device memory
+            if ram_count < MAX_RAM_RANGES {
+                ram[ram_count] = addr..end;
+                ram_count += 1;
+            } else {
+                let last = &mut ram[MAX_RAM_RANGES - 1];
+                *last = last.start.min(addr)..last.end.max(end);
+            }
+
+            // memory below 1 MiB is left alone, as it is cluttered with BIOS
+            // structures
+            let addr = addr.max(0x100000).next_multiple_of(PAGE_SIZE);
+            let end = end & !(PAGE_SIZE - 1);
+            if addr < end {
+                system_memory.deallocate(addr, end - addr);
+            }
+        }
+
+        // reserve the kernel image, including the boot page tables
+        let (kernel_start, kernel_end) = unsafe {
+            (
+                &__init_start as *const u8 as usize,
+                &__bss_end as *const u8 as usize - KERNEL_VMA,
+            )
+        };
+        system_memory
+            .allocate(
+                Some(kernel_start),
+                kernel_end.next_multiple_of(PAGE_SIZE) - kernel_start,
+            )
+            .expect("Kernel image is not in available memory");
+
+        // the modules are kept until their processes are created, they are
+        // page-aligned as requested by the multiboot header
+        for module in self.modules() {
+            let start = module.mod_start as usize;
+            let end = (module.mod_end as usize).next_multiple_of(PAGE_SIZE);
+            system_memory
+                .allocate(Some(start), end - start)
+                .expect("Boot module is not in available memory");
+        }
+
+        // the boot information is read again when the modules are loaded, its
+        // pages may be shared with each other or with the modules, which are
+        // only freed once all of them are loaded
+        let mut reserve = |addr: usize, size: usize| {
+            let start = addr & !(PAGE_SIZE - 1);
+            let end = (addr + size).next_multiple_of(PAGE_SIZE);
+            for page in (start..end).step_by(PAGE_SIZE) {
+                system_memory.allocate(Some(page), PAGE_SIZE);
+            }
+        };
+        reserve(
+            multiboot_info as *const multiboot_info as usize,
+            mem::size_of::<multiboot_info>(),
+        );
+        reserve(
+            multiboot_info.mmap_addr as usize,
+            multiboot_info.mmap_length as usize,
+        );
+        if multiboot_info.flags & multiboot::MULTIBOOT_INFO_CMDLINE != 0 {
+            let cmdline =
+                unsafe { CStr::from_ptr(multiboot_info.cmdline as usize as *const c_char) };
+            reserve(multiboot_info.cmdline as usize, cmdline.count_bytes() + 1);
+        }
+        let modules = self.modules();
+        reserve(modules.as_ptr() as usize, mem::size_of_val(modules));
+        for module in modules {
+            // the command line is only considered up to the end of its page
+            reserve(module.cmdline as usize, 1);
+        }
+
+        // the symbols of the kernel are kept for backtraces
+        let symbol_tables = SymbolTables::locate(multiboot_info).filter(|tables| {
+            let range = tables.range();
+            system_memory
+                .allocate(Some(range.start), range.len())
+                .is_some()
+        });
+
+        self.ram.call_once(|| ram);
+        self.memory
+            .call_once(|| SpinLock::new(KernelMemory::new(system_memory)));
+        if let Some(symbol_tables) = symbol_tables {
+            log::init_symbols(symbol_tables);
+        }
+        #[cfg(target_arch = "x86_64")]
+        PerCpu::current().init_interrupt_stacks();
+        PerCpu::current().init_io_bitmap();
+        PerCpu::current().init_tls();
+        x86::enable_tlb_shootdown();
+    }
+
+    fn init_interrupts(&self) {
+        self.enter(BootStage::Interrupts);
+        self.acpi.call_once(AcpiTables::new);
+        LocalApic::init();
+        IoApic::init();
+        let clock = self.clock.call_once(Clock::new);
+        info!(
+            "TSC at {} MHz, local APIC timer at {} MHz",
+            clock.tsc_frequency() / 1_000_000,
+            clock.timer_frequency() / 1_000_000
+        );
+        TimerQueue::init();
+        x86::enable_interrupts();
+    }
+
+    /// Starts all other CPUs listed in the MADT.
+    fn init_smp(&self) {
+        self.enter(BootStage::Smp);
+        let Some(madt) = self.acpi().and_then(|acpi| acpi.madt()) else {
+            return;
+        };
+
+        let bsp = PerCpu::current().apic_id();
+        for entry in madt.entries() {
+            let (apic_id, flags) = match entry {
+                MadtEntry::LocalApic(local_apic) => (local_apic.apic_id as u32, local_apic.flags),
+                MadtEntry::LocalX2Apic(local_x2apic) => {
+                    (local_x2apic.x2apic_id, local_x2apic.flags)
+                }
+                _ => continue,
+            };
+            if apic_id == bsp || flags & MadtLocalApicFlags::ENABLED == 0 {
+                continue;
+            }
+            if PerCpu::count() as usize == MAX_CPUS {
+                break;
+            }
+            if !x86::start_cpu(apic_id) {
+                warn!("CPU with APIC id {} didn't come up", apic_id);
+            }
+        }
+    }
+
+    /// Creates a process for every boot module, the first one becomes the init
+    /// process and the parent of all others, with a handle to each of them and
+    /// to the resources of [`abi::InitHandle`]. The memory of the modules is
+    /// released afterwards.
+    fn init_userspace(&self) {
+        self.enter(BootStage::Userspace);
+        let mut init = None;
+        for module in self.modules() {
+            let size = (module.mod_end - module.mod_start) as usize;
+            let data = KernelMemory::lock()
+                .map_physical(module.mod_start as usize, size, 0)
+                .expect("Not enough memory to map boot module");
+            // the command line is only considered up to the end of its page
+            let cmdline_size = PAGE_SIZE - module.cmdline as usize % PAGE_SIZE;
+            let cmdline = KernelMemory::lock()
+                .map_physical(module.cmdline as usize, cmdline_size, 0)
+                .expect("Not enough memory to map boot module");
+
+            let mut argv = [""; MAX_MODULE_ARGS];
+            let mut argc = 0;
+            let cmdline_bytes =
+                unsafe { slice::from_raw_parts(cmdline as *const u8, cmdline_size) };
+            if let Some(cmdline) = CStr::from_bytes_until_nul(cmdline_bytes)
+                .ok()
+                .and_then(|cmdline| cmdline.to_str().ok())
+            {
+                for (arg, value) in argv.iter_mut().zip(cmdline.split_whitespace()) {
+                    *arg = value;
+                    argc += 1;
+                }
+            }
+
+            let data = unsafe { slice::from_raw_parts(data as *const u8, size) };
+            let process = Elf::parse(data)
+                .and_then(|elf| elf.spawn(init, &argv[..argc], &[]))
+                .expect("Failed to load boot module");
+            info!("Started process {} from {}", process.pid(), argv[0]);
+            match init {
+                Some(init) => {
+                    let child = Capability::new(KernelObject::Process(process.pid()), Rights::ALL);
+                    init.handles().insert(child).expect("Too many boot modules");
+                }
+                None => {
+                    for kind in [
+                        ResourceKind::IO_PORTS,
+                        ResourceKind::MMIO,
+                        ResourceKind::IRQ,
+                    ] {
+                        let resource = KernelObject::Resource(Resource::root(kind));
+                        process
+                            .handles()
+                            .insert(Capability::new(resource, Rights::ALL))
+                            .unwrap();
+                    }
+                    init = Some(process);
+                }
+            }
+
+            let mut memory = KernelMemory::lock();
+            memory.unmap_physical(cmdline, cmdline_size);
+            memory.unmap_physical(data.as_ptr() as usize, size);
+        }
+
+        let mut memory = KernelMemory::lock();
+        for module in self.modules() {
+            let start = module.mod_start as usize;
+            let end = (module.mod_end as usize).next_multiple_of(PAGE_SIZE);
+            memory.system().deallocate(start, end - start);
+        }
+    }
+}
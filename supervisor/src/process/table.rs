@@ -0,0 +1,183 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::ptr::NonNull;
+
+use abi::Error;
+
+use crate::{
+    ipc::PortBinding,
+    memory::{ObjectPool, ProcessMemory},
+    process::{Pid, Process, ProcessState, INIT_PID},
+    sync::{SpinLock, WaitQueue},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+pub const MAX_PROCESSES: usize = 32;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// All processes, indexed by pid
+///
+/// A process lives in the slot `pid % MAX_PROCESSES`, pids which would collide
+/// with a living or zombie process are skipped.
+pub struct ProcessTable {
+    pool: ObjectPool<Process>,
+    slots: SpinLock<ProcessSlots>,
+    /// Parents waiting for any of their children to exit.
+    child_exited: WaitQueue,
+}
+
+struct ProcessSlots {
+    processes: [Option<&'static Process>; MAX_PROCESSES],
+    next_pid: Pid,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl ProcessTable {
+    pub const fn new() -> Self {
+        Self {
+            pool: ObjectPool::new(),
+            slots: SpinLock::new(ProcessSlots {
+                processes: [None; MAX_PROCESSES],
+                next_pid: INIT_PID,
+            }),
+            child_exited: WaitQueue::new(),
+        }
+    }
+
+    /// Creates a process without threads, the first one created becomes the
+    /// init process.
+    ///
+    /// Hands `memory` back if the table is full.
+    pub fn create(
+        &'static self,
+        parent: Option<&Process>,
+        memory: ProcessMemory,
+    ) -> Result<&'static Process, ProcessMemory> {
+        let mut slots = self.slots.lock();
+        for _ in 0..MAX_PROCESSES {
+            let pid = slots.next_pid;
+            slots.next_pid = pid.checked_add(1).unwrap_or(INIT_PID + 1);
+
+            let slot = pid as usize % MAX_PROCESSES;
+            if slots.processes[slot].is_none() {
+                let parent = parent.map_or(0, Process::pid);
+                let process: &'static Process =
+                    self.pool.allocate(Process::new(pid, parent, memory));
+                slots.processes[slot] = Some(process);
+                return Ok(process);
+            }
+        }
+        Err(memory)
+    }
+
+    /// Returns the living or zombie process with the given pid.
+    pub fn get(&self, pid: Pid) -> Option<&'static Process> {
+        let slots = self.slots.lock();
+        slots.processes[pid as usize % MAX_PROCESSES].filter(|process| process.pid() == pid)
+    }
+
+    /// Turns a process whose last thread exited into a zombie, re-parents its
+    /// children to init and notifies the parent.
+    ///
+    /// Processes without a parent are reaped right away.
+    pub fn exit(&self, process: &'static Process) {
+        let mut slots = self.slots.lock();
+        assert!(
+            process.pid() != INIT_PID,
+            "Init process exited with {}",
+            process.exit_code()
+        );
+
+        process.set_state(ProcessState::Zombie);
+        if let Some(binding) = process.exit_binding().take() {
+            binding.post(process.exit_code() as usize);
+            binding.unbind();
+        }
+        for child in slots.processes.iter().flatten() {
+            if child.parent() == process.pid() {
+                child.set_parent(INIT_PID);
+            }
+        }
+        if process.parent() == 0 {
+            self.reap(&mut slots, process);
+        }
+        drop(slots);
+
+        self.child_exited.wake_all();
+    }
+
+    /// Posts the exit code of a process to a port once it exits, replacing the
+    /// previous binding, or right away if it already did.
+    pub fn bind_exit(&self, process: &Process, binding: PortBinding) {
+        let _slots = self.slots.lock();
+        if process.state() == ProcessState::Zombie {
+            binding.post(process.exit_code() as usize);
+            binding.unbind();
+        } else if let Some(previous) = process.exit_binding().replace(Some(binding)) {
+            previous.unbind();
+        }
+    }
+
+    /// Blocks until a child of `parent` exited, reaps it and returns its pid
+    /// and exit code. With `pid` set, only that child is waited for.
+    ///
+    /// Fails right away with [`Error::NotFound`] if there is no matching
+    /// child, and with [`Error::Cancelled`] if the process is exiting.
+    pub fn wait(&self, parent: &Process, pid: Option<Pid>) -> Result<(Pid, i32), Error> {
+        let mut result = Err(Error::NotFound);
+        self.child_exited.wait_interruptible(|| {
+            let mut slots = self.slots.lock();
+            let mut has_child = false;
+            let mut zombie = None;
+            for child in slots.processes.iter().flatten() {
+                if child.parent() != parent.pid() || pid.is_some_and(|pid| child.pid() != pid) {
+                    continue;
+                }
+                has_child = true;
+                if child.state() == ProcessState::Zombie {
+                    zombie = Some(*child);
+                    break;
+                }
+            }
+            let Some(zombie) = zombie else {
+                return !has_child;
+            };
+
+            result = Ok((zombie.pid(), zombie.exit_code()));
+            self.reap(&mut slots, zombie);
+            true
+        })?;
+        result
+    }
+
+    fn reap(&self, slots: &mut ProcessSlots, process: &'static Process) {
+        slots.processes[process.pid() as usize % MAX_PROCESSES] = None;
+        self.pool
+            .deallocate(unsafe { NonNull::from(process).as_mut() });
+    }
+}
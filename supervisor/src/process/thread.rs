@@ -12,14 +12,405 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    cell::{Cell, UnsafeCell},
+    mem, ops,
+};
+
+use abi::{Error, SignalInfo, SignalSet};
+use intrusive_collections::{intrusive_adapter, LinkedListLink, RBTreeLink, UnsafeRef};
+
+use crate::{
+    ipc::IpcState,
+    memory::{KernelMemory, Mapping, PageTableEntryFlags, PAGE_SIZE},
+    process::{Process, Scheduler, SchedulingPolicy, UNBLOCKABLE},
+    sync::SpinLock,
+    time::{self, Timer},
+    x86::{self, Context, Fpu, FpuState, PerCpu},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+pub const KERNEL_STACK_SIZE: usize = 4 * PAGE_SIZE;
+
+/// Affinity mask allowing a thread to run on any CPU.
+pub const AFFINITY_ALL: u32 = u32::MAX;
+
 //==================================================================================================
 // Structures
 //==================================================================================================
 
-pub struct Thread {}
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ThreadState {
+    /// Waiting to be picked by the scheduler.
+    Ready,
+    /// Running on a CPU.
+    Running,
+    /// Waiting for an event, not considered by the scheduler.
+    Blocked,
+    /// Finished, waiting to be cleaned up.
+    Exited,
+}
+
+/// Thread control block
+pub struct Thread {
+    /// Link into a run queue or a wait queue.
+    link: LinkedListLink,
+    /// Link into the run queue of the deadline class.
+    deadline_link: RBTreeLink,
+    /// Link into the thread list of the owning process.
+    process_link: LinkedListLink,
+    context: Context,
+    kernel_stack: ops::Range<usize>,
+    state: Cell<ThreadState>,
+    /// Owning process, `None` for kernel threads.
+    process: Option<&'static Process>,
+    policy: Cell<SchedulingPolicy>,
+    priority: Cell<u8>,
+    /// Absolute deadline in nanoseconds since boot, for the deadline class.
+    deadline: Cell<u64>,
+    /// CPUs the thread may run on, one bit per CPU id.
+    affinity: Cell<u32>,
+    fpu_state: UnsafeCell<FpuState>,
+    /// Id of the CPU the thread last ran on.
+    cpu: Cell<u32>,
+    ipc: IpcState,
+    signal_mask: Cell<SignalSet>,
+    /// Signal raised by the last fault, delivered before any other.
+    pending_fault: Cell<Option<SignalInfo>>,
+    /// User thread pointer, loaded as FS base on x86_64 and as base of the
+    /// segment in GS on i386.
+    thread_pointer: Cell<usize>,
+    /// Wakes the thread from a wait with a timeout.
+    timeout: Timer,
+    /// Whether the timeout may still wake the thread.
+    timeout_armed: SpinLock<bool>,
+}
 
 //==================================================================================================
 // Implementations
 //==================================================================================================
 
-impl Thread {}
+impl Thread {
+    /// Creates a thread which calls `entry(arg)` once it is switched to for
+    /// the first time.
+    ///
+    /// Returns `None` if there is no memory left for the kernel stack.
+    pub fn new(
+        process: Option<&'static Process>,
+        entry: fn(usize),
+        arg: usize,
+        priority: u8,
+    ) -> Option<Self> {
+        let stack = KernelMemory::lock().allocate(KERNEL_STACK_SIZE, PageTableEntryFlags::RW)?;
+        let kernel_stack = stack..stack + KERNEL_STACK_SIZE;
+        Some(Self {
+            link: LinkedListLink::new(),
+            deadline_link: RBTreeLink::new(),
+            process_link: LinkedListLink::new(),
+            context: Context::new(kernel_stack.end, entry as usize, arg),
+            kernel_stack,
+            state: Cell::new(ThreadState::Ready),
+            process,
+            policy: Cell::new(SchedulingPolicy::FixedPriority),
+            priority: Cell::new(priority),
+            deadline: Cell::new(u64::MAX),
+            affinity: Cell::new(AFFINITY_ALL),
+            fpu_state: UnsafeCell::new(FpuState::new()),
+            cpu: Cell::new(0),
+            ipc: IpcState::new(),
+            signal_mask: Cell::new(0),
+            pending_fault: Cell::new(None),
+            thread_pointer: Cell::new(0),
+            timeout: Timer::new(expire_timeout, 0),
+            timeout_armed: SpinLock::new(false),
+        })
+    }
+
+    /// Creates a thread for the flow of execution already running on the
+    /// current CPU, which uses the boot stack and never exits.
+    pub fn bootstrap(cpu: u32) -> Self {
+        Self {
+            link: LinkedListLink::new(),
+            deadline_link: RBTreeLink::new(),
+            process_link: LinkedListLink::new(),
+            context: Context::bootstrap(),
+            kernel_stack: 0..0,
+            state: Cell::new(ThreadState::Running),
+            process: None,
+            policy: Cell::new(SchedulingPolicy::RoundRobin),
+            priority: Cell::new(0),
+            deadline: Cell::new(u64::MAX),
+            affinity: Cell::new(1 << cpu),
+            fpu_state: UnsafeCell::new(FpuState::new()),
+            cpu: Cell::new(cpu),
+            ipc: IpcState::new(),
+            signal_mask: Cell::new(0),
+            pending_fault: Cell::new(None),
+            thread_pointer: Cell::new(0),
+            timeout: Timer::new(expire_timeout, 0),
+            timeout_armed: SpinLock::new(false),
+        }
+    }
+
+    pub fn state(&self) -> ThreadState {
+        self.state.get()
+    }
+
+    pub fn set_state(&self, state: ThreadState) {
+        self.state.set(state);
+    }
+
+    pub fn process(&self) -> Option<&'static Process> {
+        self.process
+    }
+
+    /// Whether the process of the thread is exiting, which cancels its waits.
+    pub fn is_exiting(&self) -> bool {
+        self.process.is_some_and(Process::is_exiting)
+    }
+
+    pub fn policy(&self) -> SchedulingPolicy {
+        self.policy.get()
+    }
+
+    /// Changes the scheduling policy, only to be used through
+    /// [`Scheduler::set_scheduling`] as the thread may be queued.
+    pub(super) fn set_policy(&self, policy: SchedulingPolicy) {
+        self.policy.set(policy);
+    }
+
+    pub fn priority(&self) -> u8 {
+        self.priority.get()
+    }
+
+    pub(super) fn set_priority(&self, priority: u8) {
+        self.priority.set(priority);
+    }
+
+    pub fn deadline(&self) -> u64 {
+        self.deadline.get()
+    }
+
+    pub(super) fn set_deadline(&self, deadline: u64) {
+        self.deadline.set(deadline);
+    }
+
+    pub(super) fn set_affinity(&self, affinity: u32) {
+        self.affinity.set(affinity);
+    }
+
+    /// Whether the thread may run on the CPU with the given id.
+    pub fn can_run_on(&self, cpu_id: u32) -> bool {
+        cpu_id < u32::BITS && self.affinity.get() & 1 << cpu_id != 0
+    }
+
+    pub fn cpu(&self) -> u32 {
+        self.cpu.get()
+    }
+
+    pub fn set_cpu(&self, cpu: u32) {
+        self.cpu.set(cpu);
+    }
+
+    pub fn ipc(&self) -> &IpcState {
+        &self.ipc
+    }
+
+    /// Signals blocked for the thread, only to be used by the thread itself.
+    pub fn signal_mask(&self) -> SignalSet {
+        self.signal_mask.get()
+    }
+
+    /// Blocks the signals in `mask`, except those which can't be blocked.
+    pub fn set_signal_mask(&self, mask: SignalSet) {
+        self.signal_mask.set(mask & !UNBLOCKABLE);
+    }
+
+    /// Sets the user thread pointer, only to be used by the thread itself.
+    pub fn set_thread_pointer(&self, thread_pointer: usize) {
+        self.thread_pointer.set(thread_pointer);
+        x86::without_interrupts(|| PerCpu::current().set_user_tls(thread_pointer));
+    }
+
+    /// Points the timeout at the final address of the thread, once it is
+    /// allocated.
+    pub fn init_timeout(&mut self) {
+        self.timeout = Timer::new(expire_timeout, self as *const Self as usize);
+    }
+
+    /// Wakes the current thread at `deadline` in nanoseconds since boot if it
+    /// is still blocked then, unless the timeout is cleared before.
+    ///
+    /// Interrupts have to stay disabled until the thread blocked, as the
+    /// timeout would get lost if it was preempted in between.
+    pub fn set_timeout(&'static self, deadline: u64) {
+        *self.timeout_armed.lock() = true;
+        self.timeout.arm(deadline, 0);
+    }
+
+    /// Disarms the timeout, and returns whether it expired.
+    pub fn clear_timeout(&self) -> bool {
+        self.timeout.cancel();
+        // an expiring timeout may still be on its way, and must not wake the
+        // thread once it blocks for something else
+        !mem::replace(&mut *self.timeout_armed.lock(), false)
+    }
+
+    /// Blocks the current thread until `deadline` in nanoseconds since boot.
+    ///
+    /// Fails with [`Error::Cancelled`] if the process is exiting.
+    pub fn sleep_until(deadline: u64) -> Result<(), Error> {
+        let thread = Scheduler::current();
+        // woken early only by the exit of the process, which ends the sleep
+        // on the next round
+        while time::now() < deadline {
+            let blocked = x86::without_interrupts(|| {
+                Scheduler::prepare_block();
+                thread.set_timeout(deadline);
+                Scheduler::block_unless_exiting()
+            });
+            thread.clear_timeout();
+            if !blocked {
+                return Err(Error::Cancelled);
+            }
+        }
+        Ok(())
+    }
+
+    /// Blocks the current thread for `duration` in nanoseconds, see
+    /// [`Thread::sleep_until`].
+    pub fn sleep(duration: u64) -> Result<(), Error> {
+        Self::sleep_until(sleep_deadline(time::now(), duration))
+    }
+
+    /// Signal raised by a fault of the thread, only to be used by the thread
+    /// itself.
+    pub fn pending_fault(&self) -> &Cell<Option<SignalInfo>> {
+        &self.pending_fault
+    }
+
+    /// Whether the thread is in a run queue, a wait queue or an endpoint.
+    pub fn is_queued(&self) -> bool {
+        self.link.is_linked() || self.deadline_link.is_linked()
+    }
+
+    /// Extended state of the thread while it is not loaded into a CPU.
+    pub fn fpu_state(&self) -> *mut FpuState {
+        self.fpu_state.get()
+    }
+
+    /// Switches the current CPU from this thread to `next`, returns once this
+    /// thread is switched to again.
+    ///
+    /// # Safety
+    ///
+    /// Must be called with interrupts disabled by the thread currently running
+    /// on this CPU, and `next` must not be running on any CPU.
+    pub unsafe fn switch(&self, next: &Thread) {
+        let per_cpu = PerCpu::current();
+        Fpu::switch(Some(self), next);
+        if let Some(process) = next.process {
+            if Mapping::current().root() != process.page_table() {
+                x86::write_cr3(process.page_table());
+            }
+            let (io_bitmap, generation) = process.io_bitmap();
+            per_cpu.load_io_bitmap(io_bitmap, generation);
+            per_cpu.set_user_tls(next.thread_pointer.get());
+        } else if Mapping::current().root() != KernelMemory::root() {
+            // a process mapping is destroyed once its last thread exited, and
+            // must not be kept loaded by kernel threads
+            x86::write_cr3(KernelMemory::root());
+        }
+        per_cpu.set_kernel_stack(next.kernel_stack.end);
+        per_cpu.set_current_thread(Some(next.into()));
+        if self.state.get() == ThreadState::Running {
+            self.state.set(ThreadState::Ready);
+        }
+        next.state.set(ThreadState::Running);
+
+        self.context.switch(&next.context);
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+unsafe impl Send for Thread {}
+
+unsafe impl Sync for Thread {}
+
+intrusive_adapter!(pub ThreadAdapter = UnsafeRef<Thread>: Thread { link: LinkedListLink });
+
+intrusive_adapter!(pub DeadlineAdapter = UnsafeRef<Thread>: Thread { deadline_link: RBTreeLink });
+
+intrusive_adapter!(pub ProcessThreadAdapter = UnsafeRef<Thread>: Thread { process_link: LinkedListLink });
+
+impl Drop for Thread {
+    fn drop(&mut self) {
+        Fpu::release(self);
+        if !self.kernel_stack.is_empty() {
+            KernelMemory::lock().deallocate(self.kernel_stack.start, KERNEL_STACK_SIZE);
+        }
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Runs a new thread, called by `thread_start` on the first switch to it.
+#[no_mangle]
+extern "C" fn thread_main(entry: usize, arg: usize) -> ! {
+    Scheduler::finish_switch();
+    x86::enable_interrupts();
+
+    let entry: fn(usize) = unsafe { mem::transmute(entry) };
+    entry(arg);
+    Scheduler::exit();
+}
+
+/// Wakes the thread a timeout was set for, unless it was cleared meanwhile.
+fn expire_timeout(data: usize) {
+    let thread = unsafe { &*(data as *const Thread) };
+    // woken with the lock held, the thread can't clear the timeout and block
+    // for something else in between
+    let mut armed = thread.timeout_armed.lock();
+    if mem::replace(&mut *armed, false) {
+        Scheduler::wake(thread);
+    }
+}
+
+/// Deadline of a sleep for `duration` from `now`, a sleep which would outlast
+/// the clock never ends.
+fn sleep_deadline(now: u64, duration: u64) -> u64 {
+    now.saturating_add(duration)
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_deadline_adds_duration() {
+        assert_eq!(sleep_deadline(1_000, 500), 1_500);
+        assert_eq!(sleep_deadline(1_000, 0), 1_000);
+    }
+
+    #[test]
+    fn sleep_deadline_saturates() {
+        assert_eq!(sleep_deadline(u64::MAX - 1, 2), u64::MAX);
+        assert_eq!(sleep_deadline(1, u64::MAX), u64::MAX);
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use crate::sync::{MutexGuard, WaitQueue};
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Condition variable used together with a [`Mutex`](crate::sync::Mutex)
+pub struct Condvar {
+    waiters: WaitQueue,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Releases the mutex and blocks until notified, then acquires the mutex
+    /// again.
+    ///
+    /// Wakeups may be spurious, the condition has to be checked again.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = MutexGuard::mutex(&guard);
+        self.waiters.sleep_unless(|| {
+            drop(guard);
+            false
+        });
+        mutex.lock()
+    }
+
+    /// Blocks until `condition` doesn't hold anymore.
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    /// Wakes one waiting thread.
+    pub fn notify_one(&self) {
+        self.waiters.wake_one();
+    }
+
+    /// Wakes all waiting threads.
+    pub fn notify_all(&self) {
+        self.waiters.wake_all();
+    }
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::Mutex;
+
+    #[test]
+    fn wait_while_satisfied() {
+        let mutex = Mutex::new(1);
+        let condvar = Condvar::new();
+        let guard = condvar.wait_while(mutex.lock(), |value| *value == 0);
+
+        assert_eq!(*guard, 1);
+        // the mutex stays locked
+        assert!(mutex.try_lock().is_none());
+    }
+
+    #[test]
+    fn notify_without_waiters() {
+        let mutex = Mutex::new(());
+        let condvar = Condvar::new();
+        condvar.notify_one();
+        condvar.notify_all();
+
+        assert!(mutex.try_lock().is_some());
+    }
+}
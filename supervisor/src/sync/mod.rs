@@ -0,0 +1,35 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// most of the kernel may not sleep, so parts of the sleeping primitives are
+// only used by their unit tests yet
+#[cfg_attr(not(test), allow(dead_code))]
+mod condvar;
+#[cfg_attr(not(test), allow(dead_code))]
+mod mutex;
+#[cfg_attr(not(test), allow(dead_code))]
+mod rwlock;
+#[cfg_attr(not(test), allow(dead_code))]
+mod semaphore;
+mod spinlock;
+mod wait_queue;
+
+#[allow(unused_imports)]
+pub use condvar::*;
+pub use mutex::*;
+pub use rwlock::*;
+#[allow(unused_imports)]
+pub use semaphore::*;
+pub use spinlock::*;
+pub use wait_queue::*;
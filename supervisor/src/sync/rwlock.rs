@@ -0,0 +1,191 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    cell::UnsafeCell,
+    ops,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::sync::WaitQueue;
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Set in the state while a writer holds the lock, the other bits count the
+/// readers.
+const WRITER: usize = 1 << (usize::BITS - 1);
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Lock allowing either multiple readers or a single writer, which blocks the
+/// current thread while it is not available
+pub struct RwLock<T> {
+    state: AtomicUsize,
+    waiters: WaitQueue,
+    value: UnsafeCell<T>,
+}
+
+pub struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+pub struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            waiters: WaitQueue::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Blocks until there is no writer.
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        if !self.acquire_read() {
+            self.waiters.wait_until(|| self.acquire_read());
+        }
+        RwLockReadGuard { lock: self }
+    }
+
+    /// Blocks until there are neither readers nor a writer.
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        if !self.acquire_write() {
+            self.waiters.wait_until(|| self.acquire_write());
+        }
+        RwLockWriteGuard { lock: self }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        self.acquire_read().then(|| RwLockReadGuard { lock: self })
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        self.acquire_write()
+            .then(|| RwLockWriteGuard { lock: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    fn acquire_read(&self) -> bool {
+        self.state
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                (state & WRITER == 0).then_some(state + 1)
+            })
+            .is_ok()
+    }
+
+    fn acquire_write(&self) -> bool {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+unsafe impl<T: Send> Send for RwLock<T> {}
+
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+impl<T> ops::Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        // the last reader lets waiting writers in
+        if self.lock.state.fetch_sub(1, Ordering::Release) == 1 {
+            self.lock.waiters.wake_all();
+        }
+    }
+}
+
+impl<T> ops::Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> ops::DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+        self.lock.waiters.wake_all();
+    }
+}
+
+//==================================================================================================
+// Tests
+//==================================================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readers() {
+        let lock = RwLock::new(1);
+        let first = lock.read();
+        let second = lock.try_read().unwrap();
+
+        assert_eq!(*first + *second, 2);
+        assert!(lock.try_write().is_none());
+        drop(first);
+        assert!(lock.try_write().is_none());
+        drop(second);
+        assert!(lock.try_write().is_some());
+    }
+
+    #[test]
+    fn writer() {
+        let lock = RwLock::new(1);
+        let mut writer = lock.write();
+        *writer += 1;
+
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+        drop(writer);
+        assert_eq!(*lock.read(), 2);
+    }
+}
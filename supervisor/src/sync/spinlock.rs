@@ -0,0 +1,140 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    cell::UnsafeCell,
+    hint, ops,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::x86;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Spinning lock which keeps interrupts disabled while it is held
+///
+/// Suitable for data shared with interrupt handlers, and for short critical
+/// sections which must not sleep.
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+    interrupts_enabled: bool,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Disables interrupts and spins until the lock is acquired, interrupts
+    /// are restored once the guard is dropped.
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        let interrupts_enabled = x86::interrupts_enabled();
+        x86::disable_interrupts();
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+        }
+        SpinLockGuard {
+            lock: self,
+            interrupts_enabled,
+        }
+    }
+
+    /// Acquires the lock if it is free.
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        let interrupts_enabled = x86::interrupts_enabled();
+        x86::disable_interrupts();
+        if self
+            .locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            if interrupts_enabled {
+                x86::enable_interrupts();
+            }
+            return None;
+        }
+        Some(SpinLockGuard {
+            lock: self,
+            interrupts_enabled,
+        })
+    }
+
+    /// Releases the lock without a guard, leaving interrupts alone.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held, and its guard must have been forgotten.
+    pub unsafe fn force_unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+unsafe impl<T: Send> Send for SpinLock<T> {}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> ops::Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> ops::DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+        if self.interrupts_enabled {
+            x86::enable_interrupts();
+        }
+    }
+}
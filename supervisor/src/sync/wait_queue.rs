@@ -0,0 +1,138 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::hint;
+
+use abi::Error;
+use intrusive_collections::{LinkedList, UnsafeRef};
+
+use crate::{
+    process::{Scheduler, Thread, ThreadAdapter},
+    sync::SpinLock,
+    x86::PerCpu,
+};
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Threads blocked until an event happens
+pub struct WaitQueue {
+    threads: SpinLock<LinkedList<ThreadAdapter>>,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            threads: SpinLock::new(LinkedList::new(ThreadAdapter::NEW)),
+        }
+    }
+
+    /// Blocks the current thread until `condition` holds, it is checked again
+    /// after every wakeup.
+    ///
+    /// Spins instead if the scheduler is not running yet on this CPU.
+    pub fn wait_until(&self, mut condition: impl FnMut() -> bool) {
+        if PerCpu::current().current_thread().is_none() {
+            while !condition() {
+                hint::spin_loop();
+            }
+            return;
+        }
+
+        while self.sleep_unless(&mut condition) {}
+    }
+
+    /// Like [`WaitQueue::wait_until`], but fails with [`Error::Cancelled`]
+    /// once the process of the current thread is exiting.
+    pub fn wait_interruptible(&self, mut condition: impl FnMut() -> bool) -> Result<(), Error> {
+        let current = Scheduler::current();
+        let mut done = false;
+        while self.sleep_unless(|| {
+            done = condition();
+            done || current.is_exiting()
+        }) {}
+        match done {
+            true => Ok(()),
+            false => Err(Error::Cancelled),
+        }
+    }
+
+    /// Blocks the current thread until it is woken through this queue, unless
+    /// `f` returns `true`, and returns whether it has blocked.
+    ///
+    /// `f` runs once the thread is queued, so that wakeups caused by anything
+    /// done in `f` can't get lost.
+    pub fn sleep_unless(&self, f: impl FnOnce() -> bool) -> bool {
+        let current = Scheduler::current();
+        {
+            let mut threads = self.threads.lock();
+            threads.push_back(unsafe { UnsafeRef::from_raw(current) });
+            Scheduler::prepare_block();
+        }
+
+        let cancel = f();
+        if cancel {
+            Scheduler::cancel_block();
+        } else {
+            Scheduler::schedule();
+        }
+
+        // the thread is still queued if it didn't block or was woken by other
+        // means
+        self.remove(current);
+        !cancel
+    }
+
+    /// Wakes the thread which has been waiting the longest, and returns
+    /// whether there was one.
+    pub fn wake_one(&self) -> bool {
+        // woken with the queue locked, the thread may be woken by other means
+        // as well and must not return from its wait in between
+        let mut threads = self.threads.lock();
+        match threads.pop_front() {
+            Some(thread) => {
+                Scheduler::wake(unsafe { &*UnsafeRef::into_raw(thread) });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wakes all waiting threads, and returns their number.
+    pub fn wake_all(&self) -> usize {
+        let mut count = 0;
+        while self.wake_one() {
+            count += 1;
+        }
+        count
+    }
+
+    /// Removes a thread if it is still queued.
+    fn remove(&self, thread: &Thread) {
+        let mut threads = self.threads.lock();
+        // a running thread can only be linked into this queue
+        if thread.is_queued() {
+            unsafe { threads.cursor_mut_from_ptr(thread) }.remove();
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use abi::{Error, ResourceKind, Rights};
+
+use crate::{
+    memory::{KernelMemory, PAGE_SIZE},
+    process::{self, Capability, KernelObject, System},
+    syscall::{current_process, handle, notification, object},
+    x86::{self, PerCpu},
+};
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+pub fn resource_create(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let handles = current_process().handles();
+    let capability = handles.get(handle(args[0])?, Rights::DUPLICATE)?;
+    let KernelObject::Resource(resource) = capability.object() else {
+        capability.object().release();
+        return Err(Error::InvalidHandle);
+    };
+    let resource = KernelObject::Resource(resource.subrange(args[1], args[2])?);
+    let handle = handles.insert(Capability::new(resource, capability.rights()))?;
+    Ok(handle as usize)
+}
+
+pub fn io_port_enable(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let (base, count) = (args[1], args[2]);
+    check_resource(args[0], Rights::WRITE, ResourceKind::IO_PORTS, base, count)?;
+    let process = current_process();
+    process.enable_io_ports(base, count)?;
+    // the other threads of the process get the bitmap on their next switch
+    x86::without_interrupts(|| {
+        let (io_bitmap, generation) = process.io_bitmap();
+        PerCpu::current().load_io_bitmap(io_bitmap, generation);
+    });
+    Ok(0)
+}
+
+pub fn mmio_map(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let (phys_addr, size) = (args[1], args[2]);
+    if phys_addr % PAGE_SIZE != 0 || size % PAGE_SIZE != 0 || size == 0 {
+        return Err(Error::InvalidArgument);
+    }
+    check_resource(args[0], Rights::MAP, ResourceKind::MMIO, phys_addr, size)?;
+    // the root resource covers all physical addresses, including RAM
+    if System::get().is_ram(phys_addr, size) {
+        return Err(Error::AccessDenied);
+    }
+
+    let mut memory = current_process().memory();
+    let addr = memory.allocate(None, size).ok_or(Error::NoMemory)?;
+    let mut kernel_memory = KernelMemory::lock();
+    if kernel_memory
+        .map_device(memory.mapping_mut(), addr, phys_addr, size)
+        .is_none()
+    {
+        kernel_memory.unmap_device(memory.mapping_mut(), addr, size);
+        memory.deallocate(addr, size);
+        return Err(Error::NoMemory);
+    }
+    Ok(addr)
+}
+
+pub fn irq_bind(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let irq = args[1];
+    check_resource(args[0], Rights::READ, ResourceKind::IRQ, irq, 1)?;
+    let notification = notification(args[2], Rights::WRITE)?;
+    let result = process::bind_irq(current_process().pid(), irq as u32, notification, args[3]);
+    notification.release();
+    result.map(|()| 0)
+}
+
+pub fn irq_unbind(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let irq = args[1];
+    check_resource(args[0], Rights::READ, ResourceKind::IRQ, irq, 1)?;
+    process::unbind_irq(current_process().pid(), irq as u32)?;
+    Ok(0)
+}
+
+pub fn irq_ack(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let irq = args[1];
+    check_resource(args[0], Rights::READ, ResourceKind::IRQ, irq, 1)?;
+    process::ack_irq(current_process().pid(), irq as u32)?;
+    Ok(0)
+}
+
+/// Checks that the resource of a handle argument covers `size` units of `kind`
+/// from `base`.
+fn check_resource(
+    arg: usize,
+    rights: u32,
+    kind: usize,
+    base: usize,
+    size: usize,
+) -> Result<(), Error> {
+    match object(arg, rights)? {
+        KernelObject::Resource(resource) if resource.covers(kind, base, size) => Ok(()),
+        KernelObject::Resource(_) => Err(Error::AccessDenied),
+        object => {
+            object.release();
+            Err(Error::InvalidHandle)
+        }
+    }
+}
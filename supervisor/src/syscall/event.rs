@@ -0,0 +1,133 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::mem;
+
+use abi::{Error, Event, EventKind, Rights};
+
+use crate::{
+    ipc::{Notification, Port, MAX_PORT_SOURCES},
+    memory::UserPtr,
+    process::{KernelObject, System},
+    syscall::{insert, object},
+};
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+pub fn notification_create(_args: &mut [usize; 6]) -> Result<usize, Error> {
+    let notification = Notification::create().ok_or(Error::NoMemory)?;
+    insert(KernelObject::Notification(notification))
+}
+
+pub fn notification_signal(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let notification = notification(args[0], Rights::WRITE)?;
+    notification.signal(args[1]);
+    notification.release();
+    Ok(0)
+}
+
+pub fn notification_wait(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let notification = notification(args[0], Rights::READ)?;
+    let result = notification.wait();
+    notification.release();
+    result
+}
+
+pub fn port_create(_args: &mut [usize; 6]) -> Result<usize, Error> {
+    let port = Port::create().ok_or(Error::NoMemory)?;
+    insert(KernelObject::Port(port))
+}
+
+pub fn port_bind(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let port = port(args[0], Rights::WRITE)?;
+    let result = object(args[1], Rights::READ).and_then(|source| {
+        let result = bind(port, source, args[2]);
+        source.release();
+        result
+    });
+    port.release();
+    result.map(|()| 0)
+}
+
+pub fn port_set_timer(args: &mut [usize; 6]) -> Result<usize, Error> {
+    #[cfg(target_arch = "x86")]
+    let deadline = args[2] as u64 | (args[3] as u64) << 32;
+    #[cfg(target_arch = "x86_64")]
+    let deadline = args[2] as u64;
+
+    let port = port(args[0], Rights::WRITE)?;
+    let result = port.set_timer(args[1], deadline);
+    port.release();
+    result.map(|()| 0)
+}
+
+pub fn port_wait(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let port = port(args[0], Rights::READ)?;
+    let mut events = [Event::default(); MAX_PORT_SOURCES];
+    let result = port.wait(&mut events[..args[2].min(MAX_PORT_SOURCES)]);
+    port.release();
+    let count = result?;
+
+    for (i, event) in events[..count].iter().enumerate() {
+        let addr = args[1].wrapping_add(i * mem::size_of::<Event>());
+        UserPtr::<[usize; 3]>::new(addr).write(&[event.key, event.kind, event.data])?;
+    }
+    Ok(count)
+}
+
+/// Connects an event source to a port.
+fn bind(port: &'static Port, source: KernelObject, key: usize) -> Result<(), Error> {
+    match source {
+        KernelObject::Notification(notification) => {
+            notification.bind(port.bind(key, EventKind::NOTIFICATION)?);
+        }
+        KernelObject::Endpoint(endpoint) => endpoint.bind(port.bind(key, EventKind::ENDPOINT)?),
+        KernelObject::Process(pid) => {
+            let processes = System::get().processes();
+            let process = processes.get(pid).ok_or(Error::InvalidHandle)?;
+            processes.bind_exit(process, port.bind(key, EventKind::PROCESS_EXIT)?);
+        }
+        KernelObject::Port(_) | KernelObject::Resource(_) => return Err(Error::InvalidHandle),
+    }
+    Ok(())
+}
+
+/// Notification of a handle argument, with a reference the caller has to
+/// release.
+pub fn notification(arg: usize, rights: u32) -> Result<&'static Notification, Error> {
+    match object(arg, rights)? {
+        KernelObject::Notification(notification) => Ok(notification),
+        object => {
+            object.release();
+            Err(Error::InvalidHandle)
+        }
+    }
+}
+
+/// Port of a handle argument, with a reference the caller has to release.
+fn port(arg: usize, rights: u32) -> Result<&'static Port, Error> {
+    match object(arg, rights)? {
+        KernelObject::Port(port) => Ok(port),
+        object => {
+            object.release();
+            Err(Error::InvalidHandle)
+        }
+    }
+}
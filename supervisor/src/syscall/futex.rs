@@ -0,0 +1,71 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::mem;
+
+use abi::Error;
+
+use crate::{
+    ipc,
+    memory::{UserPtr, USER_MEMORY_RANGE},
+    syscall::current_process,
+};
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+pub fn futex_wait(args: &mut [usize; 6]) -> Result<usize, Error> {
+    #[cfg(target_arch = "x86")]
+    let deadline = args[2] as u64 | (args[3] as u64) << 32;
+    #[cfg(target_arch = "x86_64")]
+    let deadline = args[2] as u64;
+
+    let key = futex_key(args[0])?;
+    let futex = UserPtr::<u32>::new(args[0]);
+    let deadline = (deadline != 0).then_some(deadline);
+    ipc::futex_wait(key, deadline, || Ok(futex.read()? == args[1] as u32))?;
+    Ok(0)
+}
+
+pub fn futex_wake(args: &mut [usize; 6]) -> Result<usize, Error> {
+    Ok(ipc::futex_wake(futex_key(args[0])?, args[1]))
+}
+
+pub fn futex_requeue(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let key = futex_key(args[0])?;
+    let target = futex_key(args[3])?;
+    let futex = UserPtr::<u32>::new(args[0]);
+    ipc::futex_requeue(key, args[2], target, args[4], || {
+        Ok(futex.read()? == args[1] as u32)
+    })
+}
+
+/// Physical address of the futex at a user address, which identifies it
+/// across processes.
+fn futex_key(addr: usize) -> Result<usize, Error> {
+    if !addr.is_multiple_of(mem::align_of::<u32>()) {
+        return Err(Error::InvalidArgument);
+    }
+    if !USER_MEMORY_RANGE.contains(&addr) {
+        return Err(Error::Fault);
+    }
+    let memory = current_process().memory();
+    memory.find(addr).ok_or(Error::Fault)?;
+    memory.mapping().translate(addr).ok_or(Error::Fault)
+}
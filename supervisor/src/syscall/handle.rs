@@ -0,0 +1,78 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use abi::{Error, Handle, Rights};
+
+use crate::{
+    process::{Capability, KernelObject},
+    syscall::current_process,
+};
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+pub fn handle_close(args: &mut [usize; 6]) -> Result<usize, Error> {
+    current_process().handles().close(handle(args[0])?)?;
+    Ok(0)
+}
+
+pub fn handle_duplicate(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let handle = current_process()
+        .handles()
+        .duplicate(handle(args[0])?, rights(args[1])?)?;
+    Ok(handle as usize)
+}
+
+pub fn handle_reduce(args: &mut [usize; 6]) -> Result<usize, Error> {
+    current_process()
+        .handles()
+        .reduce(handle(args[0])?, rights(args[1])?)?;
+    Ok(0)
+}
+
+pub fn handle_rights(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let capability = current_process().handles().get(handle(args[0])?, 0)?;
+    capability.object().release();
+    Ok(capability.rights() as usize)
+}
+
+/// Adds a handle with all rights to a new object.
+pub fn insert(object: KernelObject) -> Result<usize, Error> {
+    let handle = current_process()
+        .handles()
+        .insert(Capability::new(object, Rights::ALL))
+        .inspect_err(|_| object.release())?;
+    Ok(handle as usize)
+}
+
+/// Object of a handle argument if the handle allows `rights`, with a
+/// reference the caller has to release.
+pub fn object(arg: usize, rights: u32) -> Result<KernelObject, Error> {
+    let capability = current_process().handles().get(handle(arg)?, rights)?;
+    Ok(capability.object())
+}
+
+/// Handle passed as a system call argument.
+pub fn handle(arg: usize) -> Result<Handle, Error> {
+    Handle::try_from(arg).map_err(|_| Error::InvalidHandle)
+}
+
+fn rights(arg: usize) -> Result<u32, Error> {
+    u32::try_from(arg).map_err(|_| Error::AccessDenied)
+}
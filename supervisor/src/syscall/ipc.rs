@@ -0,0 +1,161 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use abi::{Error, Rights, MAX_MESSAGE_SIZE};
+
+use crate::{
+    ipc::{self, Endpoint},
+    memory::UserSlice,
+    process::{KernelObject, Scheduler},
+    syscall::{current_process, handle, insert, object},
+};
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+pub fn endpoint_create(_args: &mut [usize; 6]) -> Result<usize, Error> {
+    let endpoint = Endpoint::create().ok_or(Error::NoMemory)?;
+    insert(KernelObject::Endpoint(endpoint))
+}
+
+pub fn send(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let endpoint = endpoint(args[0], Rights::WRITE)?;
+    let result =
+        load_message(args).and_then(|()| endpoint.send().inspect_err(|_| unload_message(args)));
+    endpoint.release();
+    result.map(|()| 0)
+}
+
+pub fn receive(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let endpoint = endpoint(args[0], Rights::READ)?;
+    let result = endpoint.receive();
+    endpoint.release();
+    result?;
+    store_message(args)
+}
+
+pub fn call(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let endpoint = endpoint(args[0], Rights::WRITE)?;
+    let result =
+        load_message(args).and_then(|()| endpoint.call().inspect_err(|_| unload_message(args)));
+    endpoint.release();
+    result?;
+    store_message(args)
+}
+
+pub fn reply(args: &mut [usize; 6]) -> Result<usize, Error> {
+    check_reply()?;
+    load_message(args)?;
+    ipc::reply().inspect_err(|_| unload_message(args))?;
+    Ok(0)
+}
+
+pub fn reply_receive(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let endpoint = endpoint(args[0], Rights::READ)?;
+    let result = check_reply()
+        .and_then(|()| load_message(args))
+        .and_then(|()| {
+            endpoint
+                .reply_receive()
+                .inspect_err(|_| unload_message(args))
+        });
+    endpoint.release();
+    result?;
+    store_message(args)
+}
+
+/// Endpoint of a handle argument, with a reference the caller has to release.
+fn endpoint(arg: usize, rights: u32) -> Result<&'static Endpoint, Error> {
+    match object(arg, rights)? {
+        KernelObject::Endpoint(endpoint) => Ok(endpoint),
+        object => {
+            object.release();
+            Err(Error::InvalidHandle)
+        }
+    }
+}
+
+/// Fails unless the current thread has a caller to reply to, which has to be
+/// checked before the capability of the reply is taken from the process.
+fn check_reply() -> Result<(), Error> {
+    match Scheduler::current().ipc().reply_to() {
+        Some(_) => Ok(()),
+        None => Err(Error::InvalidArgument),
+    }
+}
+
+/// Fills the IPC buffer of the current thread with the message in the
+/// arguments.
+fn load_message(args: &[usize; 6]) -> Result<(), Error> {
+    let process = current_process();
+    let message = unsafe { Scheduler::current().ipc().message() };
+    let len = args[2] & 0xFFFF;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(Error::InvalidArgument);
+    }
+    UserSlice::new(args[1], len).read(&mut message.data[..len])?;
+    message.len = len;
+    message.sender = process.pid();
+    message.words = [args[4], args[5]];
+    // taken last, it has to be put back if the message isn't delivered
+    message.capability = match handle(args[3])? {
+        0 => None,
+        handle => Some(process.handles().take(handle)?),
+    };
+    Ok(())
+}
+
+/// Puts the capability of a message which wasn't delivered back under its
+/// handle.
+fn unload_message(args: &[usize; 6]) {
+    let message = unsafe { Scheduler::current().ipc().message() };
+    if let Some(capability) = message.capability.take() {
+        // the handle was valid when the capability was taken
+        current_process()
+            .handles()
+            .restore(handle(args[3]).unwrap(), capability);
+    }
+}
+
+/// Returns the message in the IPC buffer of the current thread through the
+/// arguments, and the pid of its sender.
+fn store_message(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let message = unsafe { Scheduler::current().ipc().message() };
+    let len = message.len.min(args[2] >> 16);
+    let capability = message.capability.take();
+    // written first, the capability must not end up in the handle table of a
+    // caller which only sees an error
+    if let Err(error) = UserSlice::new(args[1], len).write(&message.data[..len]) {
+        if let Some(capability) = capability {
+            capability.object().release();
+        }
+        return Err(error);
+    }
+    args[2] = message.len;
+    args[3] = 0;
+    [args[4], args[5]] = message.words;
+    if let Some(capability) = capability {
+        let handle = current_process()
+            .handles()
+            .insert(capability)
+            .inspect_err(|_| capability.object().release())?;
+        args[3] = handle as usize;
+    }
+    Ok(message.sender as usize)
+}
@@ -0,0 +1,112 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use abi::Error;
+
+use crate::{
+    memory::{UserPtr, USER_MEMORY_RANGE},
+    process::{Pid, Process, Scheduler, SchedulingPolicy, System, Thread, PRIORITY_LEVELS},
+    x86::PerCpu,
+};
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+pub fn exit(args: &mut [usize; 6]) -> Result<usize, Error> {
+    current_process().exit(args[0] as i32);
+    Scheduler::exit();
+}
+
+pub fn exit_thread(_args: &mut [usize; 6]) -> Result<usize, Error> {
+    Scheduler::exit();
+}
+
+pub fn yield_now(_args: &mut [usize; 6]) -> Result<usize, Error> {
+    Scheduler::yield_now();
+    Ok(0)
+}
+
+pub fn sleep(args: &mut [usize; 6]) -> Result<usize, Error> {
+    #[cfg(target_arch = "x86")]
+    let duration = args[0] as u64 | (args[1] as u64) << 32;
+    #[cfg(target_arch = "x86_64")]
+    let duration = args[0] as u64;
+
+    Thread::sleep(duration)?;
+    Ok(0)
+}
+
+pub fn get_pid(_args: &mut [usize; 6]) -> Result<usize, Error> {
+    Ok(current_process().pid() as usize)
+}
+
+pub fn get_parent_pid(_args: &mut [usize; 6]) -> Result<usize, Error> {
+    Ok(current_process().parent() as usize)
+}
+
+pub fn wait(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let pid = Pid::try_from(args[0]).map_err(|_| Error::InvalidArgument)?;
+    let status = UserPtr::<i32>::new(args[1]);
+    let (pid, exit_code) = System::get()
+        .processes()
+        .wait(current_process(), (pid != 0).then_some(pid))?;
+    if !status.is_null() {
+        status.write(&exit_code)?;
+    }
+    Ok(pid as usize)
+}
+
+pub fn set_thread_pointer(args: &mut [usize; 6]) -> Result<usize, Error> {
+    // a non-canonical FS base would fault on return to user mode
+    if args[0] != 0 && !USER_MEMORY_RANGE.contains(&args[0]) {
+        return Err(Error::InvalidArgument);
+    }
+    Scheduler::current().set_thread_pointer(args[0]);
+    Ok(0)
+}
+
+pub fn set_scheduling(args: &mut [usize; 6]) -> Result<usize, Error> {
+    #[cfg(target_arch = "x86")]
+    let deadline = args[3] as u64 | (args[4] as u64) << 32;
+    #[cfg(target_arch = "x86_64")]
+    let deadline = args[3] as u64;
+
+    let policy = match args[0] {
+        abi::SchedulingPolicy::DEADLINE => SchedulingPolicy::Deadline,
+        abi::SchedulingPolicy::FIXED_PRIORITY => SchedulingPolicy::FixedPriority,
+        abi::SchedulingPolicy::ROUND_ROBIN => SchedulingPolicy::RoundRobin,
+        _ => return Err(Error::InvalidArgument),
+    };
+    let priority = u8::try_from(args[1])
+        .ok()
+        .filter(|priority| (*priority as usize) < PRIORITY_LEVELS)
+        .ok_or(Error::InvalidArgument)?;
+    let affinity = args[2] as u32;
+    if !(0..PerCpu::count()).any(|id| affinity & 1 << id != 0) {
+        return Err(Error::InvalidArgument);
+    }
+
+    Scheduler::set_scheduling(Scheduler::current(), policy, priority, deadline, affinity);
+    Ok(0)
+}
+
+/// Process of the calling thread, system calls only come from user threads.
+pub fn current_process() -> &'static Process {
+    Scheduler::current().process().unwrap()
+}
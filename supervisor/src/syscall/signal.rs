@@ -0,0 +1,77 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use abi::{Error, Rights, SignalAction, SignalMaskHow, SignalSet};
+
+use crate::{
+    memory::UserPtr,
+    process::{self, KernelObject, Scheduler, System},
+    syscall::{current_process, handle, object},
+};
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+pub fn signal_action(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let process = current_process();
+    let previous = match args[1] {
+        0 => process.signals().action(args[0])?,
+        addr => {
+            let action = UserPtr::<SignalAction>::new(addr).read()?;
+            process.signals().set_action(args[0], action)?
+        }
+    };
+    if args[2] != 0 {
+        UserPtr::new(args[2]).write(&previous)?;
+    }
+    Ok(0)
+}
+
+pub fn signal_mask(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let thread = Scheduler::current();
+    let previous = thread.signal_mask();
+    let set = args[1] as SignalSet;
+    let mask = match args[0] {
+        SignalMaskHow::BLOCK => previous | set,
+        SignalMaskHow::UNBLOCK => previous & !set,
+        SignalMaskHow::SET => set,
+        _ => return Err(Error::InvalidArgument),
+    };
+    thread.set_signal_mask(mask);
+    Ok(previous as usize)
+}
+
+pub fn signal_send(args: &mut [usize; 6]) -> Result<usize, Error> {
+    let sender = current_process();
+    let target = match handle(args[0])? {
+        0 => sender,
+        _ => match object(args[0], Rights::WRITE)? {
+            KernelObject::Process(pid) => System::get()
+                .processes()
+                .get(pid)
+                .ok_or(Error::InvalidHandle)?,
+            object => {
+                object.release();
+                return Err(Error::InvalidHandle);
+            }
+        },
+    };
+    process::send_signal(target, args[1], sender.pid())?;
+    Ok(0)
+}
@@ -0,0 +1,104 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use abi::{Error, Syscall};
+
+use crate::syscall::{
+    call, endpoint_create, exit, exit_thread, futex_requeue, futex_wait, futex_wake,
+    get_parent_pid, get_pid, handle_close, handle_duplicate, handle_reduce, handle_rights,
+    io_port_enable, irq_ack, irq_bind, irq_unbind, mmio_map, notification_create,
+    notification_signal, notification_wait, port_bind, port_create, port_set_timer, port_wait,
+    receive, reply, reply_receive, resource_create, send, set_scheduling, set_thread_pointer,
+    signal_action, signal_mask, signal_send, sleep, wait, yield_now,
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Number of entries in the dispatch table, larger numbers are invalid.
+pub const SYSCALL_COUNT: usize = 64;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static SYSCALL_TABLE: [Option<SyscallHandler>; SYSCALL_COUNT] = {
+    let mut table: [Option<SyscallHandler>; SYSCALL_COUNT] = [None; SYSCALL_COUNT];
+    table[Syscall::EXIT] = Some(exit);
+    table[Syscall::EXIT_THREAD] = Some(exit_thread);
+    table[Syscall::YIELD] = Some(yield_now);
+    table[Syscall::GET_PID] = Some(get_pid);
+    table[Syscall::GET_PARENT_PID] = Some(get_parent_pid);
+    table[Syscall::WAIT] = Some(wait);
+    table[Syscall::HANDLE_CLOSE] = Some(handle_close);
+    table[Syscall::HANDLE_DUPLICATE] = Some(handle_duplicate);
+    table[Syscall::HANDLE_REDUCE] = Some(handle_reduce);
+    table[Syscall::HANDLE_RIGHTS] = Some(handle_rights);
+    table[Syscall::ENDPOINT_CREATE] = Some(endpoint_create);
+    table[Syscall::SEND] = Some(send);
+    table[Syscall::RECEIVE] = Some(receive);
+    table[Syscall::CALL] = Some(call);
+    table[Syscall::REPLY] = Some(reply);
+    table[Syscall::REPLY_RECEIVE] = Some(reply_receive);
+    table[Syscall::NOTIFICATION_CREATE] = Some(notification_create);
+    table[Syscall::NOTIFICATION_SIGNAL] = Some(notification_signal);
+    table[Syscall::NOTIFICATION_WAIT] = Some(notification_wait);
+    table[Syscall::PORT_CREATE] = Some(port_create);
+    table[Syscall::PORT_BIND] = Some(port_bind);
+    table[Syscall::PORT_SET_TIMER] = Some(port_set_timer);
+    table[Syscall::PORT_WAIT] = Some(port_wait);
+    table[Syscall::RESOURCE_CREATE] = Some(resource_create);
+    table[Syscall::IO_PORT_ENABLE] = Some(io_port_enable);
+    table[Syscall::MMIO_MAP] = Some(mmio_map);
+    table[Syscall::IRQ_BIND] = Some(irq_bind);
+    table[Syscall::IRQ_UNBIND] = Some(irq_unbind);
+    table[Syscall::IRQ_ACK] = Some(irq_ack);
+    table[Syscall::SIGNAL_ACTION] = Some(signal_action);
+    table[Syscall::SIGNAL_MASK] = Some(signal_mask);
+    table[Syscall::SIGNAL_SEND] = Some(signal_send);
+    table[Syscall::FUTEX_WAIT] = Some(futex_wait);
+    table[Syscall::FUTEX_WAKE] = Some(futex_wake);
+    table[Syscall::FUTEX_REQUEUE] = Some(futex_requeue);
+    table[Syscall::SET_THREAD_POINTER] = Some(set_thread_pointer);
+    table[Syscall::SET_SCHEDULING] = Some(set_scheduling);
+    table[Syscall::SLEEP] = Some(sleep);
+    table
+};
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Handler of a single system call, called with all six arguments, which it
+/// may change to return more values.
+pub type SyscallHandler = fn(&mut [usize; 6]) -> Result<usize, Error>;
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Runs the handler of system call `number` on behalf of the current thread,
+/// and returns its encoded result.
+pub fn dispatch(number: usize, args: &mut [usize; 6]) -> usize {
+    let result = match SYSCALL_TABLE.get(number).copied().flatten() {
+        Some(handler) => handler(args),
+        None => Err(Error::NoSyscall),
+    };
+    abi::encode_result(result)
+}
@@ -0,0 +1,145 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use crate::{
+    process::System,
+    x86::{self, cpuid, rdtsc, Hpet, LocalApic, Pit},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Duration of each calibration run.
+const CALIBRATION_NS: u64 = 10_000_000;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Monotonic system clock
+pub struct Clock {
+    source: ClockSource,
+    /// Counter value at boot.
+    base: u64,
+    /// Nanoseconds per counter tick as 32.32 fixed point.
+    scale: u64,
+
+    tsc_frequency: u64,
+    timer_frequency: u64,
+}
+
+enum ClockSource {
+    Tsc,
+    Hpet(&'static Hpet),
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Clock {
+    /// Calibrates the TSC and the local APIC timer against the HPET or, if not
+    /// present, the PIT and selects the clock source.
+    ///
+    /// Must be called on the bootstrap processor after the local APIC has been
+    /// initialized, the other CPUs share the result.
+    pub fn new() -> Self {
+        let hpet = Hpet::init();
+
+        let (tsc_ticks, timer_ticks, ns) = x86::without_interrupts(|| {
+            let mut ns = 0;
+            let mut tsc_ticks = 0;
+            let timer_ticks = LocalApic::measure_timer(|| {
+                let tsc_start = rdtsc();
+                ns = match hpet {
+                    Some(hpet) => hpet.wait(CALIBRATION_NS),
+                    None => Pit::wait(CALIBRATION_NS),
+                };
+                tsc_ticks = rdtsc() - tsc_start;
+            });
+            (tsc_ticks, timer_ticks as u64, ns)
+        });
+        let tsc_frequency = tsc_ticks * 1_000_000_000 / ns;
+        let timer_frequency = timer_ticks * 1_000_000_000 / ns;
+
+        // the TSC is only usable as clock source if it runs at a constant
+        // rate regardless of power states, a 32-bit HPET would wrap within
+        // minutes and make the clock jump back
+        let invariant_tsc =
+            cpuid(0x80000000, 0)[0] >= 0x80000007 && cpuid(0x80000007, 0)[3] & (1 << 8) != 0;
+        let (source, frequency) = match hpet {
+            Some(hpet) if !invariant_tsc && hpet.is_64bit() => {
+                (ClockSource::Hpet(hpet), hpet.frequency())
+            }
+            _ => (ClockSource::Tsc, tsc_frequency),
+        };
+
+        let mut this = Self {
+            source,
+            base: 0,
+            scale: ((1_000_000_000u128 << 32) / frequency as u128) as u64,
+            tsc_frequency,
+            timer_frequency,
+        };
+        this.base = this.ticks();
+        this
+    }
+
+    /// Returns the clock owned by the [`System`].
+    pub fn get() -> &'static Self {
+        System::get().clock()
+    }
+
+    /// Nanoseconds since the clock was initialized.
+    pub fn now(&self) -> u64 {
+        let ticks = self.ticks().wrapping_sub(self.base);
+        ((ticks as u128 * self.scale as u128) >> 32) as u64
+    }
+
+    /// Frequency of the time-stamp counter in Hz.
+    pub fn tsc_frequency(&self) -> u64 {
+        self.tsc_frequency
+    }
+
+    /// Frequency of the local APIC timer in Hz, after division.
+    pub fn timer_frequency(&self) -> u64 {
+        self.timer_frequency
+    }
+
+    /// Converts a duration into local APIC timer ticks.
+    pub fn timer_ticks(&self, ns: u64) -> u64 {
+        (ns as u128 * self.timer_frequency as u128 / 1_000_000_000) as u64
+    }
+
+    fn ticks(&self) -> u64 {
+        match self.source {
+            ClockSource::Tsc => rdtsc(),
+            ClockSource::Hpet(hpet) => hpet.counter(),
+        }
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Nanoseconds since boot.
+pub fn now() -> u64 {
+    Clock::get().now()
+}
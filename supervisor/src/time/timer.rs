@@ -0,0 +1,210 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{cell::Cell, hint, ptr};
+
+use intrusive_collections::{intrusive_adapter, KeyAdapter, RBTree, RBTreeLink, UnsafeRef};
+
+use crate::{
+    sync::SpinLock,
+    time::{now, Clock},
+    x86::{self, register_interrupt_handler, InterruptFrame, LocalApic, PerCpu, TIMER_VECTOR},
+};
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// One-shot or periodic timer event
+///
+/// The callback is run in interrupt context on the CPU the timer was armed on.
+/// A timer has to be cancelled before it is freed.
+pub struct Timer {
+    link: RBTreeLink,
+    deadline: Cell<u64>,
+    period: Cell<u64>,
+    queue: Cell<*const TimerQueue>,
+    /// Queue the timer was last armed on, which may still run its callback
+    /// after it was disarmed.
+    last_queue: Cell<*const TimerQueue>,
+
+    callback: fn(usize),
+    data: usize,
+}
+
+/// Pending timers of a single CPU, ordered by deadline
+pub struct TimerQueue {
+    timers: SpinLock<RBTree<TimerAdapter>>,
+    /// Timer whose callback is running, protected by the `timers` lock.
+    running: Cell<*const Timer>,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Timer {
+    pub const fn new(callback: fn(usize), data: usize) -> Self {
+        Self {
+            link: RBTreeLink::new(),
+            deadline: Cell::new(0),
+            period: Cell::new(0),
+            queue: Cell::new(ptr::null()),
+            last_queue: Cell::new(ptr::null()),
+            callback,
+            data,
+        }
+    }
+
+    /// Arms the timer on the current CPU to expire at `deadline` in
+    /// nanoseconds since boot, and then every `period` nanoseconds unless
+    /// `period` is 0.
+    ///
+    /// A timer which is already armed is re-armed.
+    pub fn arm(&'static self, deadline: u64, period: u64) {
+        self.cancel();
+        x86::without_interrupts(|| {
+            let queue = PerCpu::current().timer_queue();
+            let mut timers = queue.timers.lock();
+            self.deadline.set(deadline);
+            self.period.set(period);
+            self.queue.set(queue);
+            self.last_queue.set(queue);
+            timers.insert(unsafe { UnsafeRef::from_raw(self) });
+            queue.program(&timers);
+        });
+    }
+
+    /// Disarms the timer and returns whether it was armed.
+    ///
+    /// Waits for a callback running on another CPU to return, so that the
+    /// timer can be freed afterwards.
+    pub fn cancel(&self) -> bool {
+        x86::without_interrupts(|| {
+            let queue = self.last_queue.get();
+            if queue.is_null() {
+                return false;
+            }
+            let queue = unsafe { &*queue };
+
+            let mut timers = queue.timers.lock();
+            // the timer might have expired while waiting for the lock
+            let armed = ptr::eq(self.queue.get(), queue);
+            if armed {
+                unsafe { timers.cursor_mut_from_ptr(self) }.remove();
+                self.queue.set(ptr::null());
+            }
+            // the callback itself may cancel the timer on the current CPU
+            if !ptr::eq(queue, PerCpu::current().timer_queue()) {
+                while ptr::eq(queue.running.get(), self) {
+                    drop(timers);
+                    hint::spin_loop();
+                    timers = queue.timers.lock();
+                }
+            }
+            armed
+        })
+    }
+}
+
+impl TimerQueue {
+    pub const fn new() -> Self {
+        Self {
+            timers: SpinLock::new(RBTree::new(TimerAdapter::NEW)),
+            running: Cell::new(ptr::null()),
+        }
+    }
+
+    /// Registers the interrupt handler which drives all timer queues.
+    pub fn init() {
+        register_interrupt_handler(TIMER_VECTOR, timer_interrupt);
+    }
+
+    /// Runs the callbacks of all expired timers and programs the local APIC
+    /// timer for the next one.
+    fn expire(&self) {
+        loop {
+            let mut timers = self.timers.lock();
+            // the previous callback returned
+            self.running.set(ptr::null());
+            let mut cursor = timers.front_mut();
+            if !cursor
+                .get()
+                .is_some_and(|timer| timer.deadline.get() <= now())
+            {
+                self.program(&timers);
+                return;
+            }
+            let timer = unsafe { &*UnsafeRef::into_raw(cursor.remove().unwrap()) };
+
+            let period = timer.period.get();
+            if period != 0 {
+                timer.deadline.set(timer.deadline.get() + period);
+                timers.insert(unsafe { UnsafeRef::from_raw(timer) });
+            } else {
+                timer.queue.set(ptr::null());
+            }
+            self.running.set(timer);
+            drop(timers);
+
+            (timer.callback)(timer.data);
+        }
+    }
+
+    /// Programs the local APIC timer for the earliest deadline.
+    fn program(&self, timers: &RBTree<TimerAdapter>) {
+        let Some(timer) = timers.front().get() else {
+            LocalApic::stop_timer();
+            return;
+        };
+        let ticks = Clock::get().timer_ticks(timer.deadline.get().saturating_sub(now()));
+        LocalApic::start_timer(ticks.clamp(1, u32::MAX as u64) as u32, false);
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+unsafe impl Sync for Timer {}
+
+unsafe impl Send for TimerQueue {}
+
+unsafe impl Sync for TimerQueue {}
+
+intrusive_adapter!(TimerAdapter = UnsafeRef<Timer>: Timer { link: RBTreeLink });
+
+impl KeyAdapter<'_> for TimerAdapter {
+    type Key = u64;
+
+    fn get_key(
+        &self,
+        value: &'_ <Self::PointerOps as intrusive_collections::PointerOps>::Value,
+    ) -> Self::Key {
+        value.deadline.get()
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+fn timer_interrupt(_frame: &mut InterruptFrame) {
+    LocalApic::eoi();
+    PerCpu::current().timer_queue().expire();
+}
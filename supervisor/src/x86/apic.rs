@@ -0,0 +1,276 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    hint, ptr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use spin::Once;
+
+use crate::{
+    acpi::{AcpiTables, Madt, MadtEntry, MadtInterruptFlags},
+    memory::{KernelMemory, PageTableEntryFlags},
+    x86::{outb, rdmsr, wrmsr, CpuFeature, CpuInfo, Msr},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Vector the legacy PICs are remapped to before being masked, so that
+/// spurious interrupts don't collide with exceptions.
+pub const PIC_VECTOR_BASE: u8 = 0x20;
+pub const TIMER_VECTOR: u8 = 0x30;
+/// Vector of the IPI asking another CPU to preempt its current thread.
+pub const RESCHEDULE_VECTOR: u8 = 0x31;
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+/// Base of the MSRs mirroring the registers in x2APIC mode.
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+#[allow(non_snake_case)]
+mod ApicBase {
+    pub const X2APIC_ENABLE: u64 = 1 << 10;
+    pub const ENABLE: u64 = 1 << 11;
+}
+
+/// Divisor applied to the bus clock before it reaches the timer.
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+#[allow(non_snake_case)]
+mod LocalApicRegister {
+    pub const ID: usize = 0x020;
+    pub const EOI: usize = 0x0B0;
+    pub const SVR: usize = 0x0F0;
+    pub const LVT_TIMER: usize = 0x320;
+    pub const LVT_LINT0: usize = 0x350;
+    pub const LVT_LINT1: usize = 0x360;
+    pub const TIMER_INITIAL_COUNT: usize = 0x380;
+    pub const TIMER_CURRENT_COUNT: usize = 0x390;
+    pub const TIMER_DIVIDE: usize = 0x3E0;
+    pub const ICR_LOW: usize = 0x300;
+    pub const ICR_HIGH: usize = 0x310;
+}
+
+#[allow(non_snake_case)]
+mod LocalApicLvt {
+    pub const NMI: u32 = 0b100 << 8;
+    pub const ACTIVE_LOW: u32 = 1 << 13;
+    pub const MASKED: u32 = 1 << 16;
+    pub const PERIODIC: u32 = 1 << 17;
+}
+
+#[allow(non_snake_case)]
+pub mod LocalApicIcr {
+    pub const FIXED: u32 = 0b000 << 8;
+    pub const NMI: u32 = 0b100 << 8;
+    pub const INIT: u32 = 0b101 << 8;
+    pub const STARTUP: u32 = 0b110 << 8;
+    pub const PENDING: u32 = 1 << 12;
+    pub const ASSERT: u32 = 1 << 14;
+    pub const ALL_EXCLUDING_SELF: u32 = 0b11 << 18;
+}
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static LOCAL_APIC: Once<usize> = Once::new();
+
+static X2APIC: AtomicBool = AtomicBool::new(false);
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Local APIC of the current CPU
+pub struct LocalApic;
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl LocalApic {
+    /// Enables the local APIC of the current CPU, in x2APIC mode if supported.
+    /// The bootstrap processor also maps the registers and disables the legacy
+    /// PICs.
+    pub fn init() {
+        let madt = AcpiTables::get().and_then(AcpiTables::madt);
+        LOCAL_APIC.call_once(|| {
+            if madt.is_none_or(|madt| madt.flags() & Madt::PCAT_COMPAT != 0) {
+                disable_pic();
+            }
+
+            if CpuInfo::get().has(CpuFeature::X2APIC) {
+                X2APIC.store(true, Ordering::Relaxed);
+                return 0;
+            }
+
+            // the registers are at the same address for all CPUs
+            let phys_addr = match madt {
+                Some(madt) => madt.local_apic_address() as usize,
+                None => unsafe { rdmsr(Msr::APIC_BASE) as usize & !0xFFF },
+            };
+            KernelMemory::lock()
+                .map_physical(
+                    phys_addr,
+                    0x1000,
+                    PageTableEntryFlags::RW | PageTableEntryFlags::PCD,
+                )
+                .unwrap()
+        });
+        if X2APIC.load(Ordering::Relaxed) {
+            unsafe {
+                let apic_base = rdmsr(Msr::APIC_BASE);
+                wrmsr(
+                    Msr::APIC_BASE,
+                    apic_base | ApicBase::ENABLE | ApicBase::X2APIC_ENABLE,
+                );
+            }
+        }
+
+        Self::write(LocalApicRegister::SVR, 1 << 8 | SPURIOUS_VECTOR as u32);
+        Self::write(LocalApicRegister::TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+        Self::write(LocalApicRegister::LVT_TIMER, LocalApicLvt::MASKED);
+        if let Some(madt) = madt {
+            Self::init_nmi(madt);
+        }
+    }
+
+    pub fn id() -> u32 {
+        if X2APIC.load(Ordering::Relaxed) {
+            Self::read(LocalApicRegister::ID)
+        } else {
+            Self::read(LocalApicRegister::ID) >> 24
+        }
+    }
+
+    /// Signals the end of the interrupt currently being handled.
+    pub fn eoi() {
+        Self::write(LocalApicRegister::EOI, 0);
+    }
+
+    /// Starts the timer, which raises `TIMER_VECTOR` after `count` ticks.
+    pub fn start_timer(count: u32, periodic: bool) {
+        let mode = if periodic { LocalApicLvt::PERIODIC } else { 0 };
+        Self::write(LocalApicRegister::LVT_TIMER, mode | TIMER_VECTOR as u32);
+        Self::write(LocalApicRegister::TIMER_INITIAL_COUNT, count);
+    }
+
+    pub fn stop_timer() {
+        Self::write(LocalApicRegister::TIMER_INITIAL_COUNT, 0);
+        Self::write(LocalApicRegister::LVT_TIMER, LocalApicLvt::MASKED);
+    }
+
+    /// Sends an inter-processor interrupt, `command` being a combination of
+    /// [`LocalApicIcr`] flags and the vector.
+    pub fn send_ipi(apic_id: u32, command: u32) {
+        if X2APIC.load(Ordering::Relaxed) {
+            let icr = X2APIC_MSR_BASE + (LocalApicRegister::ICR_LOW >> 4) as u32;
+            unsafe { wrmsr(icr, (apic_id as u64) << 32 | command as u64) };
+            return;
+        }
+
+        Self::write(LocalApicRegister::ICR_HIGH, apic_id << 24);
+        Self::write(LocalApicRegister::ICR_LOW, command);
+        while Self::read(LocalApicRegister::ICR_LOW) & LocalApicIcr::PENDING != 0 {
+            hint::spin_loop();
+        }
+    }
+
+    /// Returns the number of timer ticks which elapsed while running `f`,
+    /// without raising an interrupt.
+    pub fn measure_timer(f: impl FnOnce()) -> u32 {
+        Self::write(LocalApicRegister::LVT_TIMER, LocalApicLvt::MASKED);
+        Self::write(LocalApicRegister::TIMER_INITIAL_COUNT, u32::MAX);
+        f();
+        let count = Self::read(LocalApicRegister::TIMER_CURRENT_COUNT);
+        Self::write(LocalApicRegister::TIMER_INITIAL_COUNT, 0);
+        u32::MAX - count
+    }
+
+    /// Sets up the LINT pins which the MADT reports as wired to NMI for the
+    /// current CPU.
+    fn init_nmi(madt: Madt<'_>) {
+        let id = Self::id();
+        let processor_id = madt.entries().find_map(|entry| match entry {
+            MadtEntry::LocalApic(entry) if entry.apic_id as u32 == id => Some(entry.processor_id),
+            _ => None,
+        });
+        for entry in madt.entries() {
+            let MadtEntry::LocalApicNmi(entry) = entry else {
+                continue;
+            };
+            if entry.processor_id != 0xFF && Some(entry.processor_id) != processor_id {
+                continue;
+            }
+            let register = match entry.lint {
+                0 => LocalApicRegister::LVT_LINT0,
+                1 => LocalApicRegister::LVT_LINT1,
+                _ => continue,
+            };
+            // NMIs are always edge-triggered
+            let mut lvt = LocalApicLvt::NMI;
+            if entry.flags & MadtInterruptFlags::POLARITY_MASK == MadtInterruptFlags::POLARITY_LOW {
+                lvt |= LocalApicLvt::ACTIVE_LOW;
+            }
+            Self::write(register, lvt);
+        }
+    }
+
+    fn read(register: usize) -> u32 {
+        if X2APIC.load(Ordering::Relaxed) {
+            return unsafe { rdmsr(X2APIC_MSR_BASE + (register >> 4) as u32) } as u32;
+        }
+        unsafe { ptr::read_volatile((LOCAL_APIC.get().unwrap() + register) as *const u32) }
+    }
+
+    fn write(register: usize, value: u32) {
+        if X2APIC.load(Ordering::Relaxed) {
+            return unsafe { wrmsr(X2APIC_MSR_BASE + (register >> 4) as u32, value as u64) };
+        }
+        unsafe { ptr::write_volatile((LOCAL_APIC.get().unwrap() + register) as *mut u32, value) }
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Remaps the legacy PICs away from the exception vectors and masks all of
+/// their interrupts.
+fn disable_pic() {
+    unsafe {
+        // ICW1: initialize, ICW4 follows
+        outb(0x20, 0x11);
+        outb(0xA0, 0x11);
+        // ICW2: vector base
+        outb(0x21, PIC_VECTOR_BASE);
+        outb(0xA1, PIC_VECTOR_BASE + 8);
+        // ICW3: cascade on IRQ 2
+        outb(0x21, 1 << 2);
+        outb(0xA1, 2);
+        // ICW4: 8086 mode
+        outb(0x21, 0x01);
+        outb(0xA1, 0x01);
+        // mask everything
+        outb(0x21, 0xFF);
+        outb(0xA1, 0xFF);
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{arch::asm, mem};
+
+use crate::memory::USER_MEMORY_RANGE;
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Frames walked at most, in case the chain is corrupted into a loop.
+const MAX_BACKTRACE_DEPTH: usize = 32;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Return addresses along the chain of saved frame pointers, innermost first
+///
+/// The walk ends at a null or misplaced frame pointer, thread entry points
+/// clear it and the boot stack starts with one pointing past its end.
+pub struct Backtrace {
+    frame: usize,
+    depth: usize,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Backtrace {
+    /// Walks the chain starting with the frame of the caller.
+    #[inline(always)]
+    pub fn current() -> Self {
+        let frame: usize;
+        unsafe {
+            #[cfg(target_arch = "x86")]
+            asm!("mov {}, ebp", out(reg) frame, options(nomem, nostack, preserves_flags));
+            #[cfg(target_arch = "x86_64")]
+            asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack, preserves_flags));
+        }
+        Self::new(frame)
+    }
+
+    /// Walks the chain starting with the frame at `frame`, like one saved in an
+    /// interrupt frame.
+    pub fn new(frame: usize) -> Self {
+        Self { frame, depth: 0 }
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+impl Iterator for Backtrace {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let frame = self.frame;
+        if self.depth == MAX_BACKTRACE_DEPTH
+            || frame < USER_MEMORY_RANGE.end
+            || !frame.is_multiple_of(mem::size_of::<usize>())
+        {
+            return None;
+        }
+
+        // the saved frame pointer, followed by the return address
+        let (next, addr) = unsafe {
+            let frame = frame as *const usize;
+            (frame.read(), frame.add(1).read())
+        };
+        if addr == 0 {
+            return None;
+        }
+        // callers are further up the stack, anything else ends the walk
+        self.frame = if next > frame { next } else { 0 };
+        self.depth += 1;
+        Some(addr)
+    }
+}
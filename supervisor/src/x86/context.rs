@@ -0,0 +1,100 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{cell::Cell, mem, ptr};
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+extern "C" {
+    #[cfg(not(test))]
+    fn switch_context(prev: *mut usize, next: usize);
+    fn thread_start();
+}
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Register context of a thread which is not running
+///
+/// Only the stack pointer is kept here, the callee-saved registers and the
+/// return address are stored on the kernel stack of the thread itself.
+pub struct Context {
+    sp: Cell<usize>,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Context {
+    /// Prepares the stack ending at `stack_top` so that the first switch to
+    /// it calls `thread_main(entry, arg)`.
+    pub fn new(stack_top: usize, entry: usize, arg: usize) -> Self {
+        // the return address is placed so that the stack is 16-byte aligned
+        // when thread_start calls thread_main
+        #[cfg(target_arch = "x86")]
+        let frame: [usize; 5] = [
+            0,                                               // edi
+            arg,                                             // esi
+            entry,                                           // ebx
+            0,                                               // ebp
+            thread_start as unsafe extern "C" fn() as usize, // return address
+        ];
+        #[cfg(target_arch = "x86")]
+        let sp = (stack_top & !0xF) - 8 - mem::size_of_val(&frame);
+        #[cfg(target_arch = "x86_64")]
+        let frame: [usize; 7] = [
+            0,                                               // r15
+            0,                                               // r14
+            0,                                               // r13
+            arg,                                             // r12
+            entry,                                           // rbx
+            0,                                               // rbp
+            thread_start as unsafe extern "C" fn() as usize, // return address
+        ];
+        #[cfg(target_arch = "x86_64")]
+        let sp = (stack_top & !0xF) - mem::size_of_val(&frame);
+
+        unsafe { ptr::copy_nonoverlapping(frame.as_ptr(), sp as *mut usize, frame.len()) };
+        Self { sp: Cell::new(sp) }
+    }
+
+    /// Context of the flow of execution already running, which is filled in
+    /// on the first switch away from it.
+    pub const fn bootstrap() -> Self {
+        Self { sp: Cell::new(0) }
+    }
+
+    /// Saves the current context into `self` and continues with `next`,
+    /// returns once `self` is switched to again.
+    ///
+    /// # Safety
+    ///
+    /// `next` has to be a context which is not running on any CPU, and both
+    /// contexts have to stay alive until the switch is complete.
+    pub unsafe fn switch(&self, next: &Context) {
+        #[cfg(not(test))]
+        switch_context(self.sp.as_ptr(), next.sp.get());
+        // the unit tests don't link the assembly, and never block
+        #[cfg(test)]
+        unreachable!("Switch to {:#x} in a unit test", next.sp.get());
+    }
+}
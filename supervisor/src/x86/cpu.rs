@@ -0,0 +1,298 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{fmt, str};
+
+use spin::Once;
+
+use crate::x86::{cpuid, read_cr0, read_cr4, write_cr0, write_cr4, Cr0, Cr4};
+#[cfg(target_arch = "x86_64")]
+use crate::x86::{rdmsr, wrmsr, Efer, Msr};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+#[allow(non_snake_case)]
+pub mod CpuFeature {
+    pub const FPU: u32 = 1 << 0;
+    pub const TSC: u32 = 1 << 1;
+    pub const MSR: u32 = 1 << 2;
+    pub const PAE: u32 = 1 << 3;
+    pub const APIC: u32 = 1 << 4;
+    pub const PGE: u32 = 1 << 5;
+    pub const FXSR: u32 = 1 << 6;
+    pub const SSE: u32 = 1 << 7;
+    pub const SSE2: u32 = 1 << 8;
+    pub const PCID: u32 = 1 << 9;
+    pub const X2APIC: u32 = 1 << 10;
+    pub const TSC_DEADLINE: u32 = 1 << 11;
+    pub const XSAVE: u32 = 1 << 12;
+    pub const AVX: u32 = 1 << 13;
+    pub const RDRAND: u32 = 1 << 14;
+    pub const SMEP: u32 = 1 << 15;
+    pub const INVPCID: u32 = 1 << 16;
+    pub const SMAP: u32 = 1 << 17;
+    pub const UMIP: u32 = 1 << 18;
+    pub const NX: u32 = 1 << 19;
+    pub const PAGE_1GB: u32 = 1 << 20;
+    pub const LONG_MODE: u32 = 1 << 21;
+    pub const XSAVEOPT: u32 = 1 << 22;
+}
+
+const CPU_FEATURE_NAMES: [(u32, &str); 23] = [
+    (CpuFeature::FPU, "fpu"),
+    (CpuFeature::TSC, "tsc"),
+    (CpuFeature::MSR, "msr"),
+    (CpuFeature::PAE, "pae"),
+    (CpuFeature::APIC, "apic"),
+    (CpuFeature::PGE, "pge"),
+    (CpuFeature::FXSR, "fxsr"),
+    (CpuFeature::SSE, "sse"),
+    (CpuFeature::SSE2, "sse2"),
+    (CpuFeature::PCID, "pcid"),
+    (CpuFeature::X2APIC, "x2apic"),
+    (CpuFeature::TSC_DEADLINE, "tsc_deadline"),
+    (CpuFeature::XSAVE, "xsave"),
+    (CpuFeature::AVX, "avx"),
+    (CpuFeature::RDRAND, "rdrand"),
+    (CpuFeature::SMEP, "smep"),
+    (CpuFeature::INVPCID, "invpcid"),
+    (CpuFeature::SMAP, "smap"),
+    (CpuFeature::UMIP, "umip"),
+    (CpuFeature::NX, "nx"),
+    (CpuFeature::PAGE_1GB, "pdpe1gb"),
+    (CpuFeature::LONG_MODE, "lm"),
+    (CpuFeature::XSAVEOPT, "xsaveopt"),
+];
+
+/// Features the supervisor can't run without.
+#[cfg(target_arch = "x86")]
+const CPU_FEATURE_REQUIRED: u32 =
+    CpuFeature::FPU | CpuFeature::TSC | CpuFeature::MSR | CpuFeature::APIC | CpuFeature::FXSR;
+#[cfg(target_arch = "x86_64")]
+const CPU_FEATURE_REQUIRED: u32 = CpuFeature::FPU
+    | CpuFeature::TSC
+    | CpuFeature::MSR
+    | CpuFeature::PAE
+    | CpuFeature::APIC
+    | CpuFeature::FXSR
+    | CpuFeature::SSE
+    | CpuFeature::SSE2
+    | CpuFeature::LONG_MODE;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static CPU_INFO: Once<CpuInfo> = Once::new();
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Identification and features of the CPUs, which are assumed to be the same
+/// for all of them
+pub struct CpuInfo {
+    vendor: [u8; 12],
+    family: u32,
+    model: u32,
+    stepping: u32,
+    features: u32,
+}
+
+/// Formats a feature mask as a list of names.
+pub struct CpuFeatureNames(pub u32);
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl CpuInfo {
+    /// Probes the CPU on first use and enables optional features on the
+    /// current CPU.
+    ///
+    /// Panics if a required feature is missing.
+    pub fn init() -> &'static Self {
+        let this = CPU_INFO.call_once(Self::probe);
+        let missing = CPU_FEATURE_REQUIRED & !this.features;
+        assert!(
+            missing == 0,
+            "CPU lacks required features: {}",
+            CpuFeatureNames(missing)
+        );
+        this.enable();
+        this
+    }
+
+    pub fn get() -> &'static Self {
+        CPU_INFO.get().unwrap()
+    }
+
+    /// APIC id of the current CPU, which is wider than 8 bits if the CPU
+    /// supports x2APIC.
+    pub fn apic_id() -> u32 {
+        if cpuid(0x0, 0)[0] >= 0xB {
+            let topology = cpuid(0xB, 0);
+            if topology[1] != 0 {
+                return topology[3];
+            }
+        }
+        cpuid(0x1, 0)[1] >> 24
+    }
+
+    pub fn vendor(&self) -> &str {
+        str::from_utf8(&self.vendor).unwrap_or("unknown")
+    }
+
+    pub fn family(&self) -> u32 {
+        self.family
+    }
+
+    pub fn model(&self) -> u32 {
+        self.model
+    }
+
+    pub fn stepping(&self) -> u32 {
+        self.stepping
+    }
+
+    pub fn features(&self) -> u32 {
+        self.features
+    }
+
+    pub fn has(&self, feature: u32) -> bool {
+        self.features & feature == feature
+    }
+
+    fn probe() -> Self {
+        let [max_leaf, vendor_0, vendor_2, vendor_1] = cpuid(0x0, 0);
+        let mut vendor = [0; 12];
+        vendor[0..4].copy_from_slice(&vendor_0.to_le_bytes());
+        vendor[4..8].copy_from_slice(&vendor_1.to_le_bytes());
+        vendor[8..12].copy_from_slice(&vendor_2.to_le_bytes());
+
+        let mut features = 0;
+        let mut set = |condition: bool, feature: u32| {
+            if condition {
+                features |= feature;
+            }
+        };
+
+        let [signature, _, ecx, edx] = cpuid(0x1, 0);
+        set(edx & 1 << 0 != 0, CpuFeature::FPU);
+        set(edx & 1 << 4 != 0, CpuFeature::TSC);
+        set(edx & 1 << 5 != 0, CpuFeature::MSR);
+        set(edx & 1 << 6 != 0, CpuFeature::PAE);
+        set(edx & 1 << 9 != 0, CpuFeature::APIC);
+        set(edx & 1 << 13 != 0, CpuFeature::PGE);
+        set(edx & 1 << 24 != 0, CpuFeature::FXSR);
+        set(edx & 1 << 25 != 0, CpuFeature::SSE);
+        set(edx & 1 << 26 != 0, CpuFeature::SSE2);
+        set(ecx & 1 << 17 != 0, CpuFeature::PCID);
+        set(ecx & 1 << 21 != 0, CpuFeature::X2APIC);
+        set(ecx & 1 << 24 != 0, CpuFeature::TSC_DEADLINE);
+        set(ecx & 1 << 26 != 0, CpuFeature::XSAVE);
+        set(ecx & 1 << 28 != 0, CpuFeature::AVX);
+        set(ecx & 1 << 30 != 0, CpuFeature::RDRAND);
+
+        if max_leaf >= 0x7 {
+            let [_, ebx, ecx, _] = cpuid(0x7, 0);
+            set(ebx & 1 << 7 != 0, CpuFeature::SMEP);
+            set(ebx & 1 << 10 != 0, CpuFeature::INVPCID);
+            set(ebx & 1 << 20 != 0, CpuFeature::SMAP);
+            set(ecx & 1 << 2 != 0, CpuFeature::UMIP);
+        }
+        if max_leaf >= 0xD && ecx & 1 << 26 != 0 {
+            let [eax, ..] = cpuid(0xD, 1);
+            set(eax & 1 << 0 != 0, CpuFeature::XSAVEOPT);
+        }
+        if cpuid(0x80000000, 0)[0] >= 0x80000001 {
+            let [_, _, _, edx] = cpuid(0x80000001, 0);
+            set(edx & 1 << 20 != 0, CpuFeature::NX);
+            set(edx & 1 << 26 != 0, CpuFeature::PAGE_1GB);
+            set(edx & 1 << 29 != 0, CpuFeature::LONG_MODE);
+        }
+
+        // the extended fields only apply to some families
+        let mut family = signature >> 8 & 0xF;
+        let mut model = signature >> 4 & 0xF;
+        if family == 0xF {
+            family += signature >> 20 & 0xFF;
+        }
+        if family == 0x6 || family >= 0xF {
+            model |= (signature >> 16 & 0xF) << 4;
+        }
+
+        Self {
+            vendor,
+            family,
+            model,
+            stepping: signature & 0xF,
+            features,
+        }
+    }
+
+    /// Turns on optional hardening and performance features.
+    fn enable(&self) {
+        unsafe {
+            // honor read-only pages in supervisor mode as well
+            write_cr0(read_cr0() | Cr0::WP);
+
+            let mut cr4 = read_cr4();
+            if self.has(CpuFeature::PGE) {
+                cr4 |= Cr4::PGE;
+            }
+            if self.has(CpuFeature::SMEP) {
+                cr4 |= Cr4::SMEP;
+            }
+            if self.has(CpuFeature::SMAP) {
+                cr4 |= Cr4::SMAP;
+            }
+            if self.has(CpuFeature::UMIP) {
+                cr4 |= Cr4::UMIP;
+            }
+            write_cr4(cr4);
+
+            #[cfg(target_arch = "x86_64")]
+            if self.has(CpuFeature::NX) {
+                wrmsr(Msr::EFER, rdmsr(Msr::EFER) | Efer::NXE);
+            }
+        }
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+impl fmt::Display for CpuFeatureNames {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = CPU_FEATURE_NAMES
+            .iter()
+            .filter(|(feature, _)| self.0 & feature != 0)
+            .map(|(_, name)| name);
+        if let Some(name) = names.next() {
+            f.write_str(name)?;
+        }
+        for name in names {
+            write!(f, " {}", name)?;
+        }
+        Ok(())
+    }
+}
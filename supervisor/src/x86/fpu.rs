@@ -0,0 +1,392 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::arch::asm;
+#[cfg(feature = "lazy-fpu")]
+use core::ptr::NonNull;
+
+use spin::Once;
+
+#[cfg(feature = "lazy-fpu")]
+use crate::x86::{register_interrupt_handler, InterruptFrame, PerCpu};
+use crate::{
+    process::Thread,
+    x86::{cpuid, read_cr0, read_cr4, write_cr0, write_cr4, CpuFeature, CpuInfo, Cr0, Cr4},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Size of the save area, enough for the x87, SSE and AVX components.
+const FPU_STATE_SIZE: usize = 1024;
+
+/// Offset of the XSAVE header, which follows the legacy FXSAVE region.
+const XSAVE_HEADER_OFFSET: usize = 512;
+
+/// Default x87 control word, all exceptions masked and extended precision.
+const FCW_DEFAULT: u16 = 0x037F;
+
+/// Default SSE control and status, all exceptions masked.
+const MXCSR_DEFAULT: u32 = 0x1F80;
+
+/// MXCSR bits supported by all CPUs, setting any other bit faults.
+const MXCSR_MASK: u32 = 0xFFBF;
+
+/// Offset of MXCSR in the legacy FXSAVE region.
+const MXCSR_OFFSET: usize = 24;
+
+/// Size of the XSAVE header.
+const XSAVE_HEADER_SIZE: usize = 64;
+
+#[allow(non_snake_case)]
+mod Xcr0 {
+    pub const X87: u64 = 1 << 0;
+    pub const SSE: u64 = 1 << 1;
+    pub const AVX: u64 = 1 << 2;
+}
+
+/// Device not available exception raised when CR0.TS is set.
+#[cfg(feature = "lazy-fpu")]
+const DEVICE_NOT_AVAILABLE_VECTOR: u8 = 7;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static FPU: Once<Fpu> = Once::new();
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Configuration of the extended state, which is the same for all CPUs
+///
+/// The supervisor itself is compiled without floating point and vector
+/// instructions, therefore the state only has to be switched along with user
+/// threads and never around interrupts or system calls.
+pub struct Fpu {
+    save: FpuSave,
+    /// Components enabled in XCR0.
+    mask: u64,
+    /// Size of the save area actually used by the CPU.
+    size: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FpuSave {
+    Fxsave,
+    Xsave,
+    Xsaveopt,
+}
+
+/// Extended state save area, as used by FXSAVE and XSAVE
+#[repr(C, align(64))]
+pub struct FpuState([u8; FPU_STATE_SIZE]);
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Fpu {
+    /// Enables the x87, SSE and, if supported, AVX state on the current CPU.
+    ///
+    /// Must be called on every CPU after [`CpuInfo::init`].
+    pub fn init() -> &'static Self {
+        let cpu_info = CpuInfo::get();
+        unsafe {
+            write_cr0(read_cr0() & !(Cr0::EM | Cr0::TS) | Cr0::MP | Cr0::NE);
+            let mut cr4 = read_cr4() | Cr4::OSFXSR | Cr4::OSXMMEXCPT;
+            if cpu_info.has(CpuFeature::XSAVE) {
+                cr4 |= Cr4::OSXSAVE;
+            }
+            write_cr4(cr4);
+        }
+
+        let this = FPU.call_once(|| {
+            if !cpu_info.has(CpuFeature::XSAVE) {
+                return Self {
+                    save: FpuSave::Fxsave,
+                    mask: Xcr0::X87 | Xcr0::SSE,
+                    size: XSAVE_HEADER_OFFSET,
+                };
+            }
+
+            let supported = cpuid(0xD, 0)[0] as u64;
+            let mut mask = Xcr0::X87 | Xcr0::SSE;
+            if cpu_info.has(CpuFeature::AVX) {
+                mask |= supported & Xcr0::AVX;
+            }
+            unsafe { xsetbv(0, mask) };
+            // reports the size for the components currently enabled
+            let size = cpuid(0xD, 0)[1] as usize;
+            assert!(size <= FPU_STATE_SIZE, "Extended state is too large");
+
+            Self {
+                save: if cpu_info.has(CpuFeature::XSAVEOPT) {
+                    FpuSave::Xsaveopt
+                } else {
+                    FpuSave::Xsave
+                },
+                mask,
+                size,
+            }
+        });
+        if this.save != FpuSave::Fxsave {
+            unsafe { xsetbv(0, this.mask) };
+        }
+        unsafe { asm!("fninit", options(nomem, nostack)) };
+
+        #[cfg(feature = "lazy-fpu")]
+        {
+            register_interrupt_handler(DEVICE_NOT_AVAILABLE_VECTOR, device_not_available);
+            unsafe { write_cr0(read_cr0() | Cr0::TS) };
+        }
+
+        this
+    }
+
+    pub fn get() -> &'static Self {
+        FPU.get().unwrap()
+    }
+
+    /// Size of the save area actually used by the CPU.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Switches the extended state from `prev` to `next`, which must be
+    /// called by the scheduler before switching to another thread.
+    ///
+    /// With the `lazy-fpu` feature the state is only loaded on first use by
+    /// `next`, and only saved if `prev` used it, otherwise it is switched
+    /// eagerly. It is saved either way, as `prev` may continue on another CPU.
+    pub fn switch(prev: Option<&Thread>, next: &Thread) {
+        #[cfg(not(feature = "lazy-fpu"))]
+        unsafe {
+            if let Some(prev) = prev {
+                (*prev.fpu_state()).save();
+            }
+            (*next.fpu_state()).restore();
+        }
+        #[cfg(feature = "lazy-fpu")]
+        {
+            let _ = next;
+            let per_cpu = PerCpu::current();
+            if let Some(prev) = prev.filter(|&prev| Self::holds(prev)) {
+                unsafe { (*prev.fpu_state()).save() };
+                per_cpu.set_fpu_owner(None);
+            }
+            unsafe { write_cr0(read_cr0() | Cr0::TS) };
+        }
+    }
+
+    /// Writes the state of `thread`, the current thread, to its save area if it
+    /// is only held by the registers, so that it can be accessed there.
+    ///
+    /// Must be called with interrupts disabled.
+    pub fn save_current(thread: &Thread) {
+        if Self::holds(thread) {
+            unsafe { (*thread.fpu_state()).save() };
+        }
+    }
+
+    /// Loads the save area of `thread`, the current thread, into the registers
+    /// again after it was changed.
+    ///
+    /// Must be called with interrupts disabled.
+    pub fn restore_current(thread: &Thread) {
+        if Self::holds(thread) {
+            unsafe { (*thread.fpu_state()).restore() };
+        }
+    }
+
+    /// Whether the registers of the current CPU hold the state of `thread`.
+    fn holds(thread: &Thread) -> bool {
+        #[cfg(not(feature = "lazy-fpu"))]
+        {
+            let _ = thread;
+            true
+        }
+        #[cfg(feature = "lazy-fpu")]
+        {
+            PerCpu::current().fpu_owner() == Some(NonNull::from(thread))
+        }
+    }
+
+    /// Forgets about the state of an exiting thread.
+    pub fn release(thread: &Thread) {
+        #[cfg(feature = "lazy-fpu")]
+        {
+            let per_cpu = PerCpu::current();
+            if per_cpu.fpu_owner() == Some(NonNull::from(thread)) {
+                per_cpu.set_fpu_owner(None);
+            }
+        }
+        #[cfg(not(feature = "lazy-fpu"))]
+        let _ = thread;
+    }
+}
+
+impl FpuState {
+    /// Creates the initial state, all registers cleared and all exceptions
+    /// masked.
+    pub const fn new() -> Self {
+        let mut bytes = [0; FPU_STATE_SIZE];
+        let fcw = FCW_DEFAULT.to_le_bytes();
+        bytes[0] = fcw[0];
+        bytes[1] = fcw[1];
+        let mxcsr = MXCSR_DEFAULT.to_le_bytes();
+        bytes[MXCSR_OFFSET] = mxcsr[0];
+        bytes[MXCSR_OFFSET + 1] = mxcsr[1];
+        bytes[MXCSR_OFFSET + 2] = mxcsr[2];
+        bytes[MXCSR_OFFSET + 3] = mxcsr[3];
+        // an empty XSAVE header puts all other components in their initial
+        // state on restore
+        Self(bytes)
+    }
+
+    /// Part of the save area actually used by the CPU.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..Fpu::get().size]
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.0[..Fpu::get().size]
+    }
+
+    /// Clears the bits which would make a state coming from user memory fault
+    /// on restore.
+    pub fn sanitize(&mut self) {
+        let fpu = Fpu::get();
+        let mxcsr = &mut self.0[MXCSR_OFFSET..MXCSR_OFFSET + 4];
+        let value = u32::from_le_bytes(mxcsr.try_into().unwrap()) & MXCSR_MASK;
+        mxcsr.copy_from_slice(&value.to_le_bytes());
+        if fpu.save != FpuSave::Fxsave {
+            // only the components enabled, in the standard format
+            let header = &mut self.0[XSAVE_HEADER_OFFSET..XSAVE_HEADER_OFFSET + XSAVE_HEADER_SIZE];
+            let xstate_bv = u64::from_le_bytes(header[..8].try_into().unwrap()) & fpu.mask;
+            header.fill(0);
+            header[..8].copy_from_slice(&xstate_bv.to_le_bytes());
+        }
+    }
+
+    /// Saves the state of the current CPU.
+    pub fn save(&mut self) {
+        let fpu = Fpu::get();
+        let area = self.0.as_mut_ptr();
+        let (lo, hi) = (fpu.mask as u32, (fpu.mask >> 32) as u32);
+        unsafe {
+            match fpu.save {
+                #[cfg(target_arch = "x86")]
+                FpuSave::Fxsave => asm!("fxsave [{}]", in(reg) area, options(nostack)),
+                #[cfg(target_arch = "x86_64")]
+                FpuSave::Fxsave => asm!("fxsave64 [{}]", in(reg) area, options(nostack)),
+                #[cfg(target_arch = "x86")]
+                FpuSave::Xsave => {
+                    asm!("xsave [{}]", in(reg) area, in("eax") lo, in("edx") hi, options(nostack))
+                }
+                #[cfg(target_arch = "x86_64")]
+                FpuSave::Xsave => {
+                    asm!("xsave64 [{}]", in(reg) area, in("eax") lo, in("edx") hi, options(nostack))
+                }
+                #[cfg(target_arch = "x86")]
+                FpuSave::Xsaveopt => {
+                    asm!("xsaveopt [{}]", in(reg) area, in("eax") lo, in("edx") hi, options(nostack))
+                }
+                #[cfg(target_arch = "x86_64")]
+                FpuSave::Xsaveopt => {
+                    asm!("xsaveopt64 [{}]", in(reg) area, in("eax") lo, in("edx") hi, options(nostack))
+                }
+            }
+        }
+    }
+
+    /// Loads the state into the current CPU.
+    pub fn restore(&self) {
+        let fpu = Fpu::get();
+        let area = self.0.as_ptr();
+        let (lo, hi) = (fpu.mask as u32, (fpu.mask >> 32) as u32);
+        unsafe {
+            match fpu.save {
+                #[cfg(target_arch = "x86")]
+                FpuSave::Fxsave => asm!("fxrstor [{}]", in(reg) area, options(readonly, nostack)),
+                #[cfg(target_arch = "x86_64")]
+                FpuSave::Fxsave => asm!("fxrstor64 [{}]", in(reg) area, options(readonly, nostack)),
+                #[cfg(target_arch = "x86")]
+                FpuSave::Xsave | FpuSave::Xsaveopt => asm!(
+                    "xrstor [{}]",
+                    in(reg) area,
+                    in("eax") lo,
+                    in("edx") hi,
+                    options(readonly, nostack)
+                ),
+                #[cfg(target_arch = "x86_64")]
+                FpuSave::Xsave | FpuSave::Xsaveopt => asm!(
+                    "xrstor64 [{}]",
+                    in(reg) area,
+                    in("eax") lo,
+                    in("edx") hi,
+                    options(readonly, nostack)
+                ),
+            }
+        }
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+impl Default for FpuState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+unsafe fn xsetbv(register: u32, value: u64) {
+    asm!(
+        "xsetbv",
+        in("ecx") register,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nomem, nostack)
+    );
+}
+
+/// Hands the extended state over to the current thread on its first use after
+/// a switch.
+#[cfg(feature = "lazy-fpu")]
+fn device_not_available(_frame: &mut InterruptFrame) {
+    unsafe { asm!("clts", options(nomem, nostack)) };
+
+    let per_cpu = PerCpu::current();
+    let current = per_cpu.current_thread();
+    if per_cpu.fpu_owner() == current {
+        return;
+    }
+    // the previous owner saved its state when it was switched away from
+    if let Some(current) = current {
+        unsafe { (*current.as_ref().fpu_state()).restore() };
+    }
+    per_cpu.set_fpu_owner(current);
+}
@@ -0,0 +1,174 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{hint, ptr};
+
+use spin::Once;
+
+use crate::{
+    acpi::{AcpiTables, AddressSpace},
+    memory::{KernelMemory, PageTableEntryFlags},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+#[allow(non_snake_case)]
+mod HpetRegister {
+    pub const CAPABILITIES: usize = 0x000;
+    pub const CONFIGURATION: usize = 0x010;
+    pub const MAIN_COUNTER: usize = 0x0F0;
+}
+
+#[allow(non_snake_case)]
+mod HpetCapabilities {
+    /// The main counter is 64 bits wide, otherwise only 32.
+    pub const COUNT_SIZE_CAP: u64 = 1 << 13;
+}
+
+#[allow(non_snake_case)]
+mod HpetConfiguration {
+    pub const ENABLE: u64 = 1 << 0;
+}
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static HPET: Once<Option<Hpet>> = Once::new();
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// High precision event timer, only the main counter is used
+pub struct Hpet {
+    base: usize,
+    period_fs: u64,
+    counter_mask: u64,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Hpet {
+    /// Maps and enables the HPET described by ACPI, if any.
+    pub fn init() -> Option<&'static Self> {
+        HPET.call_once(|| {
+            let table = AcpiTables::get()?.hpet()?;
+            let base_address = table.base_address;
+            if base_address.address_space != AddressSpace::SYSTEM_MEMORY {
+                return None;
+            }
+
+            let base = KernelMemory::lock().map_physical(
+                usize::try_from(base_address.address).ok()?,
+                0x400,
+                PageTableEntryFlags::RW | PageTableEntryFlags::PCD,
+            )?;
+            let mut this = Self {
+                base,
+                period_fs: 0,
+                counter_mask: u64::MAX,
+            };
+            let capabilities = this.read(HpetRegister::CAPABILITIES);
+            this.period_fs = capabilities >> 32;
+            if this.period_fs == 0 {
+                return None;
+            }
+            if capabilities & HpetCapabilities::COUNT_SIZE_CAP == 0 {
+                this.counter_mask = u32::MAX as u64;
+            }
+
+            let configuration = this.read(HpetRegister::CONFIGURATION);
+            this.write(
+                HpetRegister::CONFIGURATION,
+                configuration | HpetConfiguration::ENABLE,
+            );
+            Some(this)
+        })
+        .as_ref()
+    }
+
+    /// Frequency of the main counter in Hz.
+    pub fn frequency(&self) -> u64 {
+        1_000_000_000_000_000 / self.period_fs
+    }
+
+    /// Whether the main counter is 64 bits wide, a 32-bit counter wraps
+    /// after a few minutes.
+    pub fn is_64bit(&self) -> bool {
+        self.counter_mask == u64::MAX
+    }
+
+    /// Value of the main counter, only the low 32 bits are valid unless
+    /// [`Hpet::is_64bit`].
+    pub fn counter(&self) -> u64 {
+        if !self.is_64bit() {
+            return self.read32(HpetRegister::MAIN_COUNTER) as u64;
+        }
+        #[cfg(target_arch = "x86")]
+        loop {
+            // the counter can't be read atomically, retry on overflow of the low half
+            let high = self.read32(HpetRegister::MAIN_COUNTER + 4);
+            let low = self.read32(HpetRegister::MAIN_COUNTER);
+            if self.read32(HpetRegister::MAIN_COUNTER + 4) == high {
+                return (high as u64) << 32 | low as u64;
+            }
+        }
+        #[cfg(target_arch = "x86_64")]
+        self.read(HpetRegister::MAIN_COUNTER)
+    }
+
+    /// Busy-waits and returns the time waited in nanoseconds.
+    pub fn wait(&self, ns: u64) -> u64 {
+        let start = self.counter();
+        let ticks = (ns as u128 * 1_000_000 / self.period_fs as u128) as u64;
+        let mut elapsed = 0;
+        while elapsed < ticks {
+            hint::spin_loop();
+            elapsed = self.counter().wrapping_sub(start) & self.counter_mask;
+        }
+        (elapsed as u128 * self.period_fs as u128 / 1_000_000) as u64
+    }
+
+    fn read(&self, register: usize) -> u64 {
+        #[cfg(target_arch = "x86")]
+        return self.read32(register) as u64 | (self.read32(register + 4) as u64) << 32;
+        #[cfg(target_arch = "x86_64")]
+        return unsafe { ptr::read_volatile((self.base + register) as *const u64) };
+    }
+
+    fn write(&self, register: usize, value: u64) {
+        #[cfg(target_arch = "x86")]
+        unsafe {
+            ptr::write_volatile((self.base + register) as *mut u32, value as u32);
+            ptr::write_volatile((self.base + register + 4) as *mut u32, (value >> 32) as u32);
+        }
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            ptr::write_volatile((self.base + register) as *mut u64, value)
+        };
+    }
+
+    fn read32(&self, register: usize) -> u32 {
+        unsafe { ptr::read_volatile((self.base + register) as *const u32) }
+    }
+}
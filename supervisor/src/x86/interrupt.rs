@@ -0,0 +1,555 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    arch::asm,
+    mem,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(target_arch = "x86")]
+use abi::SYSCALL_VECTOR;
+use abi::{Signal, SignalContext, SignalSet};
+use spin::Once;
+
+use crate::{
+    log::SymbolizedAddress,
+    memory::USER_MEMORY_RANGE,
+    process::{self, Scheduler},
+    x86::{self, PerCpu, Rflags, SegmentSelector},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Distance between the entry points in `interrupt_entries`.
+const INTERRUPT_ENTRY_SIZE: usize = 16;
+
+/// Number of vectors reserved for exceptions.
+pub const EXCEPTION_COUNT: usize = 32;
+
+const NMI_VECTOR: usize = 2;
+const DOUBLE_FAULT_VECTOR: usize = 8;
+const PAGE_FAULT_VECTOR: usize = 14;
+const MACHINE_CHECK_VECTOR: usize = 18;
+
+/// Vectors which may interrupt any code, and run on a stack of their own on
+/// x86_64, in the order of their slots in the interrupt stack table.
+pub const INTERRUPT_STACK_VECTORS: [usize; 3] =
+    [NMI_VECTOR, DOUBLE_FAULT_VECTOR, MACHINE_CHECK_VECTOR];
+
+pub const EXCEPTION_NAMES: [&str; EXCEPTION_COUNT] = [
+    "Divide Error",
+    "Debug",
+    "Non-maskable Interrupt",
+    "Breakpoint",
+    "Overflow",
+    "BOUND Range Exceeded",
+    "Invalid Opcode",
+    "Device Not Available",
+    "Double Fault",
+    "Coprocessor Segment Overrun",
+    "Invalid TSS",
+    "Segment Not Present",
+    "Stack-Segment Fault",
+    "General Protection",
+    "Page Fault",
+    "Reserved",
+    "x87 Floating-Point Error",
+    "Alignment Check",
+    "Machine Check",
+    "SIMD Floating-Point Exception",
+    "Virtualization Exception",
+    "Control Protection Exception",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Reserved",
+    "Hypervisor Injection Exception",
+    "VMM Communication Exception",
+    "Security Exception",
+    "Reserved",
+];
+
+#[allow(non_snake_case)]
+mod GateDescriptorAccess {
+    pub const INTERRUPT: u8 = 0xE;
+    pub const P: u8 = 1 << 7;
+}
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+extern "C" {
+    static interrupt_entries: u8;
+}
+
+static IDT: Once<[GateDescriptor; 256]> = Once::new();
+
+static INTERRUPT_HANDLERS: [AtomicUsize; 256] = [const { AtomicUsize::new(0) }; 256];
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+pub type InterruptHandler = fn(&mut InterruptFrame);
+
+/// Registers saved on interrupt entry, in the order they are pushed by
+/// `interrupt_common`
+#[cfg(target_arch = "x86")]
+#[repr(C)]
+pub struct InterruptFrame {
+    pub gs: usize,
+    pub fs: usize,
+    pub es: usize,
+    pub ds: usize,
+    pub edi: usize,
+    pub esi: usize,
+    pub ebp: usize,
+    esp_kernel: usize,
+    pub ebx: usize,
+    pub edx: usize,
+    pub ecx: usize,
+    pub eax: usize,
+    pub vector: usize,
+    pub error_code: usize,
+    pub eip: usize,
+    pub cs: usize,
+    pub eflags: usize,
+    /// Only present when coming from user mode.
+    pub esp: usize,
+    /// Only present when coming from user mode.
+    pub ss: usize,
+}
+
+/// Registers saved on interrupt entry, in the order they are pushed by
+/// `interrupt_common`
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+pub struct InterruptFrame {
+    pub r15: usize,
+    pub r14: usize,
+    pub r13: usize,
+    pub r12: usize,
+    pub r11: usize,
+    pub r10: usize,
+    pub r9: usize,
+    pub r8: usize,
+    pub rbp: usize,
+    pub rdi: usize,
+    pub rsi: usize,
+    pub rdx: usize,
+    pub rcx: usize,
+    pub rbx: usize,
+    pub rax: usize,
+    pub vector: usize,
+    pub error_code: usize,
+    pub rip: usize,
+    pub cs: usize,
+    pub rflags: usize,
+    pub rsp: usize,
+    pub ss: usize,
+}
+
+#[repr(C, packed(2))]
+struct GateDescriptorTableRegister {
+    size: u16,
+    offset: *const [GateDescriptor],
+}
+
+#[cfg(target_arch = "x86")]
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct GateDescriptor {
+    offset_0_15: u16,
+    selector: u16,
+    reserved: u8,
+    access: u8,
+    offset_16_31: u16,
+}
+
+#[cfg(target_arch = "x86_64")]
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct GateDescriptor {
+    offset_0_15: u16,
+    selector: u16,
+    ist: u8,
+    access: u8,
+    offset_16_31: u16,
+    offset_32_63: u32,
+    reserved: u32,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl InterruptFrame {
+    /// Creates a frame which enters user mode at `ip` with the stack pointer
+    /// `sp` once it is returned through `interrupt_return`.
+    #[cfg(target_arch = "x86")]
+    pub fn user(ip: usize, sp: usize) -> Self {
+        let data = SegmentSelector::UDATA as usize;
+        Self {
+            gs: SegmentSelector::UTLS as usize,
+            fs: data,
+            es: data,
+            ds: data,
+            eip: ip,
+            cs: SegmentSelector::UCODE as usize,
+            eflags: Rflags::RESERVED | Rflags::IF,
+            esp: sp,
+            ss: data,
+            ..unsafe { mem::zeroed() }
+        }
+    }
+
+    /// Creates a frame which enters user mode at `ip` with the stack pointer
+    /// `sp` once it is returned through `interrupt_return`.
+    #[cfg(target_arch = "x86_64")]
+    pub fn user(ip: usize, sp: usize) -> Self {
+        Self {
+            rip: ip,
+            cs: SegmentSelector::UCODE as usize,
+            rflags: Rflags::RESERVED | Rflags::IF,
+            rsp: sp,
+            ss: SegmentSelector::UDATA as usize,
+            ..unsafe { mem::zeroed() }
+        }
+    }
+
+    /// Whether the interrupted code was running in user mode.
+    pub fn is_user(&self) -> bool {
+        self.cs & 3 == 3
+    }
+
+    /// System call number and arguments, passed as defined by the ABI.
+    pub fn syscall_args(&self) -> (usize, [usize; 6]) {
+        #[cfg(target_arch = "x86")]
+        return (
+            self.eax,
+            [self.ebx, self.ecx, self.edx, self.esi, self.edi, self.ebp],
+        );
+        #[cfg(target_arch = "x86_64")]
+        return (
+            self.rax,
+            [self.rdi, self.rsi, self.rdx, self.r10, self.r8, self.r9],
+        );
+    }
+
+    pub fn set_syscall_args(&mut self, args: [usize; 6]) {
+        #[cfg(target_arch = "x86")]
+        {
+            [self.ebx, self.ecx, self.edx, self.esi, self.edi, self.ebp] = args;
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            [self.rdi, self.rsi, self.rdx, self.r10, self.r8, self.r9] = args;
+        }
+    }
+
+    pub fn set_syscall_result(&mut self, value: usize) {
+        #[cfg(target_arch = "x86")]
+        {
+            self.eax = value;
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            self.rax = value;
+        }
+    }
+
+    /// Instruction pointer of the interrupted code.
+    pub fn ip(&self) -> usize {
+        #[cfg(target_arch = "x86")]
+        return self.eip;
+        #[cfg(target_arch = "x86_64")]
+        return self.rip;
+    }
+
+    pub fn set_ip(&mut self, ip: usize) {
+        #[cfg(target_arch = "x86")]
+        {
+            self.eip = ip;
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            self.rip = ip;
+        }
+    }
+
+    /// Stack pointer of the interrupted code, only valid for user mode.
+    pub fn sp(&self) -> usize {
+        #[cfg(target_arch = "x86")]
+        return self.esp;
+        #[cfg(target_arch = "x86_64")]
+        return self.rsp;
+    }
+
+    /// Saves the registers of the interrupted user code for a signal handler,
+    /// together with the signal `mask` of the thread.
+    #[cfg(target_arch = "x86")]
+    pub fn save_context(&self, mask: SignalSet) -> SignalContext {
+        SignalContext {
+            edi: self.edi,
+            esi: self.esi,
+            ebp: self.ebp,
+            ebx: self.ebx,
+            edx: self.edx,
+            ecx: self.ecx,
+            eax: self.eax,
+            eip: self.eip,
+            eflags: self.eflags,
+            esp: self.esp,
+            mask: mask as usize,
+        }
+    }
+
+    /// Saves the registers of the interrupted user code for a signal handler,
+    /// together with the signal `mask` of the thread.
+    #[cfg(target_arch = "x86_64")]
+    pub fn save_context(&self, mask: SignalSet) -> SignalContext {
+        SignalContext {
+            r15: self.r15,
+            r14: self.r14,
+            r13: self.r13,
+            r12: self.r12,
+            r11: self.r11,
+            r10: self.r10,
+            r9: self.r9,
+            r8: self.r8,
+            rbp: self.rbp,
+            rdi: self.rdi,
+            rsi: self.rsi,
+            rdx: self.rdx,
+            rcx: self.rcx,
+            rbx: self.rbx,
+            rax: self.rax,
+            rip: self.rip,
+            rflags: self.rflags,
+            rsp: self.rsp,
+            mask: mask as usize,
+        }
+    }
+
+    /// Continues user mode with the registers of a context saved by
+    /// `save_context`, and returns `false` if they would fault in the kernel.
+    ///
+    /// The segments and the flags only user code may not change are kept.
+    pub fn restore_context(&mut self, context: &SignalContext) -> bool {
+        #[cfg(target_arch = "x86")]
+        let (ip, sp) = (context.eip, context.esp);
+        #[cfg(target_arch = "x86_64")]
+        let (ip, sp) = (context.rip, context.rsp);
+        if !USER_MEMORY_RANGE.contains(&ip) || sp > USER_MEMORY_RANGE.end {
+            return false;
+        }
+
+        #[cfg(target_arch = "x86")]
+        {
+            self.edi = context.edi;
+            self.esi = context.esi;
+            self.ebp = context.ebp;
+            self.ebx = context.ebx;
+            self.edx = context.edx;
+            self.ecx = context.ecx;
+            self.eax = context.eax;
+            self.eip = context.eip;
+            self.eflags = self.eflags & !Rflags::USER | context.eflags & Rflags::USER;
+            self.esp = context.esp;
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            self.r15 = context.r15;
+            self.r14 = context.r14;
+            self.r13 = context.r13;
+            self.r12 = context.r12;
+            self.r11 = context.r11;
+            self.r10 = context.r10;
+            self.r9 = context.r9;
+            self.r8 = context.r8;
+            self.rbp = context.rbp;
+            self.rdi = context.rdi;
+            self.rsi = context.rsi;
+            self.rdx = context.rdx;
+            self.rcx = context.rcx;
+            self.rbx = context.rbx;
+            self.rax = context.rax;
+            self.rip = context.rip;
+            self.rflags = self.rflags & !Rflags::USER | context.rflags & Rflags::USER;
+            self.rsp = context.rsp;
+        }
+        true
+    }
+
+    /// Calls the user function at `ip` with the stack pointer `sp`, the
+    /// return address and, on i386, the arguments have to be on the stack.
+    pub fn call_user(&mut self, ip: usize, sp: usize, args: [usize; 3]) {
+        #[cfg(target_arch = "x86")]
+        {
+            let _ = args;
+            self.eip = ip;
+            self.esp = sp;
+            self.eflags &= !(Rflags::DF | Rflags::TF);
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            [self.rdi, self.rsi, self.rdx] = args;
+            self.rip = ip;
+            self.rsp = sp;
+            self.rflags &= !(Rflags::DF | Rflags::TF);
+        }
+    }
+}
+
+impl GateDescriptor {
+    /// Creates an interrupt gate, which switches to the stack in slot `ist` of
+    /// the interrupt stack table on x86_64 unless it is 0.
+    fn new(offset: usize, dpl: u8, ist: u8) -> Self {
+        #[cfg(target_arch = "x86")]
+        let _ = ist;
+        Self {
+            offset_0_15: offset as u16,
+            selector: SegmentSelector::KCODE,
+            #[cfg(target_arch = "x86")]
+            reserved: 0,
+            #[cfg(target_arch = "x86_64")]
+            ist,
+            access: GateDescriptorAccess::INTERRUPT | GateDescriptorAccess::P | dpl << 5,
+            offset_16_31: (offset >> 16) as u16,
+            #[cfg(target_arch = "x86_64")]
+            offset_32_63: (offset >> 32) as u32,
+            #[cfg(target_arch = "x86_64")]
+            reserved: 0,
+        }
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Loads the IDT on the current CPU, building it first if necessary.
+pub fn load_idt() {
+    let idt = IDT.call_once(|| {
+        let entries = unsafe { &interrupt_entries as *const u8 as usize };
+        core::array::from_fn(|vector| {
+            // only system calls may be raised from user mode, through SYSCALL
+            // instead on x86_64
+            #[cfg(target_arch = "x86")]
+            let dpl = if vector == SYSCALL_VECTOR as usize {
+                3
+            } else {
+                0
+            };
+            #[cfg(target_arch = "x86_64")]
+            let dpl = 0;
+            let ist = INTERRUPT_STACK_VECTORS
+                .iter()
+                .position(|&stack_vector| stack_vector == vector)
+                .map_or(0, |index| index as u8 + 1);
+            GateDescriptor::new(entries + vector * INTERRUPT_ENTRY_SIZE, dpl, ist)
+        })
+    });
+    let idtr = GateDescriptorTableRegister {
+        size: mem::size_of_val(idt) as u16 - 1,
+        offset: idt as *const [GateDescriptor],
+    };
+    unsafe { asm!("lidt [{}]", in(reg) &idtr, options(readonly, nostack, preserves_flags)) };
+}
+
+/// Registers a handler for the given vector, replacing the previous one.
+pub fn register_interrupt_handler(vector: u8, handler: InterruptHandler) {
+    INTERRUPT_HANDLERS[vector as usize].store(handler as usize, Ordering::Release);
+}
+
+#[no_mangle]
+extern "C" fn interrupt_handler(frame: &mut InterruptFrame) {
+    if frame.vector == NMI_VECTOR {
+        // sent by a CPU which panicked
+        if x86::cpus_stopped() {
+            x86::halt_forever();
+        }
+        // sent by a CPU which changed the page tables
+        if x86::handle_tlb_shootdown() {
+            return;
+        }
+    }
+
+    let handler = INTERRUPT_HANDLERS[frame.vector].load(Ordering::Acquire);
+    if handler != 0 {
+        let handler: InterruptHandler = unsafe { mem::transmute(handler) };
+        handler(frame);
+    } else if frame.is_user() && is_user_fault(frame.vector) {
+        // faulting user code only gets a signal
+        let addr = match frame.vector {
+            PAGE_FAULT_VECTOR => x86::read_cr2(),
+            _ => frame.ip(),
+        };
+        process::raise_fault(exception_signal(frame.vector), addr);
+    } else if frame.vector < EXCEPTION_COUNT
+        && !(is_user_fault(frame.vector) && x86::fixup_exception(frame))
+    {
+        panic!(
+            "{} at {} (error code {:#x})",
+            EXCEPTION_NAMES[frame.vector],
+            SymbolizedAddress::new(frame.ip()),
+            frame.error_code
+        );
+    }
+
+    // these may interrupt any code, and run on a stack which can't be switched
+    // away from on x86_64
+    if INTERRUPT_STACK_VECTORS.contains(&frame.vector) {
+        return;
+    }
+
+    // preempt the interrupted thread, interrupts are still disabled here
+    if PerCpu::current().run_queue().take_need_resched() {
+        Scheduler::schedule();
+    }
+    if frame.is_user() {
+        x86::prepare_user_return(frame);
+    }
+}
+
+/// Whether the exception is caused by the instructions user code runs, other
+/// ones such as NMIs and machine checks are the kernel's business.
+fn is_user_fault(vector: usize) -> bool {
+    matches!(vector, 0 | 1 | 3..=7 | 11..=14 | 16 | 17 | 19)
+}
+
+/// Signal raised by an exception in user mode.
+fn exception_signal(vector: usize) -> usize {
+    match vector {
+        // divide error, x87 and SIMD floating-point
+        0 | 16 | 19 => Signal::FPE,
+        // debug and breakpoint
+        1 | 3 => Signal::TRAP,
+        // invalid opcode
+        6 => Signal::ILL,
+        // segment not present, stack-segment fault and alignment check
+        11 | 12 | 17 => Signal::BUS,
+        _ => Signal::SEGV,
+    }
+}
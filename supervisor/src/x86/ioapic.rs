@@ -0,0 +1,233 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::ptr;
+
+use crate::{
+    acpi::{AcpiTables, MadtEntry, MadtInterruptFlags},
+    memory::{KernelMemory, PageTableEntryFlags},
+    sync::SpinLock,
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Vector of the first hardware interrupt, the others follow in order.
+pub const IRQ_VECTOR_BASE: u8 = 0x40;
+
+/// Number of hardware interrupts which get a vector.
+pub const MAX_IRQS: usize = 64;
+
+/// Number of ISA interrupts, which may be redirected by the MADT.
+const ISA_IRQS: usize = 16;
+
+const MAX_IO_APICS: usize = 8;
+
+#[allow(non_snake_case)]
+mod IoApicRegister {
+    pub const VERSION: u32 = 0x01;
+    pub const REDIRECTION: u32 = 0x10;
+}
+
+#[allow(non_snake_case)]
+mod IoApicRedirection {
+    pub const ACTIVE_LOW: u64 = 1 << 13;
+    pub const LEVEL: u64 = 1 << 15;
+    pub const MASKED: u64 = 1 << 16;
+}
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static IO_APICS: SpinLock<IoApics> = SpinLock::new(IoApics {
+    units: [None; MAX_IO_APICS],
+    isa: [IsaIrq { gsi: 0, flags: 0 }; ISA_IRQS],
+});
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// I/O APICs routing hardware interrupts to the local APICs
+///
+/// Interrupts are numbered by global system interrupt, except for the ISA
+/// interrupts below 16, which are translated through the MADT overrides.
+pub struct IoApic;
+
+struct IoApics {
+    units: [Option<IoApicUnit>; MAX_IO_APICS],
+    isa: [IsaIrq; ISA_IRQS],
+}
+
+#[derive(Clone, Copy)]
+struct IoApicUnit {
+    /// Virtual address of the registers.
+    addr: usize,
+    gsi_base: u32,
+    count: u32,
+}
+
+#[derive(Clone, Copy)]
+struct IsaIrq {
+    gsi: u32,
+    /// Redirection entry flags for polarity and trigger mode.
+    flags: u64,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl IoApic {
+    /// Finds the I/O APICs in the MADT and masks all of their interrupts.
+    pub fn init() {
+        let Some(madt) = AcpiTables::get().and_then(|acpi| acpi.madt()) else {
+            return;
+        };
+
+        let mut io_apics = IO_APICS.lock();
+        for (irq, isa) in io_apics.isa.iter_mut().enumerate() {
+            isa.gsi = irq as u32;
+        }
+        let mut units = 0;
+        for entry in madt.entries() {
+            match entry {
+                MadtEntry::IoApic(entry) if units < MAX_IO_APICS => {
+                    let addr = KernelMemory::lock()
+                        .map_physical(
+                            entry.address as usize,
+                            0x20,
+                            PageTableEntryFlags::RW | PageTableEntryFlags::PCD,
+                        )
+                        .expect("Not enough memory to map the I/O APIC");
+                    let mut unit = IoApicUnit {
+                        addr,
+                        gsi_base: entry.gsi_base,
+                        count: 0,
+                    };
+                    unit.count = (unit.read(IoApicRegister::VERSION) >> 16 & 0xFF) + 1;
+                    for index in 0..unit.count {
+                        unit.write_entry(index, IoApicRedirection::MASKED);
+                    }
+                    io_apics.units[units] = Some(unit);
+                    units += 1;
+                }
+                MadtEntry::InterruptSourceOverride(entry) if (entry.source as usize) < ISA_IRQS => {
+                    let mut flags = 0;
+                    let polarity = entry.flags & MadtInterruptFlags::POLARITY_MASK;
+                    if polarity == MadtInterruptFlags::POLARITY_LOW {
+                        flags |= IoApicRedirection::ACTIVE_LOW;
+                    }
+                    let trigger = entry.flags & MadtInterruptFlags::TRIGGER_MASK;
+                    if trigger == MadtInterruptFlags::TRIGGER_LEVEL {
+                        flags |= IoApicRedirection::LEVEL;
+                    }
+                    io_apics.isa[entry.source as usize] = IsaIrq {
+                        gsi: entry.gsi,
+                        flags,
+                    };
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the interrupt exists and has a vector.
+    pub fn is_valid(irq: u32) -> bool {
+        (irq as usize) < MAX_IRQS && IO_APICS.lock().find(irq).is_some()
+    }
+
+    /// Delivers the interrupt to the local APIC `apic_id` through
+    /// `IRQ_VECTOR_BASE + irq`, masked until [`IoApic::unmask`]. PCI
+    /// interrupts above the ISA range are level-triggered and active-low.
+    pub fn route(irq: u32, apic_id: u32) {
+        let io_apics = IO_APICS.lock();
+        let Some((unit, index, mut flags)) = io_apics.find(irq) else {
+            return;
+        };
+        if irq as usize >= ISA_IRQS {
+            flags |= IoApicRedirection::ACTIVE_LOW | IoApicRedirection::LEVEL;
+        }
+        let vector = IRQ_VECTOR_BASE as u64 + irq as u64;
+        unit.write_entry(
+            index,
+            (apic_id as u64) << 56 | flags | IoApicRedirection::MASKED | vector,
+        );
+    }
+
+    pub fn mask(irq: u32) {
+        Self::modify(irq, |entry| entry | IoApicRedirection::MASKED);
+    }
+
+    pub fn unmask(irq: u32) {
+        Self::modify(irq, |entry| entry & !IoApicRedirection::MASKED);
+    }
+
+    fn modify(irq: u32, f: impl FnOnce(u64) -> u64) {
+        let io_apics = IO_APICS.lock();
+        if let Some((unit, index, _)) = io_apics.find(irq) {
+            unit.write_entry(index, f(unit.read_entry(index)));
+        }
+    }
+}
+
+impl IoApics {
+    /// Returns the I/O APIC of an interrupt, its index there and the flags
+    /// the MADT requires.
+    fn find(&self, irq: u32) -> Option<(IoApicUnit, u32, u64)> {
+        let (gsi, flags) = match self.isa.get(irq as usize) {
+            Some(isa) => (isa.gsi, isa.flags),
+            None => (irq, 0),
+        };
+        self.units.iter().flatten().find_map(|unit| {
+            let index = gsi.checked_sub(unit.gsi_base)?;
+            (index < unit.count).then_some((*unit, index, flags))
+        })
+    }
+}
+
+impl IoApicUnit {
+    fn read(&self, register: u32) -> u32 {
+        unsafe {
+            ptr::write_volatile(self.addr as *mut u32, register);
+            ptr::read_volatile((self.addr + 0x10) as *const u32)
+        }
+    }
+
+    fn write(&self, register: u32, value: u32) {
+        unsafe {
+            ptr::write_volatile(self.addr as *mut u32, register);
+            ptr::write_volatile((self.addr + 0x10) as *mut u32, value);
+        }
+    }
+
+    fn read_entry(&self, index: u32) -> u64 {
+        let register = IoApicRegister::REDIRECTION + 2 * index;
+        self.read(register) as u64 | (self.read(register + 1) as u64) << 32
+    }
+
+    fn write_entry(&self, index: u32, entry: u64) {
+        let register = IoApicRegister::REDIRECTION + 2 * index;
+        // the low half holds the mask bit, it is written last when unmasking
+        self.write(register, IoApicRedirection::MASKED as u32);
+        self.write(register + 1, (entry >> 32) as u32);
+        self.write(register, entry as u32);
+    }
+}
@@ -16,22 +16,66 @@
 // Imports
 //==================================================================================================
 
-use core::{arch, mem};
+use core::{arch::asm, mem};
 
-#[cfg(target_arch = "x86")]
-arch::global_asm!(include_str!("x86.S"));
-#[cfg(target_arch = "x86_64")]
-arch::global_asm!(include_str!("x86_64.S"));
+use crate::acpi::{AcpiTables, AddressSpace, FadtFlags};
+
+mod apic;
+mod backtrace;
+mod context;
+mod cpu;
+mod fpu;
+mod hpet;
+mod interrupt;
+mod ioapic;
+mod percpu;
+mod pit;
+mod serial;
+mod smp;
+mod syscall;
+mod tlb;
+mod tls;
+mod user;
+
+pub use apic::*;
+pub use backtrace::*;
+pub use context::*;
+pub use cpu::*;
+pub use fpu::*;
+pub use hpet::*;
+pub use interrupt::*;
+pub use ioapic::*;
+pub use percpu::*;
+pub use pit::*;
+pub use serial::*;
+pub use smp::*;
+pub use syscall::*;
+pub use tlb::*;
+pub use tls::*;
+pub use user::*;
+
+#[cfg(all(target_arch = "x86", not(test)))]
+core::arch::global_asm!(include_str!("x86.S"));
+#[cfg(all(target_arch = "x86_64", not(test)))]
+core::arch::global_asm!(include_str!("x86_64.S"));
 
 //==================================================================================================
 // Constants
 //==================================================================================================
 
+/// Size of the I/O permission bitmap, one bit for each port.
+pub const IO_BITMAP_SIZE: usize = 0x10000 / 8;
+
+/// Command port of the keyboard controller, which can also reset the CPU.
+const KEYBOARD_CONTROLLER: u16 = 0x64;
+
+/// Number of descriptors in the GDT of every CPU.
+const GDT_SIZE: usize = 9;
+
 #[allow(non_snake_case)]
 mod SegmentDescriptorAccess {
     pub const A: u8 = 1 << 0;
     pub const RW: u8 = 1 << 1;
-    pub const DC: u8 = 1 << 2;
     pub const E: u8 = 1 << 3;
     pub const S: u8 = 1 << 4;
     pub const P: u8 = 1 << 7;
@@ -44,12 +88,86 @@ mod SegmentDescriptorFlags {
     pub const G: u8 = 1 << 7;
 }
 
+#[allow(non_snake_case)]
+mod SegmentSelector {
+    pub const KCODE: u16 = 1 << 3;
+    pub const KDATA: u16 = 2 << 3;
+    /// User data precedes user code, SYSRET derives both from the selector
+    /// preceding them.
+    pub const UDATA: u16 = 3 << 3 | 3;
+    pub const UCODE: u16 = 4 << 3 | 3;
+    pub const TSS: u16 = 5 << 3;
+    #[cfg(target_arch = "x86")]
+    pub const PER_CPU: u16 = 6 << 3;
+    /// Kernel TLS of the CPU, loaded into GS like the user TLS.
+    #[cfg(target_arch = "x86")]
+    pub const KTLS: u16 = 7 << 3;
+    #[cfg(target_arch = "x86")]
+    pub const UTLS: u16 = 8 << 3 | 3;
+}
+
+#[allow(non_snake_case)]
+mod Cr0 {
+    pub const MP: usize = 1 << 1;
+    pub const EM: usize = 1 << 2;
+    pub const TS: usize = 1 << 3;
+    pub const NE: usize = 1 << 5;
+    pub const WP: usize = 1 << 16;
+}
+
+#[allow(non_snake_case)]
+mod Cr4 {
+    pub const PGE: usize = 1 << 7;
+    pub const OSFXSR: usize = 1 << 9;
+    pub const OSXMMEXCPT: usize = 1 << 10;
+    pub const UMIP: usize = 1 << 11;
+    pub const OSXSAVE: usize = 1 << 18;
+    pub const SMEP: usize = 1 << 20;
+    pub const SMAP: usize = 1 << 21;
+}
+
+#[allow(non_snake_case)]
+mod Rflags {
+    pub const CF: usize = 1 << 0;
+    /// Always set.
+    pub const RESERVED: usize = 1 << 1;
+    pub const PF: usize = 1 << 2;
+    pub const AF: usize = 1 << 4;
+    pub const ZF: usize = 1 << 6;
+    pub const SF: usize = 1 << 7;
+    pub const TF: usize = 1 << 8;
+    pub const IF: usize = 1 << 9;
+    pub const DF: usize = 1 << 10;
+    pub const OF: usize = 1 << 11;
+    pub const AC: usize = 1 << 18;
+    /// Flags user code may change itself.
+    pub const USER: usize = CF | PF | AF | ZF | SF | TF | DF | OF;
+}
+
+#[allow(non_snake_case)]
+mod Efer {
+    pub const SCE: u64 = 1 << 0;
+    pub const NXE: u64 = 1 << 11;
+}
+
+#[allow(non_snake_case)]
+mod Msr {
+    pub const APIC_BASE: u32 = 0x0000001B;
+    pub const EFER: u32 = 0xC0000080;
+    pub const STAR: u32 = 0xC0000081;
+    pub const LSTAR: u32 = 0xC0000082;
+    pub const FMASK: u32 = 0xC0000084;
+    pub const FS_BASE: u32 = 0xC0000100;
+    pub const GS_BASE: u32 = 0xC0000101;
+    pub const KERNEL_GS_BASE: u32 = 0xC0000102;
+}
+
 //==================================================================================================
 // Variables
 //==================================================================================================
 
 #[no_mangle]
-static GDT: [SegmentDescriptor; 7] = [
+static GDT: [SegmentDescriptor; GDT_SIZE] = [
     // NULL
     unsafe { SegmentDescriptor::zeroed() },
     // KCODE
@@ -78,6 +196,17 @@ static GDT: [SegmentDescriptor; 7] = [
         0,
         SegmentDescriptorFlags::DB | SegmentDescriptorFlags::G,
     ),
+    // UDATA
+    SegmentDescriptor::new(
+        0x00000000,
+        0xFFFFF,
+        SegmentDescriptorAccess::A
+            | SegmentDescriptorAccess::RW
+            | SegmentDescriptorAccess::S
+            | SegmentDescriptorAccess::P,
+        3,
+        SegmentDescriptorFlags::DB | SegmentDescriptorFlags::G,
+    ),
     // UCODE
     SegmentDescriptor::new(
         0x00000000,
@@ -93,7 +222,22 @@ static GDT: [SegmentDescriptor; 7] = [
         #[cfg(target_arch = "x86_64")]
         (SegmentDescriptorFlags::L | SegmentDescriptorFlags::G),
     ),
-    // UDATA
+    // TSS
+    unsafe { SegmentDescriptor::zeroed() },
+    // TSS64 / PER_CPU
+    unsafe { SegmentDescriptor::zeroed() },
+    // KTLS
+    SegmentDescriptor::new(
+        0x00000000,
+        0xFFFFF,
+        SegmentDescriptorAccess::A
+            | SegmentDescriptorAccess::RW
+            | SegmentDescriptorAccess::S
+            | SegmentDescriptorAccess::P,
+        0,
+        SegmentDescriptorFlags::DB | SegmentDescriptorFlags::G,
+    ),
+    // UTLS
     SegmentDescriptor::new(
         0x00000000,
         0xFFFFF,
@@ -104,10 +248,6 @@ static GDT: [SegmentDescriptor; 7] = [
         3,
         SegmentDescriptorFlags::DB | SegmentDescriptorFlags::G,
     ),
-    // TSS
-    unsafe { SegmentDescriptor::zeroed() },
-    // TSS64 / GS
-    unsafe { SegmentDescriptor::zeroed() },
 ];
 
 //==================================================================================================
@@ -120,6 +260,7 @@ struct SegmentDescriptorTableRegister {
     offset: *mut [SegmentDescriptor],
 }
 
+#[derive(Clone, Copy)]
 #[repr(C)]
 struct SegmentDescriptor {
     limit_0_15: u16,
@@ -130,6 +271,38 @@ struct SegmentDescriptor {
     base_24_31: u8,
 }
 
+#[cfg(target_arch = "x86")]
+#[repr(C)]
+pub struct TaskStateSegment {
+    link: u32,
+    pub esp0: u32,
+    pub ss0: u32,
+    esp1: u32,
+    ss1: u32,
+    esp2: u32,
+    ss2: u32,
+    cr3: u32,
+    eip: u32,
+    eflags: u32,
+    general: [u32; 8],
+    segment: [u32; 6],
+    ldtr: u32,
+    trap: u16,
+    iomap_base: u16,
+}
+
+#[cfg(target_arch = "x86_64")]
+#[repr(C, packed(4))]
+pub struct TaskStateSegment {
+    reserved_0: u32,
+    pub rsp: [u64; 3],
+    reserved_1: u64,
+    pub ist: [u64; 7],
+    reserved_2: u64,
+    reserved_3: u16,
+    iomap_base: u16,
+}
+
 //==================================================================================================
 // Implementations
 //==================================================================================================
@@ -149,4 +322,261 @@ impl SegmentDescriptor {
             base_24_31: (base >> 24) as u8,
         }
     }
+
+    /// Creates the upper half of a 16 byte system descriptor, which only
+    /// carries bits 32-63 of the base.
+    #[cfg(target_arch = "x86_64")]
+    const fn new_upper(base_32_63: u32) -> Self {
+        Self {
+            limit_0_15: base_32_63 as u16,
+            base_0_15: (base_32_63 >> 16) as u16,
+            base_16_23: 0,
+            access: 0,
+            flags_and_limit_16_19: 0,
+            base_24_31: 0,
+        }
+    }
+}
+
+impl TaskStateSegment {
+    const fn new() -> Self {
+        unsafe {
+            let mut this: Self = mem::MaybeUninit::zeroed().assume_init();
+            #[cfg(target_arch = "x86")]
+            {
+                this.ss0 = SegmentSelector::KDATA as u32;
+            }
+            // no I/O permission bitmap
+            this.iomap_base = mem::size_of::<Self>() as u16;
+            this
+        }
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Executes `cpuid` for the given leaf and subleaf and returns `[eax, ebx,
+/// ecx, edx]`.
+pub fn cpuid(leaf: u32, subleaf: u32) -> [u32; 4] {
+    let eax: u32;
+    let ebx: usize;
+    let ecx: u32;
+    let edx: u32;
+    // ebx is reserved by LLVM and has to be preserved manually
+    unsafe {
+        #[cfg(target_arch = "x86")]
+        asm!(
+            "mov {0}, ebx",
+            "cpuid",
+            "xchg {0}, ebx",
+            out(reg) ebx,
+            inout("eax") leaf => eax,
+            inout("ecx") subleaf => ecx,
+            out("edx") edx,
+            options(nomem, nostack, preserves_flags),
+        );
+        #[cfg(target_arch = "x86_64")]
+        asm!(
+            "mov {0}, rbx",
+            "cpuid",
+            "xchg {0}, rbx",
+            out(reg) ebx,
+            inout("eax") leaf => eax,
+            inout("ecx") subleaf => ecx,
+            out("edx") edx,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    [eax, ebx as u32, ecx, edx]
+}
+
+/// Reads a model-specific register.
+///
+/// # Safety
+///
+/// The register has to exist on the current CPU.
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let low: u32;
+    let high: u32;
+    asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    (high as u64) << 32 | low as u64
+}
+
+/// Writes a model-specific register.
+///
+/// # Safety
+///
+/// The register has to exist on the current CPU and the value must not
+/// violate any memory safety guarantees.
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    asm!("wrmsr", in("ecx") msr, in("eax") value as u32, in("edx") (value >> 32) as u32, options(nostack, preserves_flags));
+}
+
+pub fn read_cr0() -> usize {
+    let value: usize;
+    unsafe { asm!("mov {}, cr0", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+/// # Safety
+///
+/// Changing CR0 can violate memory safety, e.g. by disabling paging.
+pub unsafe fn write_cr0(value: usize) {
+    asm!("mov cr0, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+/// Reads the address which caused the last page fault.
+pub fn read_cr2() -> usize {
+    let value: usize;
+    unsafe { asm!("mov {}, cr2", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+pub fn read_cr4() -> usize {
+    let value: usize;
+    unsafe { asm!("mov {}, cr4", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+/// # Safety
+///
+/// Changing CR4 can violate memory safety, e.g. by changing the paging mode.
+pub unsafe fn write_cr4(value: usize) {
+    asm!("mov cr4, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+/// Reads the physical address of the active top-level page table.
+pub fn read_cr3() -> usize {
+    let value: usize;
+    unsafe { asm!("mov {}, cr3", out(reg) value, options(nomem, nostack, preserves_flags)) };
+    value
+}
+
+/// Activates another top-level page table.
+///
+/// # Safety
+///
+/// The page table has to map the kernel the same way the active one does.
+pub unsafe fn write_cr3(value: usize) {
+    asm!("mov cr3, {}", in(reg) value, options(nostack, preserves_flags));
+}
+
+/// Invalidates the TLB entry of a single page.
+pub fn invlpg(addr: usize) {
+    unsafe { asm!("invlpg [{}]", in(reg) addr, options(nostack, preserves_flags)) };
+}
+
+/// Reads the time-stamp counter.
+pub fn rdtsc() -> u64 {
+    let low: u32;
+    let high: u32;
+    unsafe {
+        asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags))
+    };
+    (high as u64) << 32 | low as u64
+}
+
+/// Reads a byte from an I/O port.
+///
+/// # Safety
+///
+/// Reading from an I/O port can have arbitrary side effects.
+pub unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    asm!("in al, dx", in("dx") port, out("al") value, options(nomem, nostack, preserves_flags));
+    value
+}
+
+/// Writes a byte to an I/O port.
+///
+/// # Safety
+///
+/// Writing to an I/O port can have arbitrary side effects.
+pub unsafe fn outb(port: u16, value: u8) {
+    asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+}
+
+/// Returns whether interrupts are enabled on the current CPU.
+pub fn interrupts_enabled() -> bool {
+    let flags: usize;
+    unsafe { asm!("pushf", "pop {}", out(reg) flags, options(nomem, preserves_flags)) };
+    flags & Rflags::IF != 0
+}
+
+pub fn enable_interrupts() {
+    // the unit tests run in user mode, which may not change the flag
+    #[cfg(not(test))]
+    unsafe {
+        asm!("sti", options(nostack))
+    };
+}
+
+pub fn disable_interrupts() {
+    #[cfg(not(test))]
+    unsafe {
+        asm!("cli", options(nostack))
+    };
+}
+
+/// Runs `f` with interrupts disabled, restoring the previous state afterwards.
+pub fn without_interrupts<T>(f: impl FnOnce() -> T) -> T {
+    let enabled = interrupts_enabled();
+    if enabled {
+        disable_interrupts();
+    }
+    let result = f();
+    if enabled {
+        enable_interrupts();
+    }
+    result
+}
+
+/// Waits for the next interrupt.
+pub fn halt() {
+    unsafe { asm!("hlt", options(nomem, nostack, preserves_flags)) };
+}
+
+/// Stops the current CPU for good, only NMIs can still interrupt it.
+pub fn halt_forever() -> ! {
+    disable_interrupts();
+    loop {
+        halt();
+    }
+}
+
+/// Resets the machine through the ACPI reset register, the keyboard
+/// controller, or a triple fault, whichever works first.
+pub fn reboot() -> ! {
+    disable_interrupts();
+    unsafe {
+        // a register in memory would have to be mapped, which might not work
+        // after a panic
+        if let Some(fadt) = AcpiTables::get().and_then(AcpiTables::fadt) {
+            let reset_register = fadt.reset_register;
+            if fadt.flags & FadtFlags::RESET_REG_SUP != 0
+                && reset_register.address_space == AddressSpace::SYSTEM_IO
+            {
+                outb(reset_register.address as u16, fadt.reset_value);
+            }
+        }
+        // wait a while for the input buffer to be empty, a missing controller
+        // reads as all ones, then pulse the reset line
+        for _ in 0..0x10000 {
+            if inb(KEYBOARD_CONTROLLER) & 0b10 == 0 {
+                break;
+            }
+        }
+        outb(KEYBOARD_CONTROLLER, 0xFE);
+        // without an IDT the next exception can't be delivered
+        let idtr = [0u8; mem::size_of::<usize>() + 2];
+        asm!("lidt [{}]", "int3", in(reg) &idtr, options(noreturn));
+    }
+}
+
+/// Enables interrupts and waits for the next one, without a window in which an
+/// interrupt could be missed.
+pub fn wait_for_interrupt() {
+    unsafe { asm!("sti", "hlt", options(nostack)) };
 }
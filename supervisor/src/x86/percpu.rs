@@ -0,0 +1,411 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    arch::asm,
+    cell::{Cell, UnsafeCell},
+    mem,
+    ptr::{self, NonNull},
+    slice,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+#[cfg(target_arch = "x86")]
+use crate::x86::SegmentDescriptorFlags;
+#[cfg(target_arch = "x86_64")]
+use crate::x86::{wrmsr, Msr, INTERRUPT_STACK_VECTORS};
+use crate::{
+    memory::{KernelMemory, ObjectPool, PageTableEntryFlags},
+    process::{RunQueue, System, Thread},
+    time::TimerQueue,
+    x86::{
+        create_kernel_tls, CpuInfo, SegmentDescriptor, SegmentDescriptorAccess,
+        SegmentDescriptorTableRegister, SegmentSelector, TaskStateSegment, GDT, GDT_SIZE,
+        IO_BITMAP_SIZE,
+    },
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+// the offsets are hard-coded in the entry code of x86_64.S
+#[cfg(target_arch = "x86_64")]
+const _: () = assert!(
+    PerCpu::SYSCALL_STACK == 8
+        && PerCpu::SYSCALL_SCRATCH == 16
+        && PerCpu::KERNEL_TLS == 24
+        && PerCpu::USER_TLS == 32
+);
+
+/// Size of each stack in the interrupt stack table.
+#[cfg(target_arch = "x86_64")]
+const INTERRUPT_STACK_SIZE: usize = 0x4000;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+static PER_CPU_POOL: ObjectPool<PerCpu> = ObjectPool::new();
+
+static PER_CPU_COUNT: AtomicU32 = AtomicU32::new(0);
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Per-CPU data area
+///
+/// Reachable through the GS base on x86_64 and through a dedicated FS segment
+/// on i386, the first word always points to the area itself.
+#[repr(C)]
+pub struct PerCpu {
+    this: *const PerCpu,
+    /// Kernel stack loaded on system call entry.
+    syscall_stack: Cell<usize>,
+    /// User stack saved on system call entry.
+    syscall_scratch: Cell<usize>,
+    /// Thread pointer of the kernel TLS, loaded on entry from user mode on
+    /// x86_64.
+    kernel_tls: Cell<usize>,
+    /// Thread pointer of the current thread, loaded on return to user mode on
+    /// x86_64.
+    user_tls: Cell<usize>,
+
+    id: u32,
+    apic_id: u32,
+    current_thread: Cell<Option<NonNull<Thread>>>,
+    idle_thread: Cell<Option<NonNull<Thread>>>,
+    /// Current thread whose extended state is in the registers, it is saved
+    /// and given up once the thread is switched away from.
+    #[cfg(feature = "lazy-fpu")]
+    fpu_owner: Cell<Option<NonNull<Thread>>>,
+    timer_queue: TimerQueue,
+    run_queue: RunQueue,
+
+    gdt: UnsafeCell<[SegmentDescriptor; GDT_SIZE]>,
+    /// Task state segment used until the one with the I/O permission bitmap
+    /// is allocated.
+    tss: UnsafeCell<TaskStateSegment>,
+    io_tss: Cell<Option<NonNull<IoTaskStateSegment>>>,
+    /// Address and generation of the I/O permission bitmap loaded into the
+    /// task state segment, 0 if all ports are denied.
+    io_bitmap: Cell<(usize, usize)>,
+}
+
+/// Task state segment followed by the I/O permission bitmap, which needs a
+/// trailing byte with all bits set
+#[repr(C)]
+struct IoTaskStateSegment {
+    tss: TaskStateSegment,
+    io_bitmap: [u8; IO_BITMAP_SIZE + 1],
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl PerCpu {
+    /// Offset of the system call kernel stack, for use in assembly.
+    pub const SYSCALL_STACK: usize = mem::offset_of!(PerCpu, syscall_stack);
+    /// Offset of the system call scratch slot, for use in assembly.
+    pub const SYSCALL_SCRATCH: usize = mem::offset_of!(PerCpu, syscall_scratch);
+    /// Offset of the kernel thread pointer, for use in assembly.
+    pub const KERNEL_TLS: usize = mem::offset_of!(PerCpu, kernel_tls);
+    /// Offset of the user thread pointer, for use in assembly.
+    pub const USER_TLS: usize = mem::offset_of!(PerCpu, user_tls);
+
+    /// Creates the per-CPU data area of the calling CPU, loads its GDT and TSS,
+    /// and makes it reachable through [`PerCpu::current`].
+    ///
+    /// Must be called exactly once on every CPU before anything else accesses
+    /// per-CPU data.
+    pub fn init() -> &'static Self {
+        let this = PER_CPU_POOL.allocate(Self {
+            this: ptr::null(),
+            syscall_stack: Cell::new(0),
+            syscall_scratch: Cell::new(0),
+            kernel_tls: Cell::new(0),
+            user_tls: Cell::new(0),
+            id: PER_CPU_COUNT.fetch_add(1, Ordering::Relaxed),
+            apic_id: CpuInfo::apic_id(),
+            current_thread: Cell::new(None),
+            idle_thread: Cell::new(None),
+            #[cfg(feature = "lazy-fpu")]
+            fpu_owner: Cell::new(None),
+            timer_queue: TimerQueue::new(),
+            run_queue: RunQueue::new(),
+            gdt: UnsafeCell::new(GDT),
+            tss: UnsafeCell::new(TaskStateSegment::new()),
+            io_tss: Cell::new(None),
+            io_bitmap: Cell::new((0, 0)),
+        });
+        this.this = this;
+
+        #[cfg(target_arch = "x86")]
+        {
+            this.gdt.get_mut()[6] = SegmentDescriptor::new(
+                this as *const _ as u32,
+                mem::size_of::<Self>() as u32 - 1,
+                SegmentDescriptorAccess::A
+                    | SegmentDescriptorAccess::RW
+                    | SegmentDescriptorAccess::S
+                    | SegmentDescriptorAccess::P,
+                0,
+                SegmentDescriptorFlags::DB,
+            );
+        }
+
+        let gdtr = SegmentDescriptorTableRegister {
+            size: mem::size_of::<[SegmentDescriptor; GDT_SIZE]>() as u16 - 1,
+            offset: this.gdt.get() as *mut [SegmentDescriptor],
+        };
+        unsafe {
+            asm!("lgdt [{}]", in(reg) &gdtr, options(readonly, nostack, preserves_flags));
+            this.load_tss(this.tss.get() as usize, mem::size_of::<TaskStateSegment>());
+            #[cfg(target_arch = "x86")]
+            asm!("mov fs, {:x}", in(reg) SegmentSelector::PER_CPU, options(nostack, preserves_flags));
+            #[cfg(target_arch = "x86_64")]
+            {
+                wrmsr(Msr::GS_BASE, this as *const _ as u64);
+                wrmsr(Msr::KERNEL_GS_BASE, 0);
+            }
+        }
+
+        System::get().add_cpu(this);
+        this
+    }
+
+    /// Returns the per-CPU data area of the calling CPU.
+    ///
+    /// The returned reference must not be carried over to another CPU, so it
+    /// should not be held across points where the current thread may be
+    /// rescheduled.
+    pub fn current() -> &'static Self {
+        let this: *const Self;
+        unsafe {
+            #[cfg(target_arch = "x86")]
+            asm!("mov {}, fs:[0]", out(reg) this, options(readonly, nostack, preserves_flags));
+            #[cfg(target_arch = "x86_64")]
+            asm!("mov {}, gs:[0]", out(reg) this, options(readonly, nostack, preserves_flags));
+            &*this
+        }
+    }
+
+    /// Returns the per-CPU data area of the CPU with the given id.
+    pub fn get(id: u32) -> Option<&'static Self> {
+        System::get().cpu(id)
+    }
+
+    /// Number of CPUs which have been initialized.
+    pub fn count() -> u32 {
+        PER_CPU_COUNT.load(Ordering::Relaxed)
+    }
+
+    /// Sequential id of this CPU, starting with 0 for the bootstrap processor.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Local APIC id of this CPU.
+    pub fn apic_id(&self) -> u32 {
+        self.apic_id
+    }
+
+    /// Thread currently running on this CPU.
+    pub fn current_thread(&self) -> Option<NonNull<Thread>> {
+        self.current_thread.get()
+    }
+
+    pub fn set_current_thread(&self, thread: Option<NonNull<Thread>>) {
+        self.current_thread.set(thread);
+    }
+
+    /// Thread which is run when there is nothing else to do.
+    pub fn idle_thread(&self) -> Option<NonNull<Thread>> {
+        self.idle_thread.get()
+    }
+
+    pub fn set_idle_thread(&self, thread: Option<NonNull<Thread>>) {
+        self.idle_thread.set(thread);
+    }
+
+    /// Thread whose extended state is loaded into this CPU.
+    #[cfg(feature = "lazy-fpu")]
+    pub fn fpu_owner(&self) -> Option<NonNull<Thread>> {
+        self.fpu_owner.get()
+    }
+
+    #[cfg(feature = "lazy-fpu")]
+    pub fn set_fpu_owner(&self, thread: Option<NonNull<Thread>>) {
+        self.fpu_owner.set(thread);
+    }
+
+    /// Pending timers of this CPU.
+    pub fn timer_queue(&self) -> &TimerQueue {
+        &self.timer_queue
+    }
+
+    /// Threads ready to run on this CPU.
+    pub fn run_queue(&self) -> &RunQueue {
+        &self.run_queue
+    }
+
+    /// Allocates the stacks of the vectors in [`INTERRUPT_STACK_VECTORS`], as
+    /// they may arrive while the kernel still runs on the user stack.
+    ///
+    /// Must be called once on every CPU once kernel memory is available, and
+    /// before [`PerCpu::init_io_bitmap`] which takes over the stacks.
+    #[cfg(target_arch = "x86_64")]
+    pub fn init_interrupt_stacks(&self) {
+        let tss = unsafe { &mut *self.tss.get() };
+        for index in 0..INTERRUPT_STACK_VECTORS.len() {
+            let stack = KernelMemory::lock()
+                .allocate(INTERRUPT_STACK_SIZE, PageTableEntryFlags::RW)
+                .expect("Not enough memory for the interrupt stacks");
+            tss.ist[index] = (stack + INTERRUPT_STACK_SIZE) as u64;
+        }
+    }
+
+    /// Switches this CPU to a task state segment with an I/O permission bitmap,
+    /// which denies all ports until [`PerCpu::load_io_bitmap`].
+    ///
+    /// Must be called once on every CPU once kernel memory is available.
+    pub fn init_io_bitmap(&self) {
+        let size = mem::size_of::<IoTaskStateSegment>();
+        let io_tss = KernelMemory::lock()
+            .allocate(size, PageTableEntryFlags::RW)
+            .expect("Not enough memory for the I/O permission bitmap")
+            as *mut IoTaskStateSegment;
+        unsafe {
+            ptr::addr_of_mut!((*io_tss).tss).write(ptr::read(self.tss.get()));
+            (*io_tss).tss.iomap_base = mem::offset_of!(IoTaskStateSegment, io_bitmap) as u16;
+            ptr::addr_of_mut!((*io_tss).io_bitmap).write_bytes(0xFF, 1);
+            self.io_tss.set(NonNull::new(io_tss));
+            self.load_tss(io_tss as usize, size);
+        }
+    }
+
+    /// Loads the I/O permission bitmap of [`IO_BITMAP_SIZE`] bytes at `addr`,
+    /// or denies all ports if it is 0.
+    ///
+    /// Nothing is copied if the same bitmap of the same `generation` is loaded
+    /// already.
+    pub fn load_io_bitmap(&self, addr: usize, generation: usize) {
+        let Some(io_tss) = self.io_tss.get() else {
+            return;
+        };
+        if self.io_bitmap.replace((addr, generation)) == (addr, generation) {
+            return;
+        }
+        let io_bitmap = unsafe { &mut (*io_tss.as_ptr()).io_bitmap };
+        let io_bitmap = &mut io_bitmap[..IO_BITMAP_SIZE];
+        match addr {
+            0 => io_bitmap.fill(0xFF),
+            addr => io_bitmap.copy_from_slice(unsafe {
+                slice::from_raw_parts(addr as *const u8, IO_BITMAP_SIZE)
+            }),
+        }
+    }
+
+    /// Creates the kernel TLS of this CPU from the `.tdata` and `.tbss`
+    /// template and loads it.
+    ///
+    /// Must be called once on every CPU once kernel memory is available,
+    /// thread-local variables must not be accessed before.
+    pub fn init_tls(&self) {
+        let thread_pointer = create_kernel_tls().expect("Not enough memory for the kernel TLS");
+        self.kernel_tls.set(thread_pointer);
+        #[cfg(target_arch = "x86")]
+        unsafe {
+            (*self.gdt.get())[7] = tls_descriptor(thread_pointer, 0);
+            asm!("mov gs, {:x}", in(reg) SegmentSelector::KTLS, options(nostack, preserves_flags));
+        }
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            wrmsr(Msr::FS_BASE, thread_pointer as u64);
+        }
+    }
+
+    /// Sets the thread pointer user mode continues with, which is taken over
+    /// on the next return to user mode.
+    pub fn set_user_tls(&self, thread_pointer: usize) {
+        self.user_tls.set(thread_pointer);
+        // the descriptor is only read as the segment is loaded from the frame
+        #[cfg(target_arch = "x86")]
+        unsafe {
+            (*self.gdt.get())[8] = tls_descriptor(thread_pointer, 3);
+        }
+    }
+
+    /// Sets the stack used when entering the kernel from user mode, be it
+    /// through an interrupt or a system call.
+    pub fn set_kernel_stack(&self, stack: usize) {
+        self.syscall_stack.set(stack);
+        let tss = match self.io_tss.get() {
+            Some(io_tss) => unsafe { &mut (*io_tss.as_ptr()).tss },
+            None => unsafe { &mut *self.tss.get() },
+        };
+        #[cfg(target_arch = "x86")]
+        {
+            tss.esp0 = stack as u32;
+        }
+        #[cfg(target_arch = "x86_64")]
+        {
+            tss.rsp[0] = stack as u64;
+        }
+    }
+
+    /// Points the TSS descriptor at the `size` bytes at `tss`, and loads it
+    /// into the task register.
+    unsafe fn load_tss(&self, tss: usize, size: usize) {
+        let gdt = &mut *self.gdt.get();
+        gdt[5] = SegmentDescriptor::new(
+            tss as u32,
+            size as u32 - 1,
+            SegmentDescriptorAccess::A | SegmentDescriptorAccess::E | SegmentDescriptorAccess::P,
+            0,
+            0,
+        );
+        #[cfg(target_arch = "x86_64")]
+        {
+            gdt[6] = SegmentDescriptor::new_upper((tss >> 32) as u32);
+        }
+        asm!("ltr {:x}", in(reg) SegmentSelector::TSS, options(nostack, preserves_flags));
+    }
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Descriptor of a TLS segment based at `thread_pointer`, which spans the whole
+/// address space as variables lie below the thread pointer.
+#[cfg(target_arch = "x86")]
+fn tls_descriptor(thread_pointer: usize, dpl: u8) -> SegmentDescriptor {
+    SegmentDescriptor::new(
+        thread_pointer as u32,
+        0xFFFFF,
+        SegmentDescriptorAccess::A
+            | SegmentDescriptorAccess::RW
+            | SegmentDescriptorAccess::S
+            | SegmentDescriptorAccess::P,
+        dpl,
+        SegmentDescriptorFlags::DB | SegmentDescriptorFlags::G,
+    )
+}
@@ -0,0 +1,64 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use crate::x86::{inb, outb};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Input clock of the programmable interval timer in Hz.
+pub const PIT_FREQUENCY: u64 = 1193182;
+
+const PIT_CHANNEL_2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+/// Controls the gate of channel 2 and reflects its output.
+const PIT_CHANNEL_2_GATE: u16 = 0x61;
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Programmable interval timer, only used as a calibration reference
+pub struct Pit;
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Pit {
+    /// Busy-waits using channel 2 and returns the time waited in nanoseconds,
+    /// which is at most about 54 ms.
+    pub fn wait(ns: u64) -> u64 {
+        let count = (ns * PIT_FREQUENCY / 1_000_000_000).clamp(1, u16::MAX as u64);
+        unsafe {
+            // gate low, speaker off
+            let gate = inb(PIT_CHANNEL_2_GATE) & !0b11;
+            outb(PIT_CHANNEL_2_GATE, gate);
+            // channel 2, low and high byte, mode 0 (interrupt on terminal count)
+            outb(PIT_COMMAND, 0b1011_0000);
+            outb(PIT_CHANNEL_2, count as u8);
+            outb(PIT_CHANNEL_2, (count >> 8) as u8);
+            // gate high starts counting, the output goes high at zero
+            outb(PIT_CHANNEL_2_GATE, gate | 0b01);
+            while inb(PIT_CHANNEL_2_GATE) & 0x20 == 0 {}
+            outb(PIT_CHANNEL_2_GATE, gate);
+        }
+        count * 1_000_000_000 / PIT_FREQUENCY
+    }
+}
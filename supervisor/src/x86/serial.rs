@@ -0,0 +1,148 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::fmt;
+
+use crate::x86::{inb, outb};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// I/O port base of the first serial port.
+pub const COM1: u16 = 0x3F8;
+
+/// Clock of the UART divided by 16, the baud rate for a divisor of 1.
+const SERIAL_BASE_BAUD: u32 = 115200;
+
+#[allow(non_snake_case)]
+mod SerialRegister {
+    pub const DATA: u16 = 0;
+    pub const INTERRUPT_ENABLE: u16 = 1;
+    pub const FIFO_CONTROL: u16 = 2;
+    pub const LINE_CONTROL: u16 = 3;
+    pub const MODEM_CONTROL: u16 = 4;
+    pub const LINE_STATUS: u16 = 5;
+    /// Low byte of the divisor while [`LineControl::DLAB`] is set.
+    pub const DIVISOR_LOW: u16 = 0;
+    /// High byte of the divisor while [`LineControl::DLAB`] is set.
+    pub const DIVISOR_HIGH: u16 = 1;
+}
+
+#[allow(non_snake_case)]
+mod LineControl {
+    pub const WORD_LENGTH_8: u8 = 0b11;
+    pub const DLAB: u8 = 1 << 7;
+}
+
+#[allow(non_snake_case)]
+mod FifoControl {
+    pub const ENABLE: u8 = 1 << 0;
+    pub const CLEAR_RECEIVE: u8 = 1 << 1;
+    pub const CLEAR_TRANSMIT: u8 = 1 << 2;
+    pub const TRIGGER_14: u8 = 0b11 << 6;
+}
+
+#[allow(non_snake_case)]
+mod ModemControl {
+    pub const DTR: u8 = 1 << 0;
+    pub const RTS: u8 = 1 << 1;
+}
+
+#[allow(non_snake_case)]
+mod LineStatus {
+    /// Transmitter holding register empty.
+    pub const THRE: u8 = 1 << 5;
+}
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// 16550 UART, only used for output
+pub struct Serial {
+    port: u16,
+}
+
+//==================================================================================================
+// Implementations
+//==================================================================================================
+
+impl Serial {
+    pub const fn new(port: u16) -> Self {
+        Self { port }
+    }
+
+    /// Programs 8N1 at `baud` with FIFOs and without interrupts.
+    pub fn init(&self, baud: u32) {
+        let divisor = (SERIAL_BASE_BAUD / baud).clamp(1, u16::MAX as u32) as u16;
+        unsafe {
+            self.write(SerialRegister::INTERRUPT_ENABLE, 0);
+            self.write(SerialRegister::LINE_CONTROL, LineControl::DLAB);
+            self.write(SerialRegister::DIVISOR_LOW, divisor as u8);
+            self.write(SerialRegister::DIVISOR_HIGH, (divisor >> 8) as u8);
+            self.write(SerialRegister::LINE_CONTROL, LineControl::WORD_LENGTH_8);
+            self.write(
+                SerialRegister::FIFO_CONTROL,
+                FifoControl::ENABLE
+                    | FifoControl::CLEAR_RECEIVE
+                    | FifoControl::CLEAR_TRANSMIT
+                    | FifoControl::TRIGGER_14,
+            );
+            self.write(
+                SerialRegister::MODEM_CONTROL,
+                ModemControl::DTR | ModemControl::RTS,
+            );
+        }
+    }
+
+    /// Waits until the transmitter can take another byte and sends it.
+    ///
+    /// A missing UART reads as all ones, which looks like an empty transmitter.
+    pub fn send(&self, byte: u8) {
+        unsafe {
+            while self.read(SerialRegister::LINE_STATUS) & LineStatus::THRE == 0 {}
+            self.write(SerialRegister::DATA, byte);
+        }
+    }
+
+    unsafe fn read(&self, register: u16) -> u8 {
+        inb(self.port + register)
+    }
+
+    unsafe fn write(&self, register: u16, value: u8) {
+        outb(self.port + register, value);
+    }
+}
+
+//==================================================================================================
+// Trait Implementations
+//==================================================================================================
+
+impl fmt::Write for Serial {
+    /// Sends `s`, with line feeds turned into carriage return and line feed.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    hint, ptr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::{
+    memory::{KernelMemory, PageTableEntryFlags},
+    process::KERNEL_STACK_SIZE,
+    time::now,
+    x86::{LocalApic, LocalApicIcr, PerCpu},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Physical address the startup code of the other CPUs is copied to.
+const AP_TRAMPOLINE: usize = 0x8000;
+
+/// Time to wait for a CPU to show up, in nanoseconds.
+const AP_STARTUP_TIMEOUT: u64 = 100_000_000;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+extern "C" {
+    static __entry_other: u8;
+    static __entry_other_end: u8;
+}
+
+/// Set once all CPUs are asked to stop.
+static CPUS_STOPPED: AtomicBool = AtomicBool::new(false);
+
+/// Top of the stack the CPU currently being started runs on.
+#[no_mangle]
+static AP_STACK: AtomicUsize = AtomicUsize::new(0);
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Starts the CPU with the given local APIC id, which enters `main_other`,
+/// and returns whether it came up in time.
+///
+/// CPUs have to be started one after the other, as they share the startup
+/// code.
+pub fn start_cpu(apic_id: u32) -> bool {
+    let Some(stack) = KernelMemory::lock().allocate(KERNEL_STACK_SIZE, PageTableEntryFlags::RW)
+    else {
+        return false;
+    };
+    AP_STACK.store(stack + KERNEL_STACK_SIZE, Ordering::SeqCst);
+    copy_trampoline();
+
+    // INIT, followed by up to two STARTUPs as recommended by Intel
+    let count = PerCpu::count();
+    let started = || PerCpu::count() > count;
+    LocalApic::send_ipi(apic_id, LocalApicIcr::INIT | LocalApicIcr::ASSERT);
+    delay(10_000_000);
+    for _ in 0..2 {
+        LocalApic::send_ipi(
+            apic_id,
+            LocalApicIcr::STARTUP | (AP_TRAMPOLINE >> 12) as u32,
+        );
+        delay(200_000);
+        if started() {
+            break;
+        }
+    }
+
+    let deadline = now() + AP_STARTUP_TIMEOUT;
+    while !started() {
+        if now() >= deadline {
+            // the stack is leaked, as the CPU might still come up later
+            return false;
+        }
+        hint::spin_loop();
+    }
+    true
+}
+
+/// Stops all other CPUs through an NMI, which reaches them even with interrupts
+/// disabled.
+///
+/// Returns `false` if the CPUs are being stopped already, which only happens
+/// once.
+pub fn stop_other_cpus() -> bool {
+    if CPUS_STOPPED.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+    // the local APIC is only set up by the time other CPUs are started
+    if PerCpu::count() > 1 {
+        LocalApic::send_ipi(
+            0,
+            LocalApicIcr::NMI | LocalApicIcr::ASSERT | LocalApicIcr::ALL_EXCLUDING_SELF,
+        );
+    }
+    true
+}
+
+/// Whether the CPUs were asked to stop by [`stop_other_cpus`].
+pub fn cpus_stopped() -> bool {
+    CPUS_STOPPED.load(Ordering::SeqCst)
+}
+
+/// Copies the startup code below 1 MiB, as required by real-mode.
+fn copy_trampoline() {
+    let (start, end) = unsafe { (&__entry_other as *const u8, &__entry_other_end as *const u8) };
+    let size = end as usize - start as usize;
+
+    let mut kernel_memory = KernelMemory::lock();
+    let addr = kernel_memory
+        .map_physical(AP_TRAMPOLINE, size, PageTableEntryFlags::RW)
+        .unwrap();
+    unsafe { ptr::copy_nonoverlapping(start, addr as *mut u8, size) };
+    kernel_memory.unmap_physical(addr, size);
+}
+
+fn delay(ns: u64) {
+    let deadline = now() + ns;
+    while now() < deadline {
+        hint::spin_loop();
+    }
+}
@@ -0,0 +1,90 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use abi::Syscall;
+#[cfg(target_arch = "x86")]
+use abi::SYSCALL_VECTOR;
+
+#[cfg(target_arch = "x86")]
+use crate::x86::register_interrupt_handler;
+#[cfg(target_arch = "x86_64")]
+use crate::x86::{rdmsr, wrmsr, Efer, Msr, Rflags, SegmentSelector};
+use crate::{
+    process, syscall,
+    x86::{self, InterruptFrame},
+};
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+#[cfg(target_arch = "x86_64")]
+extern "C" {
+    fn syscall_entry();
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Enables system calls from user mode on the current CPU, through SYSCALL on
+/// x86_64 and through `SYSCALL_VECTOR` on i386.
+pub fn enable_syscalls() {
+    #[cfg(target_arch = "x86")]
+    register_interrupt_handler(SYSCALL_VECTOR, handle_syscall);
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        // SYSRET loads the user selectors relative to KDATA
+        wrmsr(
+            Msr::STAR,
+            (SegmentSelector::KDATA as u64) << 48 | (SegmentSelector::KCODE as u64) << 32,
+        );
+        wrmsr(
+            Msr::LSTAR,
+            syscall_entry as unsafe extern "C" fn() as usize as u64,
+        );
+        wrmsr(
+            Msr::FMASK,
+            (Rflags::TF | Rflags::IF | Rflags::DF | Rflags::AC) as u64,
+        );
+        wrmsr(Msr::EFER, rdmsr(Msr::EFER) | Efer::SCE);
+    }
+}
+
+/// Runs a system call with interrupts enabled and stores its result and
+/// arguments in the frame.
+fn handle_syscall(frame: &mut InterruptFrame) {
+    x86::enable_interrupts();
+    let (number, mut args) = frame.syscall_args();
+    // replaces the whole frame, which the system call table has no access to
+    if number == Syscall::SIGNAL_RETURN {
+        process::return_from_signal(frame);
+    } else {
+        frame.set_syscall_result(syscall::dispatch(number, &mut args));
+        frame.set_syscall_args(args);
+    }
+    x86::disable_interrupts();
+}
+
+/// Called by `syscall_entry` with the frame it built on the kernel stack.
+#[cfg(target_arch = "x86_64")]
+#[no_mangle]
+extern "C" fn syscall_handler(frame: &mut InterruptFrame) {
+    handle_syscall(frame);
+    x86::prepare_user_return(frame);
+}
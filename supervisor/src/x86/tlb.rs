@@ -0,0 +1,147 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{
+    hint, ops,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+};
+
+use crate::{
+    memory::PAGE_SIZE,
+    process::MAX_CPUS,
+    sync::SpinLock,
+    x86::{self, Cr4, LocalApic, LocalApicIcr, PerCpu},
+};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Ranges of more pages are flushed by flushing everything.
+const MAX_FLUSH_PAGES: usize = 32;
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+/// Serializes shootdowns, the range of the one in progress is passed in
+/// `SHOOTDOWN_START` and `SHOOTDOWN_END`.
+static SHOOTDOWN: SpinLock<()> = SpinLock::new(());
+
+static SHOOTDOWN_START: AtomicUsize = AtomicUsize::new(0);
+
+static SHOOTDOWN_END: AtomicUsize = AtomicUsize::new(0);
+
+/// CPUs which take part in shootdowns, by id.
+static SHOOTDOWN_READY: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+
+/// CPUs which have to flush the range of the shootdown in progress, by id.
+static FLUSH_REQUESTED: [AtomicBool; MAX_CPUS] = [const { AtomicBool::new(false) }; MAX_CPUS];
+
+/// Shootdown NMIs sent to each CPU which it didn't receive yet, by id.
+static FLUSH_NMIS: [AtomicU32; MAX_CPUS] = [const { AtomicU32::new(0) }; MAX_CPUS];
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Makes the current CPU take part in TLB shootdowns, which needs its IDT and
+/// the stack of NMIs.
+///
+/// Everything is flushed, as translations changed before might be cached.
+pub fn enable_tlb_shootdown() {
+    SHOOTDOWN_READY[PerCpu::current().id() as usize].store(true, Ordering::SeqCst);
+    flush_all();
+}
+
+/// Invalidates the translations of the page-aligned `range` on all CPUs, and
+/// returns once none of them can use the previous ones any more.
+///
+/// The other CPUs are asked through an NMI, which also reaches them while they
+/// wait for a lock with interrupts disabled.
+pub fn flush_tlb(range: ops::Range<usize>) {
+    let _shootdown = SHOOTDOWN.lock();
+    let current = PerCpu::current().id();
+    SHOOTDOWN_START.store(range.start, Ordering::Relaxed);
+    SHOOTDOWN_END.store(range.end, Ordering::Relaxed);
+
+    let mut asked = [false; MAX_CPUS];
+    for id in 0..PerCpu::count() {
+        if id == current || !SHOOTDOWN_READY[id as usize].load(Ordering::SeqCst) {
+            continue;
+        }
+        let Some(cpu) = PerCpu::get(id) else {
+            continue;
+        };
+        FLUSH_REQUESTED[id as usize].store(true, Ordering::SeqCst);
+        FLUSH_NMIS[id as usize].fetch_add(1, Ordering::SeqCst);
+        LocalApic::send_ipi(cpu.apic_id(), LocalApicIcr::NMI | LocalApicIcr::ASSERT);
+        asked[id as usize] = true;
+    }
+
+    flush_local(range);
+    for (id, _) in asked.iter().enumerate().filter(|(_, &asked)| asked) {
+        // CPUs stopped by a panic never answer
+        while FLUSH_REQUESTED[id].load(Ordering::SeqCst) && !x86::cpus_stopped() {
+            hint::spin_loop();
+        }
+    }
+}
+
+/// Flushes the range requested by [`flush_tlb`] on another CPU, and returns
+/// whether the NMI being handled was sent by it.
+///
+/// An NMI may find the request handled already, by one which came in before.
+pub fn handle_tlb_shootdown() -> bool {
+    let id = PerCpu::current().id() as usize;
+    let sent = FLUSH_NMIS[id]
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+            count.checked_sub(1)
+        })
+        .is_ok();
+    if FLUSH_REQUESTED[id].load(Ordering::SeqCst) {
+        flush_local(SHOOTDOWN_START.load(Ordering::Relaxed)..SHOOTDOWN_END.load(Ordering::Relaxed));
+        FLUSH_REQUESTED[id].store(false, Ordering::SeqCst);
+        return true;
+    }
+    sent
+}
+
+/// Invalidates the translations of `range` on the current CPU.
+fn flush_local(range: ops::Range<usize>) {
+    if range.len() > MAX_FLUSH_PAGES * PAGE_SIZE {
+        flush_all();
+        return;
+    }
+    for page in range.step_by(PAGE_SIZE) {
+        x86::invlpg(page);
+    }
+}
+
+/// Invalidates all translations on the current CPU, including global ones.
+fn flush_all() {
+    let cr4 = x86::read_cr4();
+    unsafe {
+        if cr4 & Cr4::PGE != 0 {
+            x86::write_cr4(cr4 & !Cr4::PGE);
+            x86::write_cr4(cr4);
+        } else {
+            x86::write_cr3(x86::read_cr3());
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{mem, ptr};
+
+use crate::memory::{KernelMemory, PageTableEntryFlags, PAGE_SIZE};
+
+//==================================================================================================
+// Constants
+//==================================================================================================
+
+/// Size of the thread control block the thread pointer points to, which only
+/// holds the thread pointer itself.
+pub const TCB_SIZE: usize = mem::size_of::<usize>();
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+extern "C" {
+    static __tdata_start: u8;
+    static __tdata_end: u8;
+    static __tbss_end: u8;
+    /// Alignment of the TLS template, defined as an absolute symbol.
+    static __tls_align: u8;
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Offset of the thread pointer from the start of a TLS block, whose `size`
+/// bytes of variables aligned to `align` end right below it.
+pub fn tls_offset(size: usize, align: usize) -> usize {
+    size.next_multiple_of(align.max(mem::align_of::<usize>()))
+}
+
+/// Creates a kernel TLS block initialized from the `.tdata` and `.tbss`
+/// template, and returns its thread pointer.
+///
+/// Returns `None` if there is no memory left.
+pub fn create_kernel_tls() -> Option<usize> {
+    let (start, data_end, end, align) = unsafe {
+        (
+            &__tdata_start as *const u8 as usize,
+            &__tdata_end as *const u8 as usize,
+            &__tbss_end as *const u8 as usize,
+            &__tls_align as *const u8 as usize,
+        )
+    };
+    // the block is page-aligned, and so is the thread pointer
+    assert!(
+        align <= PAGE_SIZE,
+        "Kernel TLS alignment exceeds the page size"
+    );
+    let offset = tls_offset(end - start, align);
+    let block = KernelMemory::lock().allocate(offset + TCB_SIZE, PageTableEntryFlags::RW)?;
+    let thread_pointer = block + offset;
+    unsafe {
+        ptr::copy_nonoverlapping(start as *const u8, block as *mut u8, data_end - start);
+        ptr::write_bytes((block + data_end - start) as *mut u8, 0, end - data_end);
+        (thread_pointer as *mut usize).write(thread_pointer);
+    }
+    Some(thread_pointer)
+}
@@ -0,0 +1,128 @@
+// Copyright 2024 Kevin Ludwig
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//==================================================================================================
+// Imports
+//==================================================================================================
+
+use core::{arch::asm, mem, ptr, slice};
+
+use crate::{
+    process::{self, Process, Scheduler},
+    x86::{self, CpuFeature, CpuInfo, InterruptFrame},
+};
+
+//==================================================================================================
+// Structures
+//==================================================================================================
+
+/// Entry of the exception fixup table, emitted into the `.fixup` section next
+/// to the instructions which may fault.
+#[repr(C)]
+struct FixupEntry {
+    /// Address of the instruction which may fault.
+    addr: usize,
+    /// Address execution continues at after a fault.
+    fixup: usize,
+}
+
+//==================================================================================================
+// Variables
+//==================================================================================================
+
+extern "C" {
+    static __fixup_start: FixupEntry;
+    static __fixup_end: FixupEntry;
+
+    fn user_copy(dst: *mut u8, src: *const u8, size: usize) -> usize;
+}
+
+//==================================================================================================
+// Functions
+//==================================================================================================
+
+/// Leaves the kernel and continues the current thread in user mode at `ip`
+/// with the stack pointer `sp`.
+///
+/// Interrupts and system calls from user mode enter the kernel again at the
+/// top of the kernel stack of the thread, discarding everything on it.
+pub fn enter_user(ip: usize, sp: usize) -> ! {
+    let mut frame = InterruptFrame::user(ip, sp);
+    prepare_user_return(&mut frame);
+    // the return restores the interrupt flag, nothing may interrupt it while
+    // the segments are already switched to user mode
+    x86::disable_interrupts();
+    unsafe {
+        #[cfg(target_arch = "x86")]
+        asm!("mov esp, {}", "jmp interrupt_return", in(reg) &frame, options(noreturn));
+        #[cfg(target_arch = "x86_64")]
+        asm!("mov rsp, {}", "jmp interrupt_return", in(reg) &frame, options(noreturn));
+    }
+}
+
+/// Copies `size` bytes between kernel and user memory, and returns `false` if
+/// a fault interrupted the copy.
+///
+/// The access to user memory granted through the AC flag doesn't leak into
+/// interrupts or other threads, as interrupt entry clears the flag and only
+/// the return restores it.
+///
+/// # Safety
+///
+/// The kernel side of the copy must be valid, the user side is only checked by
+/// the hardware.
+pub unsafe fn copy_user(dst: *mut u8, src: *const u8, size: usize) -> bool {
+    let smap = CpuInfo::get().has(CpuFeature::SMAP);
+    if smap {
+        asm!("stac", options(nomem, nostack));
+    }
+    let remaining = user_copy(dst, src, size);
+    if smap {
+        asm!("clac", options(nomem, nostack));
+    }
+    remaining == 0
+}
+
+/// Continues at the fixup address if the exception was raised by one of the
+/// instructions in the fixup table, and returns whether it was.
+pub fn fixup_exception(frame: &mut InterruptFrame) -> bool {
+    let table = unsafe {
+        let start = ptr::addr_of!(__fixup_start);
+        let end = ptr::addr_of!(__fixup_end);
+        slice::from_raw_parts(
+            start,
+            (end as usize - start as usize) / mem::size_of::<FixupEntry>(),
+        )
+    };
+    match table.iter().find(|entry| entry.addr == frame.ip()) {
+        Some(entry) => {
+            frame.set_ip(entry.fixup);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Delivers pending signals through `frame`, and terminates the current thread
+/// instead of letting it return to user mode if its process is exiting, called
+/// on every return to user mode.
+pub fn prepare_user_return(frame: &mut InterruptFrame) {
+    process::deliver_signals(frame);
+    if Scheduler::current()
+        .process()
+        .is_some_and(Process::is_exiting)
+    {
+        Scheduler::exit();
+    }
+}